@@ -0,0 +1,220 @@
+use crate::integrator::PathSample;
+
+/// Arbitrary output variable, a render pass next to the beauty image.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Aov {
+    /// Distance along the camera ray to the first hit, infinite on misses.
+    Depth,
+    /// World space normal of the first hit.
+    Normal,
+    Albedo,
+    /// One plus the index of the first object hit, zero on misses.
+    ObjectId,
+    /// One plus the index of the material of the first hit, the same for every object made
+    /// of that material, zero on misses.
+    MaterialId,
+    /// Light seen straight away, from emitters and the background.
+    Emission,
+    /// Light that bounced once.
+    Direct,
+    /// Light that bounced more than once.
+    Indirect,
+    /// Light from the analytic light with this index in the world.
+    Light(usize),
+}
+
+impl Aov {
+    /// Pass with the given name, the same one `name` gives.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let aov = match name.trim() {
+            "depth" => Aov::Depth,
+            "normal" => Aov::Normal,
+            "albedo" => Aov::Albedo,
+            "object_id" => Aov::ObjectId,
+            "material_id" => Aov::MaterialId,
+            "emission" => Aov::Emission,
+            "direct" => Aov::Direct,
+            "indirect" => Aov::Indirect,
+            name if name.starts_with("light") => Aov::Light(
+                name["light".len()..]
+                    .parse()
+                    .map_err(|_| format!("bad light pass '{}'", name))?,
+            ),
+            name => return Err(format!("unknown render pass '{}'", name)),
+        };
+        Ok(aov)
+    }
+
+    /// Passes from a comma separated list of names, an empty string has none.
+    pub fn parse_list(names: &str) -> Result<Vec<Self>, String> {
+        names
+            .split(',')
+            .filter(|name| !name.trim().is_empty())
+            .map(Aov::parse)
+            .collect()
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Aov::Depth => "depth".to_string(),
+            Aov::Normal => "normal".to_string(),
+            Aov::Albedo => "albedo".to_string(),
+            Aov::ObjectId => "object_id".to_string(),
+            Aov::MaterialId => "material_id".to_string(),
+            Aov::Emission => "emission".to_string(),
+            Aov::Direct => "direct".to_string(),
+            Aov::Indirect => "indirect".to_string(),
+            Aov::Light(index) => format!("light{}", index),
+        }
+    }
+
+    /// Channel names, as in the layers of an OpenEXR file.
+    pub const fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal => &["X", "Y", "Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            _ => &["R", "G", "B"],
+        }
+    }
+
+    /// Whether the samples of a pixel are averaged. Depth and IDs keep the first sample
+    /// instead, a blend of two of them means nothing.
+    pub const fn is_averaged(&self) -> bool {
+        !matches!(self, Aov::Depth | Aov::ObjectId | Aov::MaterialId)
+    }
+}
+
+/// One pass over the whole frame, channels interleaved per pixel.
+pub struct AovBuffer {
+    pub aov: Aov,
+    pub data: Vec<f32>,
+}
+
+impl AovBuffer {
+    pub fn new(aov: Aov, pixels: usize) -> Self {
+        AovBuffer {
+            aov,
+            data: vec![0.0; pixels * aov.channels().len()],
+        }
+    }
+
+    /// Adds `sample` as sample number `index` of `pixel`, the averaged passes are summed
+    /// until `finish`.
+    pub fn add(&mut self, pixel: usize, sample: &PathSample, index: usize) {
+        let values = match self.aov {
+            Aov::Depth => [sample.depth, 0.0, 0.0],
+            Aov::Normal => sample.normal.into(),
+            Aov::Albedo => sample.albedo.into(),
+            Aov::ObjectId => [
+                sample.object.map_or(0.0, |index| index as f32 + 1.0),
+                0.0,
+                0.0,
+            ],
+            Aov::MaterialId => [sample.material.map_or(0.0, |id| id as f32), 0.0, 0.0],
+            Aov::Emission => sample.emission.into(),
+            Aov::Direct => sample.direct.into(),
+            Aov::Indirect => sample.indirect.into(),
+            Aov::Light(light) => sample
+                .lights
+                .get(light)
+                .map_or([0.0, 0.0, 0.0], |&radiance| radiance.into()),
+        };
+
+        let channels = self.aov.channels().len();
+        let pixel = &mut self.data[pixel * channels..(pixel + 1) * channels];
+        if self.aov.is_averaged() {
+            for (channel, value) in pixel.iter_mut().zip(values.iter()) {
+                *channel += value;
+            }
+        } else if index == 0 {
+            pixel.copy_from_slice(&values[..channels]);
+        }
+    }
+
+    /// Turns the sums of the averaged passes into means, given the samples of every pixel.
+    pub fn finish(&mut self, sample_counts: &[u32]) {
+        if !self.aov.is_averaged() {
+            return;
+        }
+        let channels = self.aov.channels().len();
+        for (pixel, &count) in self.data.chunks_exact_mut(channels).zip(sample_counts) {
+            for channel in pixel.iter_mut() {
+                *channel /= count.max(1) as f32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec3;
+
+    #[test]
+    fn names_round_trip() {
+        let aovs = [
+            Aov::Depth,
+            Aov::Normal,
+            Aov::Albedo,
+            Aov::ObjectId,
+            Aov::MaterialId,
+            Aov::Emission,
+            Aov::Direct,
+            Aov::Indirect,
+            Aov::Light(0),
+            Aov::Light(12),
+        ];
+        for &aov in &aovs {
+            assert!(Aov::parse(&aov.name()) == Ok(aov));
+        }
+    }
+
+    #[test]
+    fn lists_of_passes() {
+        assert!(Aov::parse_list("") == Ok(Vec::new()));
+        assert!(Aov::parse_list(" , ") == Ok(Vec::new()));
+        assert!(
+            Aov::parse_list("depth, normal,light3")
+                == Ok(vec![Aov::Depth, Aov::Normal, Aov::Light(3)])
+        );
+        assert!(Aov::parse_list("depth,shadow").is_err());
+        assert!(Aov::parse_list("light").is_err());
+        assert!(Aov::parse_list("lightx").is_err());
+    }
+
+    #[test]
+    fn ids_keep_the_first_sample() {
+        let mut sample = PathSample {
+            color: vec3(0.0, 0.0, 0.0),
+            alpha: 1.0,
+            emission: vec3(1.0, 1.0, 1.0),
+            direct: vec3(0.0, 0.0, 0.0),
+            indirect: vec3(0.0, 0.0, 0.0),
+            lights: Vec::new(),
+            depth: 2.0,
+            normal: vec3(0.0, 0.0, 0.0),
+            albedo: vec3(0.0, 0.0, 0.0),
+            object: Some(4),
+            material: Some(5),
+        };
+        let mut ids = AovBuffer::new(Aov::ObjectId, 1);
+        let mut materials = AovBuffer::new(Aov::MaterialId, 1);
+        let mut emission = AovBuffer::new(Aov::Emission, 1);
+        for index in 0..2 {
+            ids.add(0, &sample, index);
+            materials.add(0, &sample, index);
+            emission.add(0, &sample, index);
+            sample.object = None;
+            sample.material = None;
+            sample.emission *= 3.0;
+        }
+        ids.finish(&[2]);
+        materials.finish(&[2]);
+        emission.finish(&[2]);
+
+        assert_eq!(ids.data, vec![5.0]);
+        assert_eq!(materials.data, vec![5.0]);
+        assert_eq!(emission.data, vec![2.0, 2.0, 2.0]);
+    }
+}
@@ -0,0 +1,68 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::environment::EnvironmentMap;
+use crate::hdr_image::HdrImage;
+use crate::lights::IesProfile;
+use crate::media::VoxelGrid;
+use crate::scene_file::SceneDescription;
+
+/// Files loaded from the page, kept for the scenes of the following renders. Wasm runs on a
+/// single thread, so they live in a thread local.
+#[derive(Default)]
+struct Assets {
+    voxel_grid: Option<Rc<VoxelGrid>>,
+    ies_profile: Option<Rc<IesProfile>>,
+    environment_map: Option<Rc<EnvironmentMap>>,
+    backplate: Option<Rc<HdrImage>>,
+    scene_description: Option<Rc<SceneDescription>>,
+}
+
+thread_local! {
+    static ASSETS: RefCell<Assets> = RefCell::new(Assets::default());
+}
+
+pub fn set_voxel_grid(grid: VoxelGrid) {
+    ASSETS.with(|assets| assets.borrow_mut().voxel_grid = Some(Rc::new(grid)));
+}
+
+/// The last voxel grid loaded, if any.
+pub fn voxel_grid() -> Option<Rc<VoxelGrid>> {
+    ASSETS.with(|assets| assets.borrow().voxel_grid.clone())
+}
+
+pub fn set_ies_profile(profile: IesProfile) {
+    ASSETS.with(|assets| assets.borrow_mut().ies_profile = Some(Rc::new(profile)));
+}
+
+/// The last IES profile loaded, if any.
+pub fn ies_profile() -> Option<Rc<IesProfile>> {
+    ASSETS.with(|assets| assets.borrow().ies_profile.clone())
+}
+
+pub fn set_environment_map(map: EnvironmentMap) {
+    ASSETS.with(|assets| assets.borrow_mut().environment_map = Some(Rc::new(map)));
+}
+
+/// The last environment map loaded, if any.
+pub fn environment_map() -> Option<Rc<EnvironmentMap>> {
+    ASSETS.with(|assets| assets.borrow().environment_map.clone())
+}
+
+pub fn set_backplate(image: HdrImage) {
+    ASSETS.with(|assets| assets.borrow_mut().backplate = Some(Rc::new(image)));
+}
+
+/// The last backplate image loaded, if any.
+pub fn backplate() -> Option<Rc<HdrImage>> {
+    ASSETS.with(|assets| assets.borrow().backplate.clone())
+}
+
+pub fn set_scene_description(description: SceneDescription) {
+    ASSETS.with(|assets| assets.borrow_mut().scene_description = Some(Rc::new(description)));
+}
+
+/// The last scene file loaded, if any.
+pub fn scene_description() -> Option<Rc<SceneDescription>> {
+    ASSETS.with(|assets| assets.borrow().scene_description.clone())
+}
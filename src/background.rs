@@ -0,0 +1,236 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+
+use crate::environment::EnvironmentMap;
+use crate::hdr_image::HdrImage;
+use crate::lights::LightSample;
+use crate::sky::PhysicalSky;
+
+pub const BACKGROUND_COLOR: Vector3<f32> = vec3(0.01, 0.01, 0.01);
+
+/// Colour at `position` along a gradient, where it is the height of a direction from 0 at
+/// the nadir to 1 at the zenith.
+#[derive(Clone, Copy)]
+pub struct GradientStop {
+    pub position: f32,
+    pub color: Vector3<f32>,
+}
+
+/// What rays that leave the scene see.
+pub enum Background {
+    /// The same colour in every direction.
+    Solid(Vector3<f32>),
+    /// Vertical gradient through stops sorted by position, constant past the outer ones.
+    Gradient(Vec<GradientStop>),
+    /// HDR environment map, also sampled as a light.
+    Environment(EnvironmentMap),
+    /// Analytic daylight sky, its sun is a separate light.
+    Sky(PhysicalSky),
+    /// Image stretched over the frame behind the scene. Only camera rays see it, everything
+    /// else is lit by `lighting`.
+    Backplate {
+        image: HdrImage,
+        lighting: Box<Background>,
+    },
+    /// Camera rays that miss everything come out with zero alpha so the image can be
+    /// composited over something else, everything else is lit by `lighting`.
+    Transparent { lighting: Box<Background> },
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::gradient(vec3(0.1, 0.1, 0.1), BACKGROUND_COLOR)
+    }
+}
+
+impl Background {
+    /// Two-stop gradient from `bottom` at the nadir to `top` at the zenith.
+    pub fn gradient(bottom: Vector3<f32>, top: Vector3<f32>) -> Self {
+        Background::Gradient(vec![
+            GradientStop {
+                position: 0.0,
+                color: bottom,
+            },
+            GradientStop {
+                position: 1.0,
+                color: top,
+            },
+        ])
+    }
+
+    /// Three-stop gradient with `horizon` in the middle.
+    pub fn gradient3(bottom: Vector3<f32>, horizon: Vector3<f32>, top: Vector3<f32>) -> Self {
+        Background::Gradient(vec![
+            GradientStop {
+                position: 0.0,
+                color: bottom,
+            },
+            GradientStop {
+                position: 0.5,
+                color: horizon,
+            },
+            GradientStop {
+                position: 1.0,
+                color: top,
+            },
+        ])
+    }
+
+    fn gradient_color(stops: &[GradientStop], direction: &Vector3<f32>) -> Vector3<f32> {
+        let t = (direction.normalize().y + 1.0) * 0.5;
+        let next = match stops.iter().position(|stop| stop.position > t) {
+            Some(next) => next,
+            None => return stops.last().map_or(vec3(0.0, 0.0, 0.0), |stop| stop.color),
+        };
+        if next == 0 {
+            return stops[0].color;
+        }
+
+        let (from, to) = (stops[next - 1], stops[next]);
+        from.color.lerp(
+            to.color,
+            (t - from.position) / (to.position - from.position),
+        )
+    }
+
+    /// Radiance arriving from `direction`.
+    pub fn radiance(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient(stops) => Background::gradient_color(stops, direction),
+            Background::Environment(map) => map.radiance(direction),
+            Background::Sky(sky) => sky.radiance(direction),
+            Background::Backplate { lighting, .. } | Background::Transparent { lighting } => {
+                lighting.radiance(direction)
+            }
+        }
+    }
+
+    /// What a camera ray along `direction` through `screen`, from the top left of the
+    /// frame in `[0, 1]`, sees, and its alpha.
+    pub fn visible(&self, direction: &Vector3<f32>, screen: (f32, f32)) -> (Vector3<f32>, f32) {
+        match self {
+            Background::Backplate { image, .. } => {
+                let x = ((screen.0.max(0.0) * image.width as f32) as usize).min(image.width - 1);
+                let y = ((screen.1.max(0.0) * image.height as f32) as usize).min(image.height - 1);
+                (image.pixel(x, y), 1.0)
+            }
+            Background::Transparent { .. } => (vec3(0.0, 0.0, 0.0), 0.0),
+            _ => (self.radiance(direction), 1.0),
+        }
+    }
+
+    /// Samples a direction for next event estimation, if this background is importance
+    /// sampled at all, `u` in the unit square choosing which.
+    pub fn sample(&self, u: (f32, f32)) -> Option<LightSample> {
+        match self {
+            Background::Environment(map) => map.sample(u),
+            Background::Backplate { lighting, .. } | Background::Transparent { lighting } => {
+                lighting.sample(u)
+            }
+            _ => None,
+        }
+    }
+
+    /// Solid angle density with which `sample` picks `direction`, zero when it doesn't
+    /// sample.
+    pub fn pdf(&self, direction: &Vector3<f32>) -> f32 {
+        match self {
+            Background::Environment(map) => map.pdf(direction),
+            Background::Backplate { lighting, .. } | Background::Transparent { lighting } => {
+                lighting.pdf(direction)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradient_blends_between_stops() {
+        let (bottom, horizon, top) = (
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 1.0, 1.0),
+            vec3(0.0, 0.0, 2.0),
+        );
+        let background = Background::gradient3(bottom, horizon, top);
+
+        let up = background.radiance(&vec3(0.0, 2.0, 0.0));
+        assert!((up - top).magnitude() < 1e-6);
+        let down = background.radiance(&vec3(0.0, -1.0, 0.0));
+        assert!((down - bottom).magnitude() < 1e-6);
+        let level = background.radiance(&vec3(1.0, 0.0, 0.0));
+        assert!((level - horizon).magnitude() < 1e-6);
+        // Half way up from the horizon, half way between its stop and the top one.
+        let above = background.radiance(&vec3(0.0, 0.5, 0.75_f32.sqrt()));
+        assert!((above - horizon.lerp(top, 0.5)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn gradient_is_constant_past_its_outer_stops() {
+        let color = vec3(0.3, 0.2, 0.1);
+        let background = Background::Gradient(vec![GradientStop {
+            position: 0.5,
+            color,
+        }]);
+        assert_eq!(background.radiance(&vec3(0.0, 1.0, 0.0)), color);
+        assert_eq!(background.radiance(&vec3(0.0, -1.0, 0.0)), color);
+        assert_eq!(
+            Background::Gradient(Vec::new()).radiance(&vec3(0.0, 1.0, 0.0)),
+            vec3(0.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn backplate_is_only_seen_by_the_camera() {
+        let image = HdrImage {
+            width: 2,
+            height: 2,
+            pixels: vec![
+                vec3(1.0, 0.0, 0.0),
+                vec3(0.0, 1.0, 0.0),
+                vec3(0.0, 0.0, 1.0),
+                vec3(1.0, 1.0, 1.0),
+            ],
+        };
+        let lighting = vec3(0.5, 0.5, 0.5);
+        let background = Background::Backplate {
+            image,
+            lighting: Box::new(Background::Solid(lighting)),
+        };
+        let direction = vec3(0.0, 0.0, -1.0);
+
+        assert_eq!(
+            background.visible(&direction, (0.0, 0.0)).0,
+            vec3(1.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            background.visible(&direction, (0.9, 0.1)).0,
+            vec3(0.0, 1.0, 0.0)
+        );
+        assert_eq!(
+            background.visible(&direction, (1.0, 1.0)).0,
+            vec3(1.0, 1.0, 1.0)
+        );
+        assert_eq!(background.radiance(&direction), lighting);
+        assert!(background.sample((0.5, 0.5)).is_none());
+        assert_eq!(background.pdf(&direction), 0.0);
+    }
+
+    #[test]
+    fn transparent_background_keeps_lighting() {
+        let lighting = vec3(0.2, 0.4, 0.6);
+        let background = Background::Transparent {
+            lighting: Box::new(Background::Solid(lighting)),
+        };
+        let direction = vec3(0.0, 1.0, 0.0);
+        assert_eq!(
+            background.visible(&direction, (0.5, 0.5)),
+            (vec3(0.0, 0.0, 0.0), 0.0)
+        );
+        assert_eq!(background.radiance(&direction), lighting);
+    }
+}
@@ -2,16 +2,13 @@ use cgmath::prelude::*;
 use cgmath::{vec3, Point3, Vector3};
 use std::f32;
 
-use crate::random;
 use crate::Ray;
 
-fn random_vec_in_disc() -> Vector3<f32> {
-    let r = random().sqrt();
-    let theta = 2.0 * f32::consts::PI * random();
-    let x = r * theta.cos();
-    let y = r * theta.sin();
-
-    random() * vec3(x, y, 0.0)
+/// Uniformly distributed point on the unit disc for a sample in the unit square.
+fn sample_disc(u: (f32, f32)) -> Vector3<f32> {
+    let r = u.0.sqrt();
+    let theta = 2.0 * f32::consts::PI * u.1;
+    vec3(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
 pub struct Camera {
@@ -22,11 +19,13 @@ pub struct Camera {
     u: Vector3<f32>,
     v: Vector3<f32>,
     lens_radius: f32,
+    view_height: f32,
     time_start: f32,
     time_end: f32,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: &Point3<f32>,
         look_at: &Point3<f32>,
@@ -63,16 +62,24 @@ impl Camera {
             u,
             v,
             lens_radius,
+            view_height: 2.0 * half_height,
             time_start,
             time_end,
         }
     }
 
-    pub fn get_ray(&self, u_coord: f32, v_coord: f32) -> Ray {
-        let rd = random_vec_in_disc() * self.lens_radius;
+    /// Angle subtended by a single pixel for an image `canvas_height` pixels tall.
+    pub fn pixel_spread(&self, canvas_height: u16) -> f32 {
+        self.view_height / f32::from(canvas_height)
+    }
+
+    /// Ray through `u_coord`, `v_coord` on the image, with `lens` picking where on the
+    /// aperture it starts and `time` when in the shutter interval, both in `[0, 1)`.
+    pub fn get_ray(&self, u_coord: f32, v_coord: f32, lens: (f32, f32), time: f32) -> Ray {
+        let rd = sample_disc(lens) * self.lens_radius;
         let offset = (self.u * rd.x) + (self.v * rd.y);
         let (x, y, z) = ((self.horizontal * u_coord) + (self.vertical * - v_coord) - offset).into();
-        let time = self.time_start + random() * (self.time_end - self.time_start);
+        let time = self.time_start + time * (self.time_end - self.time_start);
         Ray::new(
             self.origin + offset,
             self.top_left_corner + (Point3::new(x, y, z) - self.origin),
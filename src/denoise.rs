@@ -0,0 +1,391 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+
+use crate::aov::Aov;
+use crate::render::Framebuffer;
+
+/// B3 spline taps of the à-trous kernel.
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Albedo below which a pixel is filtered as is instead of its illumination.
+const MIN_ALBEDO: f32 = 0.01;
+
+#[inline]
+fn luminance(rgb: &Vector3<f32>) -> f32 {
+    0.2126 * rgb.x + 0.7152 * rgb.y + 0.0722 * rgb.z
+}
+
+/// Edge-avoiding à-trous wavelet filter after Dammertz et al., "Edge-Avoiding À-Trous
+/// Wavelet Transform for fast Global Illumination Filtering" (2010).
+///
+/// The colour is divided by the albedo first so textures stay sharp, then blurred with a
+/// kernel that doubles its spacing every iteration and stops at differences in the
+/// illumination, normal, depth and albedo of neighbouring pixels.
+pub struct Denoiser {
+    pub iterations: usize,
+    /// How different the luminance of the illumination may get, halved every iteration.
+    pub color_sigma: f32,
+    /// Exponent on the cosine between normals, higher keeps creases sharper.
+    pub normal_power: f32,
+    /// Depth difference allowed per pixel of distance, relative to the depth.
+    pub depth_sigma: f32,
+    pub albedo_sigma: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            color_sigma: 0.5,
+            normal_power: 64.0,
+            depth_sigma: 0.02,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+/// Guide values of one pixel.
+struct Guide {
+    normal: Vector3<f32>,
+    depth: f32,
+    albedo: Vector3<f32>,
+}
+
+impl Denoiser {
+    /// Denoises the colour of `framebuffer` in place, guided by its depth, normal and albedo
+    /// passes.
+    pub fn apply(&self, framebuffer: &mut Framebuffer) -> Result<(), String> {
+        let pass = |aov: Aov| {
+            framebuffer
+                .aov(aov)
+                .map(|buffer| &buffer.data)
+                .ok_or_else(|| format!("denoising needs the {} pass", aov.name()))
+        };
+        let (depth, normal, albedo) = (pass(Aov::Depth)?, pass(Aov::Normal)?, pass(Aov::Albedo)?);
+
+        let guides: Vec<Guide> = (0..framebuffer.color.len())
+            .map(|i| {
+                let normal = vec3(normal[3 * i], normal[3 * i + 1], normal[3 * i + 2]);
+                Guide {
+                    normal: if normal.magnitude2() > 0.0 {
+                        normal.normalize()
+                    } else {
+                        normal
+                    },
+                    depth: depth[i],
+                    albedo: vec3(albedo[3 * i], albedo[3 * i + 1], albedo[3 * i + 2]),
+                }
+            })
+            .collect();
+
+        // Filter the illumination, the colour with the albedo divided out.
+        let modulation: Vec<Vector3<f32>> = guides
+            .iter()
+            .map(|guide| {
+                let a = guide.albedo;
+                vec3(
+                    if a.x > MIN_ALBEDO { a.x } else { 1.0 },
+                    if a.y > MIN_ALBEDO { a.y } else { 1.0 },
+                    if a.z > MIN_ALBEDO { a.z } else { 1.0 },
+                )
+            })
+            .collect();
+        let mut illumination: Vec<Vector3<f32>> = framebuffer
+            .color
+            .iter()
+            .zip(&modulation)
+            .map(|(color, m)| color.div_element_wise(*m))
+            .collect();
+
+        for iteration in 0..self.iterations {
+            illumination = self.filter_pass(
+                &illumination,
+                &guides,
+                framebuffer.width,
+                framebuffer.height,
+                1 << iteration,
+                self.color_sigma / (1 << iteration) as f32,
+            );
+        }
+
+        for ((color, illumination), m) in framebuffer
+            .color
+            .iter_mut()
+            .zip(&illumination)
+            .zip(&modulation)
+        {
+            *color = illumination.mul_element_wise(*m);
+        }
+        Ok(())
+    }
+
+    fn filter_pass(
+        &self,
+        input: &[Vector3<f32>],
+        guides: &[Guide],
+        width: usize,
+        height: usize,
+        step: usize,
+        color_sigma: f32,
+    ) -> Vec<Vector3<f32>> {
+        let mut output = Vec::with_capacity(input.len());
+        for y in 0..height {
+            for x in 0..width {
+                let center = y * width + x;
+                let (color, guide) = (input[center], &guides[center]);
+                let mut sum = vec3(0.0, 0.0, 0.0);
+                let mut total = 0.0;
+
+                for (ky, hy) in KERNEL.iter().enumerate() {
+                    let sy = y as isize + (ky as isize - 2) * step as isize;
+                    if sy < 0 || sy >= height as isize {
+                        continue;
+                    }
+                    for (kx, hx) in KERNEL.iter().enumerate() {
+                        let sx = x as isize + (kx as isize - 2) * step as isize;
+                        if sx < 0 || sx >= width as isize {
+                            continue;
+                        }
+                        let neighbour = sy as usize * width + sx as usize;
+                        let weight = hx
+                            * hy
+                            * self.edge_weight(
+                                &color,
+                                &input[neighbour],
+                                guide,
+                                &guides[neighbour],
+                                step,
+                                color_sigma,
+                            );
+                        sum += input[neighbour] * weight;
+                        total += weight;
+                    }
+                }
+
+                output.push(if total > 0.0 { sum / total } else { color });
+            }
+        }
+        output
+    }
+
+    /// How much a neighbour counts, from one for a pixel that looks the same down to zero.
+    fn edge_weight(
+        &self,
+        color: &Vector3<f32>,
+        other_color: &Vector3<f32>,
+        guide: &Guide,
+        other: &Guide,
+        step: usize,
+        color_sigma: f32,
+    ) -> f32 {
+        // Misses only blend with misses.
+        let depth_term = match (guide.depth.is_finite(), other.depth.is_finite()) {
+            (true, true) => {
+                let scale = self.depth_sigma * step as f32 * guide.depth.abs() + 1e-4;
+                (guide.depth - other.depth).abs() / scale
+            }
+            (false, false) => 0.0,
+            _ => return 0.0,
+        };
+
+        let color_term = (luminance(color) - luminance(other_color)).powi(2)
+            / (color_sigma * color_sigma).max(1e-8);
+        let albedo_term =
+            (guide.albedo - other.albedo).magnitude2() / (self.albedo_sigma * self.albedo_sigma);
+        let normal_weight = if guide.normal.magnitude2() > 0.0 {
+            guide
+                .normal
+                .dot(other.normal)
+                .max(0.0)
+                .powf(self.normal_power)
+        } else {
+            1.0
+        };
+
+        (-(color_term + albedo_term + depth_term)).exp() * normal_weight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Point3;
+
+    use crate::aov::AovBuffer;
+    use crate::background::Background;
+    use crate::camera::Camera;
+    use crate::film::Film;
+    use crate::filter::Filter;
+    use crate::geometric_objects::Sphere;
+    use crate::materials::Lambertian;
+    use crate::render::{render, RenderSettings};
+    use crate::sampler::SamplerKind;
+    use crate::textures::{CheckerMode, Texture};
+    use crate::world::World;
+
+    const WIDTH: u16 = 40;
+    const HEIGHT: u16 = 30;
+
+    /// Checkered floor and a ball under an overcast sky, with their guide passes.
+    fn render_scene(samples_per_pixel: u32) -> Framebuffer {
+        let mut world = World::new();
+        world.add_object(Box::new(Sphere::new(
+            Point3::new(0.0, -1000.5, -1.0),
+            1000.0,
+            Lambertian {
+                texture: Texture::Checkerboard {
+                    left: Box::new(Texture::scalar(0.8)),
+                    right: Box::new(Texture::scalar(0.2)),
+                    scale: vec3(2.0, 2.0, 2.0),
+                    offset: vec3(0.0, 0.0, 0.0),
+                    mode: CheckerMode::Solid,
+                },
+            },
+        )));
+        world.add_object(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Lambertian {
+                texture: Texture::scalar(0.5),
+            },
+        )));
+        world.set_background(Background::gradient(
+            vec3(0.2, 0.2, 0.2),
+            vec3(1.0, 1.0, 1.0),
+        ));
+
+        let camera = Camera::new(
+            &Point3::new(0.0, 0.5, 2.0),
+            &Point3::new(0.0, 0.0, -1.0),
+            &vec3(0.0, 1.0, 0.0),
+            45.0,
+            f32::from(WIDTH) / f32::from(HEIGHT),
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        );
+        let settings = RenderSettings {
+            film: Film::new(WIDTH, HEIGHT),
+            samples_per_pixel,
+            sampler: SamplerKind::Independent,
+            filter: Filter::default(),
+            spectral: false,
+            aovs: vec![Aov::Depth, Aov::Normal, Aov::Albedo],
+            denoise: false,
+            adaptive: None,
+        };
+        render(&camera, &world, &settings)
+    }
+
+    fn mean_squared_error(image: &[Vector3<f32>], reference: &[Vector3<f32>]) -> f32 {
+        image
+            .iter()
+            .zip(reference)
+            .map(|(a, b)| (a - b).magnitude2())
+            .sum::<f32>()
+            / image.len() as f32
+    }
+
+    #[test]
+    fn denoising_brings_a_noisy_render_closer_to_the_reference() {
+        let reference = render_scene(256);
+        let mut noisy = render_scene(4);
+        let before = mean_squared_error(&noisy.color, &reference.color);
+
+        Denoiser::default().apply(&mut noisy).unwrap();
+        let after = mean_squared_error(&noisy.color, &reference.color);
+        assert!(after < 0.5 * before, "{} before, {} after", before, after);
+    }
+
+    #[test]
+    fn denoising_needs_the_guide_passes() {
+        let mut framebuffer = render_scene(1);
+        framebuffer.aovs.retain(|buffer| buffer.aov != Aov::Normal);
+        assert!(Denoiser::default().apply(&mut framebuffer).is_err());
+    }
+
+    /// Noisy image of a flat, evenly lit surface, its left and right halves differing in
+    /// the guide passes `guide` fills in, seen with `light` on each side.
+    fn split_image<F>(light: (f32, f32), guide: F) -> Framebuffer
+    where
+        F: Fn(bool) -> (Vector3<f32>, Vector3<f32>),
+    {
+        let (width, height) = (16, 8);
+        let mut normals = AovBuffer::new(Aov::Normal, width * height);
+        let mut albedos = AovBuffer::new(Aov::Albedo, width * height);
+        let mut color = Vec::new();
+        for i in 0..width * height {
+            let right = i % width >= width / 2;
+            let (normal, albedo) = guide(right);
+            normals.data[3 * i..3 * i + 3].copy_from_slice(AsRef::<[f32; 3]>::as_ref(&normal));
+            albedos.data[3 * i..3 * i + 3].copy_from_slice(AsRef::<[f32; 3]>::as_ref(&albedo));
+
+            let light = if right { light.1 } else { light.0 };
+            // Deterministic noise of up to a fifth either way.
+            let noise = 1.0 + 0.2 * ((i * 7919 % 101) as f32 / 50.0 - 1.0);
+            color.push(albedo * light * noise);
+        }
+
+        Framebuffer {
+            width,
+            height,
+            origin: (0, 0),
+            alpha: vec![1.0; color.len()],
+            sample_counts: vec![1; color.len()],
+            color,
+            aovs: vec![
+                AovBuffer {
+                    aov: Aov::Depth,
+                    data: vec![1.0; width * height],
+                },
+                normals,
+                albedos,
+            ],
+        }
+    }
+
+    /// Mean luminance of the columns on either side of the middle of `framebuffer`.
+    fn edge_columns(framebuffer: &Framebuffer) -> (f32, f32) {
+        let (width, height) = (framebuffer.width, framebuffer.height);
+        let column = |x: usize| {
+            (0..height)
+                .map(|y| luminance(&framebuffer.color[y * width + x]))
+                .sum::<f32>()
+                / height as f32
+        };
+        (column(width / 2 - 1), column(width / 2))
+    }
+
+    #[test]
+    fn albedo_edges_stay_sharp() {
+        let albedo = |right: bool| {
+            let albedo = if right { 0.9 } else { 0.1 };
+            (vec3(0.0, 0.0, 1.0), vec3(albedo, albedo, albedo))
+        };
+        let mut framebuffer = split_image((1.0, 1.0), albedo);
+        Denoiser::default().apply(&mut framebuffer).unwrap();
+
+        let (left, right) = edge_columns(&framebuffer);
+        assert!((left - 0.1).abs() < 0.01, "{}", left);
+        assert!((right - 0.9).abs() < 0.05, "{}", right);
+    }
+
+    #[test]
+    fn normal_edges_stay_sharp() {
+        let normal = |right: bool| {
+            let normal = if right {
+                vec3(1.0, 0.0, 0.0)
+            } else {
+                vec3(0.0, 0.0, 1.0)
+            };
+            (normal, vec3(0.5, 0.5, 0.5))
+        };
+        let mut framebuffer = split_image((0.2, 1.8), normal);
+        Denoiser::default().apply(&mut framebuffer).unwrap();
+
+        let (left, right) = edge_columns(&framebuffer);
+        assert!((left - 0.1).abs() < 0.01, "{}", left);
+        assert!((right - 0.9).abs() < 0.05, "{}", right);
+    }
+}
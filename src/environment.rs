@@ -0,0 +1,260 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+use std::f32::consts::PI;
+
+use crate::hdr_image::HdrImage;
+use crate::lights::LightSample;
+
+/// Piecewise constant distribution over `[0, 1)` with one bucket per value of `func`.
+#[derive(Clone)]
+pub(crate) struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    pub(crate) fn new(func: Vec<f32>) -> Self {
+        let n = func.len() as f32;
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for (i, value) in func.iter().enumerate() {
+            cdf.push(cdf[i] + value / n);
+        }
+
+        let integral = cdf[func.len()];
+        if integral > 0.0 {
+            for value in cdf.iter_mut() {
+                *value /= integral;
+            }
+        } else {
+            // Nothing to go by, fall back to sampling uniformly.
+            for (i, value) in cdf.iter_mut().enumerate() {
+                *value = i as f32 / n;
+            }
+        }
+
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    /// Maps `u` to a point in `[0, 1)`, returning it with its density and its bucket.
+    pub(crate) fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let bucket = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(self.func.len() - 1);
+        let width = self.cdf[bucket + 1] - self.cdf[bucket];
+        let offset = if width > 0.0 {
+            (u - self.cdf[bucket]) / width
+        } else {
+            0.0
+        };
+
+        let x = ((bucket as f32 + offset) / self.func.len() as f32).min(1.0 - f32::EPSILON);
+        (x, self.pdf(bucket), bucket)
+    }
+
+    fn pdf(&self, bucket: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.func[bucket] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Equirectangular HDR environment surrounding the scene, used both as the background and
+/// as a light source. Directions are importance sampled by pixel brightness, so small bright
+/// features like the sun are found by next event estimation instead of by chance.
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    image: HdrImage,
+    /// One distribution over the columns of every row.
+    conditional: Vec<Distribution1D>,
+    /// Distribution over the rows.
+    marginal: Distribution1D,
+    /// Rotation around the vertical axis, in radians.
+    pub rotation: f32,
+    /// Multiplier for the radiance of the whole map.
+    pub intensity: f32,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: HdrImage) -> Self {
+        let (width, height) = (image.width, image.height);
+        let conditional: Vec<_> = (0..height)
+            .map(|y| {
+                // Rows near the poles cover less solid angle.
+                let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+                Distribution1D::new(
+                    (0..width)
+                        .map(|x| luminance(&image.pixel(x, y)) * sin_theta)
+                        .collect(),
+                )
+            })
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|row| row.integral).collect());
+
+        EnvironmentMap {
+            image,
+            conditional,
+            marginal,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    /// Reads a Radiance `.hdr` or OpenEXR file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let image = HdrImage::parse(bytes)?;
+        if image.width == 0 || image.height == 0 {
+            return Err("environment map is empty".to_string());
+        }
+        Ok(EnvironmentMap::new(image))
+    }
+
+    /// Image coordinates in `[0, 1)` of the unit `direction`.
+    fn direction_to_uv(&self, direction: &Vector3<f32>) -> (f32, f32) {
+        let phi = direction.x.atan2(-direction.z) - self.rotation;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn uv_to_direction(&self, u: f32, v: f32) -> Vector3<f32> {
+        let phi = 2.0 * PI * (u - 0.5) + self.rotation;
+        let theta = PI * v;
+        vec3(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+
+    fn pixel_at(&self, u: f32, v: f32) -> (usize, usize) {
+        let x = ((u * self.image.width as f32) as usize).min(self.image.width - 1);
+        let y = ((v * self.image.height as f32) as usize).min(self.image.height - 1);
+        (x, y)
+    }
+
+    /// Radiance arriving from `direction`.
+    pub fn radiance(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        let (u, v) = self.direction_to_uv(&direction.normalize());
+        let (x, y) = self.pixel_at(u, v);
+        self.image.pixel(x, y) * self.intensity
+    }
+
+    /// Solid angle density with which `sample` picks `direction`.
+    pub fn pdf(&self, direction: &Vector3<f32>) -> f32 {
+        let (u, v) = self.direction_to_uv(&direction.normalize());
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let (x, y) = self.pixel_at(u, v);
+        self.marginal.pdf(y) * self.conditional[y].pdf(x) / (2.0 * PI * PI * sin_theta)
+    }
+
+    /// Picks a direction proportionally to the brightness of the map, `u` in the unit
+    /// square choosing which.
+    pub fn sample(&self, u: (f32, f32)) -> Option<LightSample> {
+        let (v, pdf_v, y) = self.marginal.sample(u.1);
+        let (u, pdf_u, _) = self.conditional[y].sample(u.0);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 || pdf_u * pdf_v <= 0.0 {
+            return None;
+        }
+
+        let direction = self.uv_to_direction(u, v);
+        Some(LightSample {
+            direction,
+            distance: f32::MAX,
+            radiance: self.radiance(&direction),
+            pdf: pdf_u * pdf_v / (2.0 * PI * PI * sin_theta),
+        })
+    }
+}
+
+#[inline]
+fn luminance(rgb: &Vector3<f32>) -> f32 {
+    rgb.dot(vec3(0.2126, 0.7152, 0.0722)).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distribution_follows_its_function() {
+        let distribution = Distribution1D::new(vec![1.0, 3.0, 0.0, 4.0]);
+        assert!((distribution.integral - 2.0).abs() < 1e-6);
+
+        let n = 10_000;
+        let mut counts = [0; 4];
+        for i in 0..n {
+            let (x, pdf, bucket) = distribution.sample((i as f32 + 0.5) / n as f32);
+            assert_eq!(bucket, (x * 4.0) as usize);
+            assert!((pdf - distribution.func[bucket] / 2.0).abs() < 1e-6);
+            counts[bucket] += 1;
+        }
+        assert_eq!(counts, [1250, 3750, 0, 5000]);
+    }
+
+    #[test]
+    fn distribution_of_nothing_is_uniform() {
+        let distribution = Distribution1D::new(vec![0.0; 4]);
+        for &(u, bucket) in &[(0.1, 0), (0.3, 1), (0.6, 2), (0.99, 3)] {
+            let (x, pdf, sampled) = distribution.sample(u);
+            assert_eq!(sampled, bucket);
+            assert!((x - u).abs() < 1e-6);
+            assert_eq!(pdf, 1.0);
+        }
+    }
+
+    #[test]
+    fn environment_pdf_matches_its_samples() {
+        // A dim map with one bright pixel, which most samples should land on.
+        let (width, height) = (8, 4);
+        let mut pixels = vec![vec3(0.1, 0.1, 0.1); width * height];
+        pixels[width + 5] = vec3(100.0, 100.0, 100.0);
+        let map = EnvironmentMap::new(HdrImage {
+            width,
+            height,
+            pixels,
+        });
+
+        let n = 64;
+        let mut bright = 0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = ((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+                let sample = map.sample(u).unwrap();
+                let pdf = map.pdf(&sample.direction);
+                assert!((sample.pdf - pdf).abs() <= 1e-3 * pdf);
+                if sample.radiance.x > 1.0 {
+                    bright += 1;
+                }
+            }
+        }
+        assert!(bright > n * n * 9 / 10);
+
+        // The density integrates to one over the sphere.
+        let (steps_theta, steps_phi) = (200, 400);
+        let mut total = 0.0;
+        for i in 0..steps_theta {
+            let theta = PI * (i as f32 + 0.5) / steps_theta as f32;
+            for j in 0..steps_phi {
+                let phi = 2.0 * PI * (j as f32 + 0.5) / steps_phi as f32;
+                let direction = vec3(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                total += map.pdf(&direction) * theta.sin();
+            }
+        }
+        total *= PI / steps_theta as f32 * 2.0 * PI / steps_phi as f32;
+        assert!((total - 1.0).abs() < 0.02, "{}", total);
+    }
+}
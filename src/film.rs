@@ -0,0 +1,144 @@
+/// Rectangle of pixels, from the top left.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub const fn pixels(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+/// Sensor of the camera: the size of the full frame and the part of it that gets rendered.
+/// Camera rays and sample positions depend on where a pixel is in the full frame, not in
+/// the crop, so a crop shows the part of the full frame it covers and renders of regions
+/// put side by side give the full frame back. Only the noise differs where lights, media
+/// and the independent sampler draw from `random`.
+#[derive(Clone, Copy)]
+pub struct Film {
+    pub width: u16,
+    pub height: u16,
+    crop: Region,
+}
+
+impl Film {
+    /// Film rendering the whole `width` by `height` frame.
+    pub fn new(width: u16, height: u16) -> Self {
+        Film {
+            width,
+            height,
+            crop: Region {
+                x: 0,
+                y: 0,
+                width: usize::from(width),
+                height: usize::from(height),
+            },
+        }
+    }
+
+    /// Renders only `region` of the frame.
+    pub fn with_crop(mut self, region: Region) -> Result<Self, String> {
+        let right = region.x.checked_add(region.width);
+        let bottom = region.y.checked_add(region.height);
+        if region.width == 0
+            || region.height == 0
+            || right.map_or(true, |right| right > usize::from(self.width))
+            || bottom.map_or(true, |bottom| bottom > usize::from(self.height))
+        {
+            return Err(format!(
+                "crop of {}x{} at {}, {} is not inside the {}x{} frame",
+                region.width, region.height, region.x, region.y, self.width, self.height
+            ));
+        }
+        self.crop = region;
+        Ok(self)
+    }
+
+    /// Renders only the pixels whose centres are inside a window given in fractions of the
+    /// frame, from `x0`, `y0` at the top left to `x1`, `y1`.
+    pub fn with_crop_window(self, x0: f32, y0: f32, x1: f32, y1: f32) -> Result<Self, String> {
+        let to_pixel = |t: f32, size: u16| {
+            ((t.clamp(0.0, 1.0) * f32::from(size) - 0.5).ceil().max(0.0) as usize)
+                .min(usize::from(size))
+        };
+        let (left, right) = (to_pixel(x0, self.width), to_pixel(x1, self.width));
+        let (top, bottom) = (to_pixel(y0, self.height), to_pixel(y1, self.height));
+        self.with_crop(Region {
+            x: left,
+            y: top,
+            width: right.saturating_sub(left),
+            height: bottom.saturating_sub(top),
+        })
+    }
+
+    pub const fn crop(&self) -> Region {
+        self.crop
+    }
+
+    /// Position in the full frame of pixel number `index` of the crop.
+    pub const fn frame_position(&self, index: usize) -> (usize, usize) {
+        (
+            self.crop.x + index % self.crop.width,
+            self.crop.y + index / self.crop.width,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(x: usize, y: usize, width: usize, height: usize) -> Region {
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn frame_position_offsets_by_the_crop() {
+        let film = Film::new(100, 60).with_crop(region(10, 20, 30, 5)).unwrap();
+        assert_eq!(film.frame_position(0), (10, 20));
+        assert_eq!(film.frame_position(29), (39, 20));
+        assert_eq!(film.frame_position(30), (10, 21));
+        assert_eq!(film.frame_position(149), (39, 24));
+    }
+
+    #[test]
+    fn crops_outside_the_frame_are_errors() {
+        let film = Film::new(100, 60);
+        assert!(film.with_crop(region(0, 0, 100, 60)).is_ok());
+        assert!(film.with_crop(region(0, 0, 0, 60)).is_err());
+        assert!(film.with_crop(region(0, 0, 100, 0)).is_err());
+        assert!(film.with_crop(region(1, 0, 100, 60)).is_err());
+        assert!(film.with_crop(region(0, 1, 100, 60)).is_err());
+        assert!(film.with_crop(region(usize::MAX, 0, 2, 1)).is_err());
+        assert!(film.with_crop(region(0, usize::MAX, 1, 2)).is_err());
+        assert!(film.with_crop(region(2, 0, usize::MAX, 1)).is_err());
+    }
+
+    #[test]
+    fn crop_window_keeps_pixels_with_centres_inside() {
+        let crop = Film::new(100, 60)
+            .with_crop_window(0.1, 0.25, 0.5, 1.0)
+            .unwrap()
+            .crop();
+        assert!(crop == region(10, 15, 40, 45));
+
+        let whole = Film::new(100, 60)
+            .with_crop_window(-1.0, -1.0, 2.0, 2.0)
+            .unwrap()
+            .crop();
+        assert!(whole == region(0, 0, 100, 60));
+
+        assert!(Film::new(100, 60)
+            .with_crop_window(0.5, 0.5, 0.5, 0.9)
+            .is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use std::f32::consts::PI;
+
+use crate::environment::Distribution1D;
+
+/// Buckets of the table the filter offsets are sampled from, across its whole width.
+const TABLE_SIZE: usize = 256;
+
+/// Shape of a pixel reconstruction filter.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    Box,
+    /// Linear falloff to zero at the radius.
+    Tent,
+    /// Gaussian with a standard deviation of a third of the radius, shifted down to reach
+    /// zero at the radius.
+    Gaussian,
+    /// Mitchell-Netravali cubic with B = C = 1/3, sharper than the Gaussian with slightly
+    /// negative lobes.
+    Mitchell,
+    /// Sinc windowed by a sinc as wide as the radius, so the radius is the number of lobes.
+    Lanczos,
+}
+
+impl FilterKind {
+    /// Filter with the given name, the same one `name` gives.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let kind = match name.trim() {
+            "box" => FilterKind::Box,
+            "tent" => FilterKind::Tent,
+            "gaussian" => FilterKind::Gaussian,
+            "mitchell" => FilterKind::Mitchell,
+            "lanczos" => FilterKind::Lanczos,
+            name => return Err(format!("unknown filter '{}'", name)),
+        };
+        Ok(kind)
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    /// Radius in pixels the filter is usually used with.
+    pub const fn default_radius(&self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+
+    /// Value of the filter with radius `r` along one axis, at `x` pixels from the centre.
+    fn evaluate_1d(self, r: f32, x: f32) -> f32 {
+        if x.abs() > r {
+            return 0.0;
+        }
+        match self {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => r - x.abs(),
+            FilterKind::Gaussian => {
+                let sigma = r / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(r)).max(0.0)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r, 1.0 / 3.0, 1.0 / 3.0),
+            FilterKind::Lanczos => sinc(x) * sinc(x / r),
+        }
+    }
+}
+
+#[inline]
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Mitchell-Netravali cubic over `[-2, 2]`.
+fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x.powi(3)
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+/// Separable pixel reconstruction filter, applied by filter importance sampling (Ernst et
+/// al. 2006): sample offsets from the pixel centre are drawn proportionally to the
+/// magnitude of the filter and the samples weighted by its value over that density. Pixels
+/// then take the weighted mean of their own samples, which matches splatting every sample
+/// into all pixels it reaches without any pixel reading another's samples.
+pub struct Filter {
+    pub kind: FilterKind,
+    /// Half the width of the filter, in pixels.
+    pub radius: f32,
+    /// Magnitude of the filter along one axis, over `[-radius, radius]`.
+    distribution: Distribution1D,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind, radius: f32) -> Self {
+        let radius = radius.max(1e-3);
+        let table = (0..TABLE_SIZE)
+            .map(|i| {
+                let x = radius * (2.0 * (i as f32 + 0.5) / TABLE_SIZE as f32 - 1.0);
+                kind.evaluate_1d(radius, x).abs()
+            })
+            .collect();
+        Filter {
+            kind,
+            radius,
+            distribution: Distribution1D::new(table),
+        }
+    }
+
+    pub fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.kind.evaluate_1d(self.radius, x) * self.kind.evaluate_1d(self.radius, y)
+    }
+
+    /// Offset from the pixel centre for `u` in the unit square, with the weight of the
+    /// sample taken there. The weight is negative in the negative lobes.
+    pub fn sample(&self, u: (f32, f32)) -> ((f32, f32), f32) {
+        let width = 2.0 * self.radius;
+        let (tx, pdf_x, _) = self.distribution.sample(u.0);
+        let (ty, pdf_y, _) = self.distribution.sample(u.1);
+        let (x, y) = (
+            self.radius * (2.0 * tx - 1.0),
+            self.radius * (2.0 * ty - 1.0),
+        );
+
+        let pdf = pdf_x * pdf_y / (width * width);
+        let weight = if pdf > 0.0 {
+            self.evaluate(x, y) / pdf
+        } else {
+            0.0
+        };
+        ((x, y), weight)
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::new(FilterKind::Box, FilterKind::Box.default_radius())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ];
+
+    /// Integral of the filter along one axis, by the midpoint rule.
+    fn integral_1d(filter: &Filter) -> f32 {
+        let steps = 10_000;
+        let dx = 2.0 * filter.radius / steps as f32;
+        (0..steps)
+            .map(|i| {
+                let x = -filter.radius + (i as f32 + 0.5) * dx;
+                filter.kind.evaluate_1d(filter.radius, x) * dx
+            })
+            .sum()
+    }
+
+    /// Samples of `filter` for a stratified grid of `n` by `n` points in the unit square.
+    fn samples(filter: &Filter, n: usize) -> Vec<((f32, f32), f32)> {
+        (0..n * n)
+            .map(|i| {
+                let u = (
+                    ((i % n) as f32 + 0.5) / n as f32,
+                    ((i / n) as f32 + 0.5) / n as f32,
+                );
+                filter.sample(u)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for &kind in &KINDS {
+            assert!(FilterKind::parse(kind.name()) == Ok(kind));
+        }
+        assert!(FilterKind::parse("bicubic").is_err());
+    }
+
+    #[test]
+    fn sample_weights_average_to_the_filter_integral() {
+        for &kind in &KINDS {
+            let filter = Filter::new(kind, kind.default_radius());
+            let samples = samples(&filter, 512);
+            let mean =
+                samples.iter().map(|&(_, weight)| weight).sum::<f32>() / samples.len() as f32;
+            let expected = integral_1d(&filter).powi(2);
+            assert!(
+                (mean - expected).abs() < 0.01 * expected,
+                "{}: {} against {}",
+                kind.name(),
+                mean,
+                expected
+            );
+            for &((x, y), _) in &samples {
+                assert!(x.abs() <= filter.radius && y.abs() <= filter.radius);
+            }
+        }
+    }
+
+    #[test]
+    fn offsets_follow_the_filter() {
+        // Three quarters of the area under a tent lie within half its radius.
+        let filter = Filter::new(FilterKind::Tent, 1.0);
+        let samples = samples(&filter, 100);
+        let inner = samples.iter().filter(|&&((x, _), _)| x.abs() < 0.5).count();
+        let fraction = inner as f32 / samples.len() as f32;
+        assert!((fraction - 0.75).abs() < 0.01, "{}", fraction);
+
+        // Importance sampling a filter that is never negative leaves equal weights.
+        for &(_, weight) in &samples {
+            assert!((weight - 1.0).abs() < 0.05, "{}", weight);
+        }
+    }
+
+    #[test]
+    fn negative_lobes_give_negative_weights() {
+        let filter = Filter::new(FilterKind::Mitchell, 2.0);
+        let samples = samples(&filter, 100);
+        for &((x, y), weight) in &samples {
+            assert_eq!(weight < 0.0, filter.evaluate(x, y) < 0.0);
+        }
+        assert!(samples.iter().any(|&(_, weight)| weight < 0.0));
+    }
+}
@@ -0,0 +1,50 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+
+/// Orthonormal shading frame with `n` as the local z axis.
+#[derive(Clone, Copy)]
+pub struct Frame {
+    pub s: Vector3<f32>,
+    pub t: Vector3<f32>,
+    pub n: Vector3<f32>,
+}
+
+impl Frame {
+    /// Builds an arbitrary but continuous frame around `n` (Duff et al. 2017).
+    pub fn from_normal(n: &Vector3<f32>) -> Self {
+        let sign = 1.0_f32.copysign(n.z);
+        let a = -1.0 / (sign + n.z);
+        let b = n.x * n.y * a;
+
+        Frame {
+            s: vec3(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+            t: vec3(b, sign + n.y * n.y * a, -n.y),
+            n: *n,
+        }
+    }
+
+    /// Builds a frame around `n` whose s axis follows `tangent` as closely as possible.
+    pub fn from_normal_tangent(n: &Vector3<f32>, tangent: &Vector3<f32>) -> Self {
+        let s = tangent - n * n.dot(*tangent);
+        if s.magnitude2() < 1e-8 {
+            return Frame::from_normal(n);
+        }
+        let s = s.normalize();
+
+        Frame {
+            s,
+            t: n.cross(s),
+            n: *n,
+        }
+    }
+
+    #[inline]
+    pub fn to_local(self, v: &Vector3<f32>) -> Vector3<f32> {
+        vec3(v.dot(self.s), v.dot(self.t), v.dot(self.n))
+    }
+
+    #[inline]
+    pub fn to_world(self, v: &Vector3<f32>) -> Vector3<f32> {
+        self.s * v.x + self.t * v.y + self.n * v.z
+    }
+}
@@ -3,33 +3,58 @@ extern crate cgmath;
 use cgmath::prelude::*;
 use cgmath::{Point3, Vector3};
 use std::f32;
+use std::f32::consts::PI;
+use std::rc::Rc;
 
-use crate::materials::Material;
+use crate::materials::{IntoMaterial, Material};
 use crate::shade_record::ShadeRecord;
 use crate::Ray;
 
 pub trait GeometricObject {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord>;
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>>;
+
+    /// Surface area, used to turn emitted power into radiance.
+    fn area(&self) -> f32;
+
+    /// Material the object is made of.
+    fn material(&self) -> &dyn Material;
+
+    /// Fraction of light that gets through the object between `t_min` and `t_max`, used
+    /// for shadow rays. Solid objects block everything they are hit by, media override it.
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        if self.hit(ray, t_min, t_max).is_some() {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Spherical (u, v) coordinates of a point on a unit sphere given its outward normal.
+fn sphere_uv(normal: &Vector3<f32>) -> (f32, f32) {
+    let phi = f32::atan2(-normal.z, normal.x) + PI;
+    let theta = f32::acos((-normal.y).clamp(-1.0, 1.0));
+    (phi / (2.0 * PI), theta / PI)
 }
 
 pub struct Sphere {
     center: Point3<f32>,
     radius: f32,
-    material: Material,
+    material: Rc<dyn Material>,
 }
 
 impl Sphere {
-    pub const fn new(center: Point3<f32>, radius: f32, material: Material) -> Self {
+    pub fn new<M: IntoMaterial>(center: Point3<f32>, radius: f32, material: M) -> Self {
         Sphere {
             center,
             radius,
-            material,
+            material: material.into_material(),
         }
     }
 }
 
 impl GeometricObject for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
         let oc: Vector3<f32> = ray.origin - self.center;
         let a = ray.direction.magnitude2();
         let b = oc.dot(ray.direction);
@@ -48,21 +73,37 @@ impl GeometricObject for Sphere {
                 _ => None,
             };
 
-            option_t.and_then(|intersect_parameter| {
+            option_t.map(|intersect_parameter| {
                 let local_hit_point = ray.point_at_parameter(intersect_parameter);
                 let normal = (local_hit_point - self.center) / self.radius;
+                let (u, v) = sphere_uv(&normal);
+                let footprint = ray.footprint_at(intersect_parameter);
 
-                Some(ShadeRecord {
+                ShadeRecord {
                     intersect_parameter,
                     local_hit_point,
                     normal,
-                    material: &self.material,
-                })
+                    tangent: Vector3::new(normal.z, 0.0, -normal.x),
+                    material: self.material.as_ref(),
+                    object: Some(self),
+                    u,
+                    v,
+                    footprint,
+                    uv_footprint: footprint / (PI * self.radius),
+                }
             })
         } else {
             None
         }
     }
+
+    fn area(&self) -> f32 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn material(&self) -> &dyn Material {
+        self.material.as_ref()
+    }
 }
 
 pub struct MovingSphere {
@@ -71,17 +112,17 @@ pub struct MovingSphere {
     time_start: f32,
     time_end: f32,
     radius: f32,
-    material: Material,
+    material: Rc<dyn Material>,
 }
 
 impl MovingSphere {
-    pub const fn new(
+    pub fn new<M: IntoMaterial>(
         center_start: Point3<f32>,
         center_end: Point3<f32>,
         time_start: f32,
         time_end: f32,
         radius: f32,
-        material: Material,
+        material: M,
     ) -> Self {
         MovingSphere {
             center_start,
@@ -89,7 +130,7 @@ impl MovingSphere {
             time_start,
             time_end,
             radius,
-            material,
+            material: material.into_material(),
         }
     }
 
@@ -101,7 +142,7 @@ impl MovingSphere {
 }
 
 impl GeometricObject for MovingSphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
         let oc: Vector3<f32> = ray.origin - self.center(ray.time);
         let a = ray.direction.magnitude2();
         let b = oc.dot(ray.direction);
@@ -120,21 +161,37 @@ impl GeometricObject for MovingSphere {
                 _ => None,
             };
 
-            option_t.and_then(|intersect_parameter| {
+            option_t.map(|intersect_parameter| {
                 let local_hit_point = ray.point_at_parameter(intersect_parameter);
                 let normal = (local_hit_point - self.center(ray.time)) / self.radius;
+                let (u, v) = sphere_uv(&normal);
+                let footprint = ray.footprint_at(intersect_parameter);
 
-                Some(ShadeRecord {
+                ShadeRecord {
                     intersect_parameter,
                     local_hit_point,
                     normal,
-                    material: &self.material,
-                })
+                    tangent: Vector3::new(normal.z, 0.0, -normal.x),
+                    material: self.material.as_ref(),
+                    object: Some(self),
+                    u,
+                    v,
+                    footprint,
+                    uv_footprint: footprint / (PI * self.radius),
+                }
             })
         } else {
             None
         }
     }
+
+    fn area(&self) -> f32 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn material(&self) -> &dyn Material {
+        self.material.as_ref()
+    }
 }
 
 pub struct Rect {
@@ -143,17 +200,17 @@ pub struct Rect {
     z0: f32,
     z1: f32,
     y_height: f32,
-    material: Material,
+    material: Rc<dyn Material>,
 }
 
 impl Rect {
-pub const fn new(x0: f32, x1: f32, z0: f32, z1: f32, y_height: f32, material: Material) -> Self {
-        Self { x0, x1, z0, z1, y_height, material }
+pub fn new<M: IntoMaterial>(x0: f32, x1: f32, z0: f32, z1: f32, y_height: f32, material: M) -> Self {
+        Self { x0, x1, z0, z1, y_height, material: material.into_material() }
     }
 }
 
 impl GeometricObject for Rect {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
         let intersect_param = match (self.y_height - ray.origin.y) / ray.direction.y {
             t if t < t_min || t > t_max => None,
             t => Some(t)
@@ -162,14 +219,31 @@ impl GeometricObject for Rect {
         intersect_param.and_then(|t| {
             match (ray.origin.x + t * ray.direction.x , ray.origin.z + t * ray.direction.z) {
                 (x, z)  if x < self.x0 || x > self.x1 || z < self.z0 || z > self.z1 => None,
-                _ =>  
+                (x, z) => {
+                    let (width, depth) = (self.x1 - self.x0, self.z1 - self.z0);
+                    let footprint = ray.footprint_at(t);
                     Some(ShadeRecord{
                         intersect_parameter: t,
                         local_hit_point: ray.point_at_parameter(t),
-                        normal: Vector3::new(0.0, 0.0, 1.0),
-                        material: &self.material,
+                        normal: Vector3::new(0.0, 1.0, 0.0),
+                        tangent: Vector3::new(1.0, 0.0, 0.0),
+                        material: self.material.as_ref(),
+                        object: Some(self),
+                        u: (x - self.x0) / width,
+                        v: (z - self.z0) / depth,
+                        footprint,
+                        uv_footprint: footprint / width.min(depth),
                     })
+                }
             }
         })
     }
+
+    fn area(&self) -> f32 {
+        (self.x1 - self.x0) * (self.z1 - self.z0)
+    }
+
+    fn material(&self) -> &dyn Material {
+        self.material.as_ref()
+    }
 }
\ No newline at end of file
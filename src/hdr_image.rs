@@ -0,0 +1,565 @@
+use cgmath::{vec3, Vector3};
+use std::str;
+
+/// Largest image the parsers accept, far more than fits in the memory of a browser tab.
+const MAX_PIXELS: usize = 1 << 26;
+
+/// Number of pixels of a `width` by `height` image, if it is a size the parsers accept.
+fn pixel_count(width: usize, height: usize) -> Result<usize, String> {
+    match width.checked_mul(height) {
+        Some(count) if count > 0 && count <= MAX_PIXELS => Ok(count),
+        _ => Err(format!("image size {}x{} is not supported", width, height)),
+    }
+}
+
+/// Floating point RGB image, stored row by row from the top.
+#[derive(Clone)]
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vector3<f32>>,
+}
+
+impl HdrImage {
+    #[inline]
+    pub fn pixel(&self, x: usize, y: usize) -> Vector3<f32> {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Decodes 8 bit sRGB pixels with alpha, as canvas `ImageData` has them, to linear RGB.
+    pub fn from_srgba8(width: usize, height: usize, rgba: &[u8]) -> Result<Self, String> {
+        if pixel_count(width, height).ok().map(|count| 4 * count) != Some(rgba.len()) {
+            return Err(format!(
+                "{} bytes don't make a {}x{} RGBA image",
+                rgba.len(),
+                width,
+                height
+            ));
+        }
+
+        let decode = |value: u8| {
+            let c = f32::from(value) / 255.0;
+            if c <= 0.040_45 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        let pixels = rgba
+            .chunks_exact(4)
+            .map(|p| vec3(decode(p[0]), decode(p[1]), decode(p[2])))
+            .collect();
+
+        Ok(HdrImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Reads either a Radiance `.hdr` or an OpenEXR file, going by its magic number.
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.starts_with(&EXR_MAGIC) {
+            HdrImage::parse_exr(bytes)
+        } else {
+            HdrImage::parse_hdr(bytes)
+        }
+    }
+
+    /// Reads a Radiance RGBE image, flat or with the usual run length encoded scanlines.
+    pub fn parse_hdr(bytes: &[u8]) -> Result<Self, String> {
+        let mut cursor = 0;
+        let mut next_line = || -> Result<&str, String> {
+            let end = bytes[cursor..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .ok_or_else(|| "unexpected end of .hdr header".to_string())?;
+            let line = str::from_utf8(&bytes[cursor..cursor + end])
+                .map_err(|_| "invalid .hdr header".to_string())?;
+            cursor += end + 1;
+            Ok(line)
+        };
+
+        if !next_line()?.starts_with("#?") {
+            return Err("not a Radiance .hdr file".to_string());
+        }
+        loop {
+            let line = next_line()?;
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+                return Err(format!("unsupported .hdr {}", line));
+            }
+        }
+
+        let resolution = next_line()?;
+        let fields: Vec<&str> = resolution.split_whitespace().collect();
+        let (height, width) = match fields.as_slice() {
+            ["-Y", height, "+X", width] => (
+                height.parse::<usize>().map_err(|_| "bad .hdr height")?,
+                width.parse::<usize>().map_err(|_| "bad .hdr width")?,
+            ),
+            _ => return Err(format!("unsupported .hdr orientation '{}'", resolution)),
+        };
+
+        let mut data = &bytes[cursor..];
+        let mut pixels = Vec::with_capacity(pixel_count(width, height)?);
+        let mut scanline = vec![[0_u8; 4]; width];
+        for _ in 0..height {
+            data = read_hdr_scanline(data, &mut scanline)?;
+            pixels.extend(scanline.iter().map(rgbe_to_rgb));
+        }
+
+        Ok(HdrImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Reads a single part, scanline, uncompressed OpenEXR image with half or float R, G
+    /// and B channels.
+    pub fn parse_exr(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = ByteReader { bytes, offset: 0 };
+        if reader.take(4)? != EXR_MAGIC {
+            return Err("not an OpenEXR file".to_string());
+        }
+        let version = reader.u32()?;
+        if version & 0xff != 2 || version & !0xff != 0 {
+            return Err("only single part scanline OpenEXR files are supported".to_string());
+        }
+
+        let mut channels = Vec::new();
+        let mut data_window = None;
+        loop {
+            let name = reader.string()?;
+            if name.is_empty() {
+                break;
+            }
+            let kind = reader.string()?;
+            let size = reader.u32()? as usize;
+            let mut value = ByteReader {
+                bytes: reader.take(size)?,
+                offset: 0,
+            };
+
+            match (name.as_str(), kind.as_str()) {
+                ("channels", "chlist") => loop {
+                    let channel = value.string()?;
+                    if channel.is_empty() {
+                        break;
+                    }
+                    let pixel_type = value.u32()?;
+                    value.take(12)?;
+                    channels.push((channel, pixel_type));
+                },
+                ("compression", "compression") if value.take(1)?[0] != 0 => {
+                    return Err("only uncompressed OpenEXR files are supported".to_string());
+                }
+                ("dataWindow", "box2i") => {
+                    let (x_min, y_min) = (value.i32()?, value.i32()?);
+                    let (x_max, y_max) = (value.i32()?, value.i32()?);
+                    data_window = Some((x_min, y_min, x_max, y_max));
+                }
+                _ => {}
+            }
+        }
+
+        let (x_min, y_min, x_max, y_max) =
+            data_window.ok_or_else(|| "OpenEXR file has no data window".to_string())?;
+        if x_max < x_min || y_max < y_min {
+            return Err("empty OpenEXR data window".to_string());
+        }
+        let width = (i64::from(x_max) - i64::from(x_min) + 1) as usize;
+        let height = (i64::from(y_max) - i64::from(y_min) + 1) as usize;
+        let pixel_count = pixel_count(width, height)?;
+
+        let channel_index = |name: &str| {
+            channels
+                .iter()
+                .position(|(channel, _)| channel == name)
+                .ok_or_else(|| format!("OpenEXR file has no {} channel", name))
+        };
+        let rgb = [
+            channel_index("R")?,
+            channel_index("G")?,
+            channel_index("B")?,
+        ];
+
+        // Every scanline is its own block, the offset table lists where each one starts.
+        let mut offsets = Vec::with_capacity(height);
+        for _ in 0..height {
+            offsets.push(reader.u64()? as usize);
+        }
+
+        let mut pixels = vec![vec3(0.0, 0.0, 0.0); pixel_count];
+        let mut values = vec![0.0; width];
+        for offset in offsets {
+            let mut block = ByteReader { bytes, offset };
+            let y = (i64::from(block.i32()?) - i64::from(y_min)) as usize;
+            let _size = block.u32()?;
+            if y >= height {
+                return Err("OpenEXR scanline outside the data window".to_string());
+            }
+
+            // Channels are stored one after the other, in the order of the channel list.
+            for (index, (_, pixel_type)) in channels.iter().enumerate() {
+                for value in values.iter_mut() {
+                    *value = match pixel_type {
+                        0 => block.u32()? as f32,
+                        1 => half_to_f32(block.u16()?),
+                        2 => f32::from_bits(block.u32()?),
+                        _ => return Err("unknown OpenEXR pixel type".to_string()),
+                    };
+                }
+                for (axis, &channel) in rgb.iter().enumerate() {
+                    if channel == index {
+                        for (x, value) in values.iter().enumerate() {
+                            pixels[y * width + x][axis] = *value;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(HdrImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+const EXR_MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+
+/// Writes float channels, each with one value per pixel row by row from the top, as an
+/// uncompressed scanline OpenEXR file. Names like `normal.X` put channels into layers.
+pub fn write_exr(width: usize, height: usize, channels: &[(String, Vec<f32>)]) -> Vec<u8> {
+    // Readers expect the channels sorted by name, in the list and in the pixel data.
+    let mut channels: Vec<&(String, Vec<f32>)> = channels.iter().collect();
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    fn attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        out.extend_from_slice(kind.as_bytes());
+        out.push(0);
+        out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        out.extend_from_slice(value);
+    }
+    let ints = |values: &[i32]| -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    };
+
+    let mut out = EXR_MAGIC.to_vec();
+    out.extend_from_slice(&2_u32.to_le_bytes());
+
+    let mut list = Vec::new();
+    for (name, _) in &channels {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+        // 32 bit float, not linear, reserved, no subsampling.
+        list.extend_from_slice(&ints(&[2, 0, 1, 1]));
+    }
+    list.push(0);
+    attribute(&mut out, "channels", "chlist", &list);
+    attribute(&mut out, "compression", "compression", &[0]);
+    let window = ints(&[0, 0, width as i32 - 1, height as i32 - 1]);
+    attribute(&mut out, "dataWindow", "box2i", &window);
+    attribute(&mut out, "displayWindow", "box2i", &window);
+    attribute(&mut out, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut out,
+        "pixelAspectRatio",
+        "float",
+        &1.0_f32.to_le_bytes(),
+    );
+    attribute(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut out,
+        "screenWindowWidth",
+        "float",
+        &1.0_f32.to_le_bytes(),
+    );
+    out.push(0);
+
+    let data_size = channels.len() * width * 4;
+    let first_block = out.len() + 8 * height;
+    for y in 0..height {
+        out.extend_from_slice(&((first_block + y * (8 + data_size)) as u64).to_le_bytes());
+    }
+    for y in 0..height {
+        out.extend_from_slice(&(y as i32).to_le_bytes());
+        out.extend_from_slice(&(data_size as u32).to_le_bytes());
+        for (_, values) in &channels {
+            for value in &values[y * width..(y + 1) * width] {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    out
+}
+
+fn rgbe_to_rgb(rgbe: &[u8; 4]) -> Vector3<f32> {
+    if rgbe[3] == 0 {
+        return vec3(0.0, 0.0, 0.0);
+    }
+    let scale = 2.0_f32.powi(i32::from(rgbe[3]) - 136);
+    vec3(
+        (f32::from(rgbe[0]) + 0.5) * scale,
+        (f32::from(rgbe[1]) + 0.5) * scale,
+        (f32::from(rgbe[2]) + 0.5) * scale,
+    )
+}
+
+/// Decodes one scanline into `scanline`, returning the remaining data.
+fn read_hdr_scanline<'a>(data: &'a [u8], scanline: &mut [[u8; 4]]) -> Result<&'a [u8], String> {
+    let width = scanline.len();
+    let truncated = || "truncated .hdr pixel data".to_string();
+
+    let run_length_encoded = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && data[2] & 0x80 == 0;
+    if !run_length_encoded {
+        let flat = data.get(..4 * width).ok_or_else(truncated)?;
+        for (pixel, rgbe) in scanline.iter_mut().zip(flat.chunks_exact(4)) {
+            pixel.copy_from_slice(rgbe);
+        }
+        return Ok(&data[4 * width..]);
+    }
+    if (usize::from(data[2]) << 8 | usize::from(data[3])) != width {
+        return Err("mismatched .hdr scanline width".to_string());
+    }
+
+    // Each of the four components is encoded separately as runs and literal spans.
+    let mut data = &data[4..];
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = usize::from(*data.first().ok_or_else(truncated)?);
+            if count > 128 {
+                let count = count - 128;
+                let value = *data.get(1).ok_or_else(truncated)?;
+                if x + count > width {
+                    return Err("bad .hdr run length".to_string());
+                }
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[component] = value;
+                }
+                data = &data[2..];
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err("bad .hdr run length".to_string());
+                }
+                let values = data.get(1..=count).ok_or_else(truncated)?;
+                for (pixel, &value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[component] = value;
+                }
+                data = &data[count + 1..];
+                x += count;
+            }
+        }
+    }
+    Ok(data)
+}
+
+/// IEEE half precision to single precision.
+pub fn half_to_f32(half: u16) -> f32 {
+    let sign = u32::from(half >> 15) << 31;
+    let exponent = u32::from((half >> 10) & 0x1f);
+    let mantissa = u32::from(half & 0x3ff);
+
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        (0, _) => {
+            // Subnormal, renormalize into a single precision normal number.
+            let shift = mantissa.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | ((mantissa << shift) & 0x3ff) << 13
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | mantissa << 13,
+        _ => sign | (exponent + 112) << 23 | mantissa << 13,
+    };
+    f32::from_bits(bits)
+}
+
+/// Little endian reader over a byte slice.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let slice = self
+            .offset
+            .checked_add(count)
+            .and_then(|end| self.bytes.get(self.offset..end))
+            .ok_or_else(|| "unexpected end of OpenEXR file".to_string())?;
+        self.offset += count;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from(self.u32()?) | u64::from(self.u32()?) << 32)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let rest = &self.bytes[self.offset.min(self.bytes.len())..];
+        let end = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| "unterminated string in OpenEXR header".to_string())?;
+        let string = String::from_utf8_lossy(&rest[..end]).into_owned();
+        self.offset += end + 1;
+        Ok(string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    /// Shared exponent encoding of `color`, the inverse of `rgbe_to_rgb`.
+    fn rgbe(color: Vector3<f32>) -> [u8; 4] {
+        let max = color.x.max(color.y).max(color.z);
+        if max < 1e-32 {
+            return [0; 4];
+        }
+        let exponent = max.log2().floor() as i32 + 1;
+        let scale = 256.0 / 2.0_f32.powi(exponent);
+        [
+            (color.x * scale) as u8,
+            (color.y * scale) as u8,
+            (color.z * scale) as u8,
+            (exponent + 128) as u8,
+        ]
+    }
+
+    fn hdr_file(width: usize, height: usize, data: &[u8]) -> Vec<u8> {
+        let mut bytes = format!(
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            height, width
+        )
+        .into_bytes();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn rgbe_round_trip() {
+        let colors = [
+            vec3(1.0, 0.5, 0.25),
+            vec3(0.0, 0.0, 0.0),
+            vec3(1000.0, 20.0, 0.3),
+            vec3(0.01, 0.02, 0.03),
+        ];
+        let data: Vec<u8> = colors.iter().flat_map(|&color| rgbe(color)).collect();
+        let image = HdrImage::parse(&hdr_file(2, 2, &data)).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 2));
+        for (pixel, color) in image.pixels.iter().zip(&colors) {
+            // Eight bit mantissas lose about one part in 256 of the brightest channel.
+            let max = color.x.max(color.y).max(color.z);
+            assert!((pixel - color).magnitude() <= max / 100.0);
+        }
+    }
+
+    #[test]
+    fn run_length_encoded_scanlines_match_flat_ones() {
+        let width = 8;
+        let reds: Vec<u8> = (0..width).map(|x| 100 + x as u8).collect();
+        let (green, blue, exponent) = (50, 25, 129);
+
+        // A literal span for the red channel, one run each for the others.
+        let mut encoded = vec![2, 2, 0, width as u8, width as u8];
+        encoded.extend_from_slice(&reds);
+        for &value in &[green, blue, exponent] {
+            encoded.extend_from_slice(&[128 + width as u8, value]);
+        }
+        let flat: Vec<u8> = reds
+            .iter()
+            .flat_map(|&red| vec![red, green, blue, exponent])
+            .collect();
+
+        let decoded = HdrImage::parse(&hdr_file(width, 1, &encoded)).unwrap();
+        let expected = HdrImage::parse(&hdr_file(width, 1, &flat)).unwrap();
+        assert_eq!(decoded.pixels, expected.pixels);
+        assert!(HdrImage::parse(&hdr_file(width, 1, &encoded[..10])).is_err());
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let (width, height) = (3, 2);
+        let channel = |name: &str, scale: f32| {
+            let values = (0..width * height).map(|i| i as f32 * scale).collect();
+            (name.to_string(), values)
+        };
+        let bytes = write_exr(
+            width,
+            height,
+            &[channel("R", 1.0), channel("G", 0.5), channel("B", -2.0)],
+        );
+        let image = HdrImage::parse(&bytes).unwrap();
+
+        assert_eq!((image.width, image.height), (width, height));
+        for (i, pixel) in image.pixels.iter().enumerate() {
+            assert_eq!(*pixel, vec3(i as f32, i as f32 * 0.5, i as f32 * -2.0));
+        }
+    }
+
+    #[test]
+    fn oversized_images_are_errors() {
+        assert!(HdrImage::parse(&hdr_file(100_000, 100_000, &[])).is_err());
+        assert!(HdrImage::parse(&hdr_file(2, 0, &[])).is_err());
+        let truncated = HdrImage::parse(&hdr_file(2, 2, &[])).err();
+        assert!(truncated.is_some_and(|error| error.contains("truncated")));
+        let header = format!("#?RADIANCE\n\n-Y {0} +X {0}\n", usize::MAX);
+        assert!(HdrImage::parse(header.as_bytes()).is_err());
+
+        // Stretch the data window of a valid file as far as it goes.
+        let mut bytes = write_exr(1, 1, &[("R".to_string(), vec![1.0])]);
+        let name = b"dataWindow\0box2i\0";
+        let start = bytes
+            .windows(name.len())
+            .position(|window| window == name)
+            .unwrap()
+            + name.len()
+            + 4;
+        bytes[start..start + 4].copy_from_slice(&i32::MIN.to_le_bytes());
+        bytes[start + 8..start + 12].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert!(HdrImage::parse(&bytes).is_err());
+
+        assert!(HdrImage::from_srgba8(usize::MAX, 2, &[0; 8]).is_err());
+    }
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+        assert_eq!(half_to_f32(0x0001), 2.0_f32.powi(-24));
+        assert!(half_to_f32(0x7c00).is_infinite());
+    }
+}
@@ -0,0 +1,454 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+
+use crate::lights::Light;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+use crate::spectrum;
+use crate::world::World;
+
+const MAX_DEPTH: usize = 100;
+
+/// Light samples per camera ray for the shadow on a shadow catcher.
+const SHADOW_SAMPLES: usize = 4;
+
+#[inline]
+fn luminance(rgb: &Vector3<f32>) -> f32 {
+    0.2126 * rgb.x + 0.7152 * rgb.y + 0.0722 * rgb.z
+}
+
+/// Power heuristic weight of a strategy with density `pdf` against one with `other_pdf`.
+#[inline]
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// Upsamples an RGB quantity to the wavelengths of the path, if there are any.
+fn to_path_space(rgb: Vector3<f32>, wavelengths: &Option<Vector3<f32>>) -> Vector3<f32> {
+    match wavelengths {
+        Some(wavelengths) => spectrum::uplift(&rgb, wavelengths),
+        None => rgb,
+    }
+}
+
+/// Next event estimation, light reflected along `-ray.direction` arriving straight from
+/// `light`. Analytic lights can't be hit by paths, so there is nothing to weight against.
+fn sample_light(ray: &Ray, rec: &ShadeRecord, world: &World, light: &dyn Light) -> Vector3<f32> {
+    let sample = match light.sample(&rec.local_hit_point) {
+        Some(sample) if sample.pdf > 0.0 => sample,
+        _ => return vec3(0.0, 0.0, 0.0),
+    };
+    let bsdf = rec.material.eval(ray, rec, &sample.direction);
+    if bsdf == vec3(0.0, 0.0, 0.0) {
+        return vec3(0.0, 0.0, 0.0);
+    }
+
+    let shadow_ray = Ray::new(rec.local_hit_point, sample.direction, ray.time);
+    let visibility = world.transmittance(&shadow_ray, sample.distance * (1.0 - 1e-4));
+    if visibility <= 0.0 {
+        return vec3(0.0, 0.0, 0.0);
+    }
+    bsdf.mul_element_wise(sample.radiance) * (visibility / sample.pdf)
+}
+
+/// Next event estimation towards an importance sampled background, weighted against the
+/// material sampling the same direction and missing everything.
+fn sample_background(ray: &Ray, rec: &ShadeRecord, world: &World, u: (f32, f32)) -> Vector3<f32> {
+    let sample = match world.background().sample(u) {
+        Some(sample) if sample.pdf > 0.0 => sample,
+        _ => return vec3(0.0, 0.0, 0.0),
+    };
+    let bsdf = rec.material.eval(ray, rec, &sample.direction);
+    if bsdf == vec3(0.0, 0.0, 0.0) {
+        return vec3(0.0, 0.0, 0.0);
+    }
+
+    let shadow_ray = Ray::new(rec.local_hit_point, sample.direction, ray.time);
+    let visibility = world.transmittance(&shadow_ray, sample.distance);
+    if visibility <= 0.0 {
+        return vec3(0.0, 0.0, 0.0);
+    }
+
+    let bsdf_pdf = rec.material.pdf(ray, rec, &sample.direction);
+    let weight = power_heuristic(sample.pdf, bsdf_pdf);
+    bsdf.mul_element_wise(sample.radiance) * (visibility * weight / sample.pdf)
+}
+
+/// How much of the direct light reaching a shadow catcher other objects block, from zero
+/// where it is fully lit to one in full shadow.
+fn shadow_amount(ray: &Ray, rec: &ShadeRecord, world: &World, sampler: &mut dyn Sampler) -> f32 {
+    let (mut lit, mut unoccluded) = (0.0, 0.0);
+    let mut add = |direction: Vector3<f32>, distance: f32, light: Vector3<f32>| {
+        let light = luminance(&light);
+        if light <= 0.0 {
+            return;
+        }
+        let shadow_ray = Ray::new(rec.local_hit_point, direction, ray.time);
+        unoccluded += light;
+        lit += light * world.transmittance(&shadow_ray, distance * (1.0 - 1e-4));
+    };
+
+    for _ in 0..SHADOW_SAMPLES {
+        for sample in world
+            .lights()
+            .iter()
+            .filter_map(|light| light.sample(&rec.local_hit_point))
+            .filter(|sample| sample.pdf > 0.0)
+        {
+            let bsdf = rec.material.eval(ray, rec, &sample.direction);
+            add(
+                sample.direction,
+                sample.distance,
+                bsdf.mul_element_wise(sample.radiance) / sample.pdf,
+            );
+        }
+
+        // Backgrounds that can't be sampled directly are found through the material.
+        match world.background().sample(sampler.get_2d()) {
+            Some(sample) if sample.pdf > 0.0 => {
+                let bsdf = rec.material.eval(ray, rec, &sample.direction);
+                add(
+                    sample.direction,
+                    sample.distance,
+                    bsdf.mul_element_wise(sample.radiance) / sample.pdf,
+                );
+            }
+            _ => {
+                if let Some(sample) = rec.material.sample(ray, rec, sampler) {
+                    let background = world.background().radiance(&sample.direction);
+                    add(
+                        sample.direction,
+                        f32::MAX,
+                        sample.weight.mul_element_wise(background),
+                    );
+                }
+            }
+        }
+    }
+
+    if unoccluded > 0.0 {
+        (1.0 - lit / unoccluded).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// What a camera ray found: the colour split up by how the light got there, and the first
+/// surface it hit. Colours are premultiplied by `alpha`.
+pub struct PathSample {
+    /// Sum of `emission`, `direct` and `indirect`.
+    pub color: Vector3<f32>,
+    /// Only below one where the ray sees a transparent background or a shadow catcher.
+    pub alpha: f32,
+    /// Light seen straight away, from emitters and the background.
+    pub emission: Vector3<f32>,
+    /// Light that bounced once on its way to the camera.
+    pub direct: Vector3<f32>,
+    /// Light that bounced more than once.
+    pub indirect: Vector3<f32>,
+    /// Light from each of the analytic lights of the world, in the same order.
+    pub lights: Vec<Vector3<f32>>,
+    /// Ray parameter of the first hit, infinite when the ray misses everything.
+    pub depth: f32,
+    pub normal: Vector3<f32>,
+    pub albedo: Vector3<f32>,
+    /// Index of the first object hit in the world.
+    pub object: Option<usize>,
+    /// One plus the index of the first material hit in the world.
+    pub material: Option<usize>,
+}
+
+impl PathSample {
+    fn new(lights: usize) -> Self {
+        PathSample {
+            color: vec3(0.0, 0.0, 0.0),
+            alpha: 1.0,
+            emission: vec3(0.0, 0.0, 0.0),
+            direct: vec3(0.0, 0.0, 0.0),
+            indirect: vec3(0.0, 0.0, 0.0),
+            lights: vec![vec3(0.0, 0.0, 0.0); lights],
+            depth: f32::INFINITY,
+            normal: vec3(0.0, 0.0, 0.0),
+            albedo: vec3(0.0, 0.0, 0.0),
+            object: None,
+            material: None,
+        }
+    }
+
+    /// Adds light that bounced `bounces` times before reaching the camera.
+    fn add(&mut self, bounces: usize, radiance: Vector3<f32>) {
+        match bounces {
+            0 => self.emission += radiance,
+            1 => self.direct += radiance,
+            _ => self.indirect += radiance,
+        }
+    }
+}
+
+/// Estimates the radiance arriving along `ray` with a path tracer that only talks to
+/// materials through the `Material` trait. Rays carrying wavelengths are traced
+/// spectrally, materials and textures stay in RGB and get upsampled per bounce.
+///
+/// `screen` is where the camera ray goes through the frame, from the top left in `[0, 1]`.
+/// `sampler` supplies the background samples of every bounce.
+pub fn generate_color_for_pixel(
+    ray: Ray,
+    world: &World,
+    screen: (f32, f32),
+    sampler: &mut dyn Sampler,
+) -> PathSample {
+    let wavelengths = ray.wavelengths;
+    let mut sample = trace_path(ray, world, screen, sampler);
+    if let Some(wavelengths) = wavelengths {
+        let to_rgb = |radiance: &Vector3<f32>| spectrum::to_rgb(radiance, &wavelengths);
+        sample.emission = to_rgb(&sample.emission);
+        sample.direct = to_rgb(&sample.direct);
+        sample.indirect = to_rgb(&sample.indirect);
+        for light in sample.lights.iter_mut() {
+            *light = to_rgb(light);
+        }
+    }
+    sample.color = sample.emission + sample.direct + sample.indirect;
+    sample
+}
+
+fn trace_path(
+    ray: Ray,
+    world: &World,
+    screen: (f32, f32),
+    sampler: &mut dyn Sampler,
+) -> PathSample {
+    let mut ray = ray;
+    let mut path = PathSample::new(world.lights().len());
+    let mut throughput = vec3(1.0, 1.0, 1.0);
+    let mut secondary_terminated = false;
+    // Density of the material sample that produced `ray`, `None` for camera rays and
+    // specular bounces which next event estimation can't reproduce.
+    let mut bsdf_pdf: Option<f32> = None;
+
+    for depth in 0..MAX_DEPTH {
+        let rec = match world.trace(&ray) {
+            Some(rec) => rec,
+            None if depth == 0 => {
+                let (background, alpha) = world.background().visible(&ray.direction, screen);
+                path.emission = to_path_space(background, &ray.wavelengths);
+                path.alpha = alpha;
+                return path;
+            }
+            None => {
+                let background = world.background();
+                let weight = match bsdf_pdf {
+                    Some(pdf) if background.pdf(&ray.direction) > 0.0 => {
+                        power_heuristic(pdf, background.pdf(&ray.direction))
+                    }
+                    _ => 1.0,
+                };
+                let background =
+                    to_path_space(background.radiance(&ray.direction), &ray.wavelengths);
+                path.add(depth, throughput.mul_element_wise(background) * weight);
+                return path;
+            }
+        };
+
+        if depth == 0 {
+            path.depth = rec.intersect_parameter;
+            path.normal = rec.normal;
+            path.albedo = rec.material.albedo(&rec);
+            path.object = rec.object.and_then(|object| world.object_index(object));
+            path.material = world.material_index(rec.material).map(|index| index + 1);
+
+            if rec.material.is_shadow_catcher() {
+                let shadow = shadow_amount(&ray, &rec, world, sampler);
+                let (backdrop, alpha) = world.background().visible(&ray.direction, screen);
+                if alpha > 0.0 {
+                    path.emission = to_path_space(backdrop * (1.0 - shadow), &ray.wavelengths);
+                    path.alpha = alpha;
+                } else {
+                    // Nothing behind to darken, the shadow is a black layer of its own.
+                    path.alpha = shadow;
+                }
+                return path;
+            }
+        }
+
+        let emitted = to_path_space(rec.material.emitted(&ray, &rec), &ray.wavelengths);
+        path.add(depth, throughput.mul_element_wise(emitted));
+
+        for (index, light) in world.lights().iter().enumerate() {
+            let direct = sample_light(&ray, &rec, world, light.as_ref());
+            let direct = throughput.mul_element_wise(to_path_space(direct, &ray.wavelengths));
+            path.lights[index] += direct;
+            path.add(depth + 1, direct);
+        }
+        let direct = sample_background(&ray, &rec, world, sampler.get_2d());
+        let direct = to_path_space(direct, &ray.wavelengths);
+        path.add(depth + 1, throughput.mul_element_wise(direct));
+
+        match rec.material.sample(&ray, &rec, sampler) {
+            Some(sample) => {
+                let weight = to_path_space(sample.weight, &ray.wavelengths);
+                throughput = throughput.mul_element_wise(weight);
+                if sample.terminate_secondary && !secondary_terminated {
+                    // The hero wavelength now stands in for all three.
+                    throughput = vec3(throughput.x * 3.0, 0.0, 0.0);
+                    secondary_terminated = true;
+                }
+                bsdf_pdf = if sample.specular {
+                    None
+                } else {
+                    Some(sample.pdf)
+                };
+                let origin = sample.origin.unwrap_or(rec.local_hit_point);
+                ray =
+                    Ray::new(origin, sample.direction, ray.time).with_wavelengths(ray.wavelengths);
+            }
+            None => return path,
+        }
+    }
+
+    // Out of bounces, assume the rest of the path would have escaped.
+    let background = to_path_space(
+        world.background().radiance(&ray.direction),
+        &ray.wavelengths,
+    );
+    path.add(MAX_DEPTH, throughput.mul_element_wise(background));
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Point3;
+
+    use crate::background::Background;
+    use crate::geometric_objects::Sphere;
+    use crate::lights::DirectionalLight;
+    use crate::materials::{Lambertian, Material, ShadowCatcher};
+    use crate::sampler::IndependentSampler;
+    use crate::textures::Texture;
+    use std::rc::Rc;
+
+    /// Shadow catcher floor at height zero, with a ball casting a shadow straight down on
+    /// the origin.
+    fn shadow_world(transparent: bool) -> World {
+        let mut world = World::new();
+        world.add_object(Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            ShadowCatcher {
+                base: Lambertian {
+                    texture: Texture::scalar(0.5),
+                },
+            },
+        )));
+        world.add_object(Box::new(Sphere::new(
+            Point3::new(0.0, 1.0, 0.0),
+            0.5,
+            Lambertian {
+                texture: Texture::scalar(0.5),
+            },
+        )));
+        world.add_light(Box::new(DirectionalLight {
+            direction: vec3(0.0, -1.0, 0.0),
+            irradiance: vec3(20.0, 20.0, 20.0),
+            angular_diameter: 0.0,
+        }));
+        if transparent {
+            world.set_background(Background::Solid(vec3(0.0, 0.0, 0.0)));
+            world.set_transparent_background();
+        } else {
+            world.set_background(Background::Solid(vec3(0.4, 0.4, 0.4)));
+        }
+        world
+    }
+
+    /// Camera ray looking at the floor at `x` from the side, below the ball.
+    fn look_at_floor(world: &World, x: f32) -> PathSample {
+        let origin = Point3::new(x, 0.2, 3.0);
+        let ray = Ray::new(origin, Point3::new(x, 0.0, 0.0) - origin, 0.0);
+        generate_color_for_pixel(ray, world, (0.5, 0.5), &mut IndependentSampler)
+    }
+
+    #[test]
+    fn shadow_catcher_darkens_the_background() {
+        let world = shadow_world(false);
+
+        let lit = look_at_floor(&world, 2.0);
+        assert_eq!(lit.alpha, 1.0);
+        assert!((lit.color.x - 0.4).abs() < 0.02);
+
+        // The background lights the floor a little too, so the shadow isn't quite black.
+        let shadowed = look_at_floor(&world, 0.0);
+        assert_eq!(shadowed.alpha, 1.0);
+        assert!(shadowed.color.x < 0.05);
+    }
+
+    #[test]
+    fn shadow_catcher_over_transparency_is_a_shadow_layer() {
+        let world = shadow_world(true);
+
+        let lit = look_at_floor(&world, 2.0);
+        assert_eq!(lit.alpha, 0.0);
+        assert_eq!(lit.color, vec3(0.0, 0.0, 0.0));
+
+        let shadowed = look_at_floor(&world, 0.0);
+        assert!(shadowed.alpha > 0.95);
+        assert_eq!(shadowed.color, vec3(0.0, 0.0, 0.0));
+        assert_eq!(shadowed.material, Some(1));
+
+        // Other objects still show up in full.
+        let origin = Point3::new(0.0, 1.0, 3.0);
+        let ray = Ray::new(origin, vec3(0.0, 0.0, -1.0), 0.0);
+        let ball = generate_color_for_pixel(ray, &world, (0.5, 0.5), &mut IndependentSampler);
+        assert_eq!(ball.alpha, 1.0);
+        assert_eq!(ball.object, Some(1));
+        assert_eq!(ball.material, Some(2));
+    }
+
+    #[test]
+    fn objects_sharing_a_material_share_its_id() {
+        let grey: Rc<dyn Material> = Rc::new(Lambertian {
+            texture: Texture::scalar(0.5),
+        });
+        let mut world = World::new();
+        for &(x, shared) in &[(-2.0, true), (0.0, false), (2.0, true)] {
+            let center = Point3::new(x, 0.0, 0.0);
+            world.add_object(Box::new(if shared {
+                Sphere::new(center, 0.5, grey.clone())
+            } else {
+                Sphere::new(
+                    center,
+                    0.5,
+                    Lambertian {
+                        texture: Texture::scalar(0.5),
+                    },
+                )
+            }));
+        }
+
+        let ids: Vec<_> = [-2.0, 0.0, 2.0, 4.0]
+            .iter()
+            .map(|&x| {
+                let ray = Ray::new(Point3::new(x, 0.0, 5.0), vec3(0.0, 0.0, -1.0), 0.0);
+                let path =
+                    generate_color_for_pixel(ray, &world, (0.5, 0.5), &mut IndependentSampler);
+                (path.object, path.material)
+            })
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                (Some(0), Some(1)),
+                (Some(1), Some(2)),
+                (Some(2), Some(1)),
+                (None, None),
+            ]
+        );
+    }
+}
@@ -1,221 +1,338 @@
 #![warn(clippy::all)]
 #![warn(clippy::missing_const_for_fn)]
-use cgmath::prelude::*;
-use cgmath::{vec3, Point3, Vector3};
-use std::{f32, mem, u16, usize};
 use wasm_bindgen::prelude::*;
 
-mod camera;
-mod geometric_objects;
-mod materials;
-mod ray;
-mod scene;
-mod shade_record;
-mod world;
-
-use crate::materials::{
-    generate_reflect_probability, random_vec_in_unit_sphere, reflected_vector, refracted_vector,
-    Material::*,
-};
+pub mod aov;
+pub mod assets;
+pub mod background;
+pub mod camera;
+pub mod denoise;
+pub mod environment;
+pub mod film;
+pub mod filter;
+pub mod frame;
+pub mod geometric_objects;
+pub mod hdr_image;
+pub mod integrator;
+pub mod lights;
+pub mod materials;
+pub mod media;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod shade_record;
+pub mod sky;
+pub mod spectrum;
+pub mod textures;
+pub mod world;
+
+use crate::aov::Aov;
+use crate::environment::EnvironmentMap;
+use crate::film::{Film, Region};
+use crate::filter::{Filter, FilterKind};
+use crate::hdr_image::HdrImage;
+use crate::lights::IesProfile;
+use crate::media::VoxelGrid;
 use crate::ray::Ray;
-use crate::scene::{get_predefined_scene, get_random_scene};
-use crate::world::World;
+use crate::render::{render, AdaptiveSampling, Framebuffer, RenderSettings};
+use crate::sampler::SamplerKind;
+use crate::scene::{get_scene, VOXEL_GRID_MAX, VOXEL_GRID_MIN};
+use crate::scene_file::SceneDescription;
 
 #[wasm_bindgen]
 extern "C" {
     fn alert(s: &str);
 
-    #[wasm_bindgen(js_namespace = Math, js_name = random)]
-    pub fn random() -> f32;
-
     #[wasm_bindgen(js_namespace = console, js_name = log)]
     pub fn log(s: &str);
+}
 
-    type Crypto;
-    static crypto: Crypto;
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Math, js_name = random)]
+    pub fn random() -> f32;
+}
 
-    #[wasm_bindgen(method, js_name = getRandomValues)]
-    fn get_random_values(this: &Crypto, values: Vec<u16>) -> Vec<u16>;
+/// Stand-in for `Math.random` outside the browser, so the tests can run natively. A
+/// xorshift generator per thread, seeded the same every time.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn random() -> f32 {
+    use std::cell::Cell;
+
+    thread_local! {
+        static STATE: Cell<u32> = const { Cell::new(0x9e37_79b9) };
+    }
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        state.set(x);
+        (x >> 8) as f32 / (1 << 24) as f32
+    })
 }
 
-fn make_random_array(len: usize) -> Vec<f32> {
-    let max_val = f32::from(u16::MAX);
+/// With adaptive sampling pixels get up to this many times the requested samples.
+const MAX_ADAPTIVE_SAMPLES_FACTOR: u32 = 4;
 
-    crypto
-        .get_random_values(vec![0_u16; len])
-        .into_iter()
-        .map(|x: u16| 1.0 - (2.0 * (f32::from(x) / max_val)))
-        .collect::<Vec<_>>()
+/// Reconstruction filter called `name`, with its usual radius unless `radius` is positive.
+fn parse_filter(name: &str, radius: f32) -> Result<Filter, JsValue> {
+    let kind = FilterKind::parse(name).map_err(|error| JsValue::from_str(&error))?;
+    let radius = if radius > 0.0 {
+        radius
+    } else {
+        kind.default_radius()
+    };
+    Ok(Filter::new(kind, radius))
 }
 
-fn jittered_samples(num_samples: u8) -> Vec<(f32, f32)> {
-    let n = f32::from(num_samples).sqrt() as usize;
-    let mut samples = Vec::new();
-    for j in 0..n {
-        for k in 0..n {
-            let sp = (
-                (k as f32 + (1.0 - 2.0 * random())) / n as f32,
-                (j as f32 + (1.0 - 2.0 * random())) / n as f32,
-            );
-            samples.push(sp);
-        }
+/// Renders the crop of `film` of the built-in scene called `scene`, built from `seed`, with
+/// the given passes next to the beauty image.
+#[allow(clippy::too_many_arguments)]
+fn render_scene(
+    film: Film,
+    num_samples: u8,
+    scene: &str,
+    seed: u32,
+    sampler: SamplerKind,
+    filter: Filter,
+    spectral: bool,
+    transparent_background: bool,
+    denoise: bool,
+    noise_threshold: f32,
+    aovs: Vec<Aov>,
+) -> Result<Framebuffer, JsValue> {
+    let (camera, mut world) = get_scene(scene, film.width, film.height, seed)
+        .map_err(|error| JsValue::from_str(&error))?;
+    if transparent_background {
+        world.set_transparent_background();
     }
-    samples
-}
-
-const BACKGROUND_COLOR: Vector3<f32> = vec3(0.01, 0.01, 0.01);
-
-fn generate_color_for_pixel(ray: &Ray, world: &World, depth: usize) -> Vector3<f32> {
-    let shade_record = world.trace(ray);
-
-    let pixel_color: Vector3<f32> = match (shade_record, depth < 100) {
-        (_, false) => BACKGROUND_COLOR,
-        (None, _) => {
-            // This code adds background ambiental fake light source.
-            let unit_direction = ray.direction.normalize();
-            let t = (unit_direction.y + 1.0) * 0.5;
-            vec3(0.1, 0.1, 0.1).lerp(BACKGROUND_COLOR, t)
-        }
-        // TODO: Figure out how to add time=0.0 as default param for ray class
-        (Some(ref rec), true) => {
-            let accumulated_color: Vector3<f32> = match &rec.material {
-                Lambertian { texture } => {
-                    let target = rec.local_hit_point + rec.normal + random_vec_in_unit_sphere();
-                    let bounced_ray =
-                        Ray::new(rec.local_hit_point, target - rec.local_hit_point, 0.0);
-                    let v = generate_color_for_pixel(&bounced_ray, world, depth + 1);
-                    let Point3 { x: r, y: g, z: b } = texture.value(0.0, 0.0, &rec.local_hit_point);
-                    vec3(v.x * r, v.y * g, v.z * b)
-                }
-                Metallic { r, g, b } => {
-                    let reflected = reflected_vector(&ray.direction.normalize(), &rec.normal);
-                    let scattered = Ray::new(
-                        rec.local_hit_point,
-                        reflected + 0.5 * random_vec_in_unit_sphere(),
-                        0.0,
-                    );
-
-                    if scattered.direction.dot(rec.normal) > 0.0 {
-                        let u = generate_color_for_pixel(&scattered, world, depth + 1);
-                        vec3(u.x * r, u.y * g, u.z * b)
-                    } else {
-                        generate_color_for_pixel(&scattered, world, depth + 1)
-                    }
-                }
-                Dielectric { refractive_index } => {
-                    let reflected = reflected_vector(&ray.direction, &rec.normal);
-                    let ni_over_t;
-                    let outward_normal;
-                    let refracted;
-                    let reflect_prob;
-                    let mut cosine;
-
-                    if ray.direction.dot(rec.normal) > 0.0 {
-                        outward_normal = -rec.normal;
-                        ni_over_t = *refractive_index;
-                        cosine = ray.direction.dot(rec.normal) / ray.direction.magnitude();
-                        cosine = (1.0
-                            - refractive_index * refractive_index * (1.0 - cosine * cosine))
-                            .sqrt();
-                    } else {
-                        outward_normal = rec.normal;
-                        ni_over_t = 1.0 / refractive_index;
-                        cosine = -ray.direction.dot(rec.normal) / ray.direction.magnitude();
-                    }
-
-                    if let Some(x) = refracted_vector(&ray.direction, &outward_normal, ni_over_t) {
-                        reflect_prob = generate_reflect_probability(cosine, *refractive_index);
-                        refracted = x;
-                    } else {
-                        reflect_prob = 1.0;
-                        refracted = vec3(1.0, 1.0, 1.0);
-                    };
-
-                    let bounced_ray = if random() < reflect_prob {
-                        Ray::new(rec.local_hit_point, reflected, 0.0)
-                    } else {
-                        Ray::new(rec.local_hit_point, refracted, 0.0)
-                    };
-                    generate_color_for_pixel(&bounced_ray, world, depth + 1)
-                }
-                DiffuseLight { texture } => {
-                    let Point3 { x: r, y: g, z: b } = texture.value(
-                        rec.local_hit_point.x,
-                        rec.local_hit_point.y,
-                        &rec.local_hit_point,
-                    );
-                    vec3(r, g, b)
-                }
-            };
-            accumulated_color
-        }
+
+    let settings = RenderSettings {
+        film,
+        samples_per_pixel: u32::from(num_samples),
+        sampler,
+        filter,
+        spectral,
+        aovs,
+        denoise,
+        adaptive: if noise_threshold > 0.0 {
+            Some(AdaptiveSampling {
+                noise_threshold,
+                max_samples: MAX_ADAPTIVE_SAMPLES_FACTOR * u32::from(num_samples),
+            })
+        } else {
+            None
+        },
     };
-    pixel_color
+    Ok(render(&camera, &world, &settings))
 }
 
 #[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
 pub fn make_image(
     canvas_width: u16,
     canvas_height: u16,
     num_samples: u8,
-    random_scene: bool,
-    jittered_sampling: bool,
-) -> Vec<u32> {
-    let preallocate_capacity = usize::from(canvas_width) * usize::from(canvas_height);
+    scene: &str,
+    seed: u32,
+    sampler: &str,
+    filter: &str,
+    filter_radius: f32,
+    spectral: bool,
+    transparent_background: bool,
+    denoise: bool,
+    noise_threshold: f32,
+) -> Result<Vec<u32>, JsValue> {
+    let sampler = SamplerKind::parse(sampler).map_err(|error| JsValue::from_str(&error))?;
+    let filter = parse_filter(filter, filter_radius)?;
+    let framebuffer = render_scene(
+        Film::new(canvas_width, canvas_height),
+        num_samples,
+        scene,
+        seed,
+        sampler,
+        filter,
+        spectral,
+        transparent_background,
+        denoise,
+        noise_threshold,
+        Vec::new(),
+    )?;
+    Ok(framebuffer.to_rgba8())
+}
 
-    let samples_divider = f32::from(num_samples);
+/// Like `make_image`, rendering only the `region_width` by `region_height` pixels at
+/// `region_x`, `region_y` of the frame, with the camera of the full frame. Returns the
+/// pixels of the region. Given the `seed` of the full frame the region shows the same
+/// scene and lines up with the rest of the image, with noise of its own. Denoising only
+/// sees the region, so it can differ along its edges.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn make_region(
+    canvas_width: u16,
+    canvas_height: u16,
+    region_x: usize,
+    region_y: usize,
+    region_width: usize,
+    region_height: usize,
+    num_samples: u8,
+    scene: &str,
+    seed: u32,
+    sampler: &str,
+    filter: &str,
+    filter_radius: f32,
+    spectral: bool,
+    transparent_background: bool,
+    denoise: bool,
+    noise_threshold: f32,
+) -> Result<Vec<u32>, JsValue> {
+    let film = Film::new(canvas_width, canvas_height)
+        .with_crop(Region {
+            x: region_x,
+            y: region_y,
+            width: region_width,
+            height: region_height,
+        })
+        .map_err(|error| JsValue::from_str(&error))?;
+    let sampler = SamplerKind::parse(sampler).map_err(|error| JsValue::from_str(&error))?;
+    let filter = parse_filter(filter, filter_radius)?;
+    let framebuffer = render_scene(
+        film,
+        num_samples,
+        scene,
+        seed,
+        sampler,
+        filter,
+        spectral,
+        transparent_background,
+        denoise,
+        noise_threshold,
+        Vec::new(),
+    )?;
+    Ok(framebuffer.to_rgba8())
+}
 
-    let (camera, world) = if random_scene {
-        get_random_scene(canvas_width, canvas_height, 20)
-    } else {
-        get_predefined_scene(canvas_width, canvas_height)
-    };
-    let mut pixel_color = vec3(0.0, 0.0, 0.0);
-    let mut image = Vec::<u32>::with_capacity(preallocate_capacity);
+/// Loads a voxel grid in the `VOL nx ny nz` format of `VoxelGrid::parse` for the media
+/// scene.
+#[wasm_bindgen]
+pub fn load_voxel_grid(bytes: &[u8]) -> Result<(), JsValue> {
+    let grid = VoxelGrid::parse(bytes, VOXEL_GRID_MIN, VOXEL_GRID_MAX)
+        .map_err(|error| JsValue::from_str(&error))?;
+    assets::set_voxel_grid(grid);
+    Ok(())
+}
 
-    // generate precomputed displacements
-    // TODO: optimize samples generation
-    let samples = if jittered_sampling {
-        jittered_samples(num_samples)
-    } else {
-        let xs = make_random_array(usize::from(num_samples));
-        let ys = make_random_array(usize::from(num_samples));
-        let mut vals = Vec::new();
-        for i in 0..xs.len() {
-            vals.push((xs[i], ys[i]));
-        }
-        vals
-    };
+/// Loads the text of an IES photometric file for the IES lights of the lights scene and of
+/// scene files.
+#[wasm_bindgen]
+pub fn load_ies_profile(text: &str) -> Result<(), JsValue> {
+    let profile = IesProfile::parse(text).map_err(|error| JsValue::from_str(&error))?;
+    assets::set_ies_profile(profile);
+    Ok(())
+}
+
+/// Loads a Radiance `.hdr` or OpenEXR panorama to light the environment scene.
+#[wasm_bindgen]
+pub fn load_environment_map(bytes: &[u8]) -> Result<(), JsValue> {
+    let map = EnvironmentMap::from_bytes(bytes).map_err(|error| JsValue::from_str(&error))?;
+    assets::set_environment_map(map);
+    Ok(())
+}
+
+/// Loads the pixels of a decoded photo, as in canvas `ImageData`, to show behind the
+/// environment scene.
+#[wasm_bindgen]
+pub fn load_backplate(width: usize, height: usize, rgba: &[u8]) -> Result<(), JsValue> {
+    let image =
+        HdrImage::from_srgba8(width, height, rgba).map_err(|error| JsValue::from_str(&error))?;
+    assets::set_backplate(image);
+    Ok(())
+}
+
+/// Loads the text of a scene file, described in `SceneDescription`, for the file scene.
+#[wasm_bindgen]
+pub fn load_scene_description(text: &str) -> Result<(), JsValue> {
+    let description =
+        SceneDescription::parse(text).map_err(|error| JsValue::from_str(&error))?;
+    assets::set_scene_description(description);
+    Ok(())
+}
+
+/// Beauty image and render passes, handed to JS.
+#[wasm_bindgen]
+pub struct RenderResult {
+    framebuffer: Framebuffer,
+}
+
+#[wasm_bindgen]
+impl RenderResult {
+    /// The beauty image as canvas pixels.
+    pub fn image(&self) -> Vec<u32> {
+        self.framebuffer.to_rgba8()
+    }
+
+    /// Float values of the pass called `name`, channels interleaved per pixel.
+    pub fn aov(&self, name: &str) -> Option<Vec<f32>> {
+        let aov = Aov::parse(name).ok()?;
+        self.framebuffer.aov(aov).map(|buffer| buffer.data.clone())
+    }
+
+    /// How many samples every pixel took, as canvas pixels.
+    pub fn sample_heatmap(&self) -> Vec<u32> {
+        self.framebuffer.sample_heatmap()
+    }
+
+    /// Number of samples taken by each pixel.
+    pub fn sample_counts(&self) -> Vec<u32> {
+        self.framebuffer.sample_counts.clone()
+    }
 
-    for i in 0..canvas_height {
-        for j in 0..canvas_width {
-            pixel_color.x = 0.0;
-            pixel_color.y = 0.0;
-            pixel_color.z = 0.0;
-
-            for sample in &samples {
-                let dx = (f32::from(j) + sample.0) / f32::from(canvas_width);
-                let dy = (f32::from(i) + sample.1) / f32::from(canvas_height);
-
-                let direction = camera.get_ray(dx, dy);
-                pixel_color += generate_color_for_pixel(&direction, &world, 0);
-            }
-            pixel_color /= samples_divider;
-
-            let Vector3 { x: r, y: g, z: b } = pixel_color;
-
-            let pixel = unsafe {
-                mem::transmute::<[u8; 4], u32>([
-                    (r.sqrt() * 255.99) as u8,
-                    (g.sqrt() * 255.99) as u8,
-                    (b.sqrt() * 255.99) as u8,
-                    255,
-                ])
-            };
-            image.push(pixel);
-        }
+    /// Everything as layers of an OpenEXR file.
+    pub fn exr(&self) -> Vec<u8> {
+        self.framebuffer.to_exr()
     }
-    image
+}
+
+/// Like `make_image`, also rendering the passes in the comma separated list `aovs`, like
+/// `"depth,normal,albedo,light0"`.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn render_passes(
+    canvas_width: u16,
+    canvas_height: u16,
+    num_samples: u8,
+    scene: &str,
+    seed: u32,
+    sampler: &str,
+    filter: &str,
+    filter_radius: f32,
+    spectral: bool,
+    transparent_background: bool,
+    denoise: bool,
+    noise_threshold: f32,
+    aovs: &str,
+) -> Result<RenderResult, JsValue> {
+    let sampler = SamplerKind::parse(sampler).map_err(|error| JsValue::from_str(&error))?;
+    let filter = parse_filter(filter, filter_radius)?;
+    let aovs = Aov::parse_list(aovs).map_err(|error| JsValue::from_str(&error))?;
+    let framebuffer = render_scene(
+        Film::new(canvas_width, canvas_height),
+        num_samples,
+        scene,
+        seed,
+        sampler,
+        filter,
+        spectral,
+        transparent_background,
+        denoise,
+        noise_threshold,
+        aovs,
+    )?;
+    Ok(RenderResult { framebuffer })
 }
 
 // test to see if wasm-bindgen works
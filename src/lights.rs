@@ -0,0 +1,427 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+use std::cmp::Ordering;
+use std::f32;
+use std::f32::consts::PI;
+
+use crate::frame::Frame;
+use crate::random;
+use crate::spectrum::LUMENS_PER_WATT;
+
+/// Light arriving at a point from a sampled light source.
+pub struct LightSample {
+    /// Unit direction from the point towards the light.
+    pub direction: Vector3<f32>,
+    /// Distance to the light, `f32::MAX` for lights at infinity.
+    pub distance: f32,
+    /// Incident radiance, or irradiance for lights that are a delta in direction.
+    pub radiance: Vector3<f32>,
+    /// Solid angle density of `direction`, one for delta lights.
+    pub pdf: f32,
+}
+
+/// Light source without geometry. Paths can not hit these, they only contribute through
+/// next event estimation.
+pub trait Light {
+    fn sample(&self, point: &Point3<f32>) -> Option<LightSample>;
+}
+
+/// Sample towards a light at `position` that has `intensity` in that direction.
+fn point_sample(
+    point: &Point3<f32>,
+    position: &Point3<f32>,
+    intensity: Vector3<f32>,
+) -> Option<LightSample> {
+    let to_light = position - point;
+    let distance2 = to_light.magnitude2();
+    if distance2 <= 0.0 {
+        return None;
+    }
+    let distance = distance2.sqrt();
+
+    Some(LightSample {
+        direction: to_light / distance,
+        distance,
+        radiance: intensity / distance2,
+        pdf: 1.0,
+    })
+}
+
+/// Light emitting `intensity`, power per steradian, equally in all directions.
+pub struct PointLight {
+    pub position: Point3<f32>,
+    pub intensity: Vector3<f32>,
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: &Point3<f32>) -> Option<LightSample> {
+        point_sample(point, &self.position, self.intensity)
+    }
+}
+
+/// Point light restricted to a cone around `direction`. The intensity falls off smoothly
+/// between `falloff_start` and `cone_angle`, both half angles in radians.
+pub struct SpotLight {
+    pub position: Point3<f32>,
+    pub direction: Vector3<f32>,
+    pub intensity: Vector3<f32>,
+    pub cone_angle: f32,
+    pub falloff_start: f32,
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: &Point3<f32>) -> Option<LightSample> {
+        let cos_theta = (point - self.position)
+            .normalize()
+            .dot(self.direction.normalize());
+        let (cos_total, cos_start) = (self.cone_angle.cos(), self.falloff_start.cos());
+
+        let falloff = if cos_start <= cos_total {
+            if cos_theta >= cos_total {
+                1.0
+            } else {
+                0.0
+            }
+        } else {
+            let t = ((cos_theta - cos_total) / (cos_start - cos_total)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        };
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        point_sample(point, &self.position, self.intensity * falloff)
+    }
+}
+
+/// Light from a source at infinity like the sun, travelling along `direction`. With an
+/// `angular_diameter` above zero, in radians, it is a small disk in the sky that casts soft
+/// shadows. `irradiance` is measured perpendicular to the light either way.
+pub struct DirectionalLight {
+    pub direction: Vector3<f32>,
+    pub irradiance: Vector3<f32>,
+    pub angular_diameter: f32,
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: &Point3<f32>) -> Option<LightSample> {
+        let to_light = -self.direction.normalize();
+        if self.angular_diameter <= 0.0 {
+            return Some(LightSample {
+                direction: to_light,
+                distance: f32::MAX,
+                radiance: self.irradiance,
+                pdf: 1.0,
+            });
+        }
+
+        // Uniformly sample the cone the disk subtends.
+        let cos_max = (0.5 * self.angular_diameter).cos();
+        let solid_angle = 2.0 * PI * (1.0 - cos_max);
+        let cos_theta = 1.0 - random() * (1.0 - cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random();
+        let local = vec3(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+
+        Some(LightSample {
+            direction: Frame::from_normal(&to_light).to_world(&local),
+            distance: f32::MAX,
+            radiance: self.irradiance / solid_angle,
+            pdf: 1.0 / solid_angle,
+        })
+    }
+}
+
+/// Candela distribution of a luminaire read from an IES LM-63 photometric file. Vertical
+/// angles start at the nadir, horizontal ones go around it, both in degrees.
+#[derive(Clone)]
+pub struct IesProfile {
+    vertical_angles: Vec<f32>,
+    horizontal_angles: Vec<f32>,
+    /// Candelas, one row of vertical samples per horizontal angle.
+    candelas: Vec<Vec<f32>>,
+}
+
+impl IesProfile {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        let tilt = loop {
+            match lines.next() {
+                Some(line) if line.trim_start().starts_with("TILT=") => {
+                    break line.trim_start()["TILT=".len()..].trim().to_string();
+                }
+                Some(_) => continue,
+                None => return Err("missing TILT line in IES file".to_string()),
+            }
+        };
+
+        let rest: Vec<&str> = lines.collect();
+        let mut numbers = rest.iter().flat_map(|line| {
+            line.split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|token| !token.is_empty())
+        });
+        let mut next = |what: &str| -> Result<f32, String> {
+            let token = numbers
+                .next()
+                .ok_or_else(|| format!("IES file ended while reading {}", what))?;
+            token
+                .parse::<f32>()
+                .map_err(|_| format!("bad {} '{}' in IES file", what, token))
+        };
+
+        match tilt.as_str() {
+            "NONE" => {}
+            "INCLUDE" => {
+                // Lamp tilt data only matters for tilted lamps, skip it.
+                next("lamp to luminaire geometry")?;
+                let pairs = next("number of tilt angles")? as usize;
+                for _ in 0..pairs {
+                    next("tilt angle")?;
+                    next("tilt multiplier")?;
+                }
+            }
+            file => {
+                return Err(format!(
+                    "external IES tilt file '{}' is not supported",
+                    file
+                ))
+            }
+        }
+
+        let _lamps = next("number of lamps")?;
+        let _lumens_per_lamp = next("lumens per lamp")?;
+        let multiplier = next("candela multiplier")?;
+        let vertical_count = next("number of vertical angles")? as usize;
+        let horizontal_count = next("number of horizontal angles")? as usize;
+        let photometric_type = next("photometric type")?;
+        for what in &["units type", "width", "length", "height"] {
+            next(what)?;
+        }
+        let ballast_factor = next("ballast factor")?;
+        for what in &["file generation type", "input watts"] {
+            next(what)?;
+        }
+
+        if photometric_type as u32 != 1 {
+            return Err("only type C IES photometry is supported".to_string());
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err("IES file has no angles".to_string());
+        }
+
+        let vertical_angles = (0..vertical_count)
+            .map(|_| next("vertical angle"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let horizontal_angles = (0..horizontal_count)
+            .map(|_| next("horizontal angle"))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Interpolation divides by the gap between neighbouring angles.
+        for (what, angles) in &[
+            ("vertical", &vertical_angles),
+            ("horizontal", &horizontal_angles),
+        ] {
+            if angles
+                .windows(2)
+                .any(|pair| pair[0].partial_cmp(&pair[1]) != Some(Ordering::Less))
+            {
+                return Err(format!("IES {} angles are not increasing", what));
+            }
+        }
+        let candelas = (0..horizontal_count)
+            .map(|_| {
+                (0..vertical_count)
+                    .map(|_| next("candela value").map(|c| c * multiplier * ballast_factor))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candelas,
+        })
+    }
+
+    /// Index and interpolation weight of `x` in the sorted `angles`, clamped to their range.
+    fn locate(angles: &[f32], x: f32) -> (usize, f32) {
+        if angles.len() == 1 || x <= angles[0] {
+            return (0, 0.0);
+        }
+        match angles.iter().position(|&angle| angle > x) {
+            Some(i) => {
+                let (a, b) = (angles[i - 1], angles[i]);
+                (i - 1, (x - a) / (b - a))
+            }
+            None => (angles.len() - 1, 0.0),
+        }
+    }
+
+    /// Candelas at `vertical` degrees from the nadir and `horizontal` degrees around it.
+    pub fn candela(&self, vertical: f32, horizontal: f32) -> f32 {
+        // Fold the horizontal angle into the range the file covers, by its symmetry.
+        let last = *self.horizontal_angles.last().unwrap_or(&0.0);
+        let horizontal = horizontal.rem_euclid(360.0);
+        let horizontal = if last <= 0.0 {
+            0.0
+        } else if last <= 90.0 {
+            let h = horizontal % 180.0;
+            if h > 90.0 {
+                180.0 - h
+            } else {
+                h
+            }
+        } else if last <= 180.0 && horizontal > 180.0 {
+            360.0 - horizontal
+        } else {
+            horizontal
+        };
+
+        let (h, th) = IesProfile::locate(&self.horizontal_angles, horizontal);
+        let (v, tv) = IesProfile::locate(&self.vertical_angles, vertical);
+        let (h1, v1) = (
+            (h + 1).min(self.horizontal_angles.len() - 1),
+            (v + 1).min(self.vertical_angles.len() - 1),
+        );
+
+        let row = |h: usize| self.candelas[h][v] * (1.0 - tv) + self.candelas[h][v1] * tv;
+        let value = row(h) * (1.0 - th) + row(h1) * th;
+        // Angles past the last vertical one get no light.
+        if vertical > *self.vertical_angles.last().unwrap_or(&180.0) {
+            0.0
+        } else {
+            value
+        }
+    }
+}
+
+/// Point light shaped by a measured IES profile. `color` scales the photometric intensity.
+pub struct IesLight {
+    pub position: Point3<f32>,
+    pub frame: Frame,
+    pub profile: IesProfile,
+    pub color: Vector3<f32>,
+}
+
+impl IesLight {
+    /// Light whose profile nadir points along `down`, with horizontal angles starting at
+    /// `front`.
+    pub fn new(
+        position: Point3<f32>,
+        down: Vector3<f32>,
+        front: Vector3<f32>,
+        profile: IesProfile,
+        color: Vector3<f32>,
+    ) -> Self {
+        IesLight {
+            position,
+            frame: Frame::from_normal_tangent(&down.normalize(), &front),
+            profile,
+            color,
+        }
+    }
+}
+
+impl Light for IesLight {
+    fn sample(&self, point: &Point3<f32>) -> Option<LightSample> {
+        let local = self.frame.to_local(&(point - self.position).normalize());
+        let vertical = local.z.clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = local.y.atan2(local.x).to_degrees();
+
+        let candela = self.profile.candela(vertical, horizontal);
+        if candela <= 0.0 {
+            return None;
+        }
+        point_sample(
+            point,
+            &self.position,
+            self.color * (candela / LUMENS_PER_WATT),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILE: &str = "IESNA:LM-63-2002
+[TEST] two planes
+TILT=NONE
+1 1000 2 3 2 1 2 0 0 0
+1 1 10
+0 45 90
+0 90
+100 50 0
+200 100 0
+";
+
+    #[test]
+    fn point_light_falls_off_with_the_square_of_distance() {
+        let light = PointLight {
+            position: Point3::new(0.0, 2.0, 0.0),
+            intensity: vec3(4.0, 4.0, 4.0),
+        };
+        let sample = light.sample(&Point3::new(0.0, 0.0, 0.0)).unwrap();
+        assert!((sample.direction - vec3(0.0, 1.0, 0.0)).magnitude() < 1e-6);
+        assert!((sample.distance - 2.0).abs() < 1e-6);
+        assert!((sample.radiance.x - 1.0).abs() < 1e-6);
+        assert!(light.sample(&light.position).is_none());
+    }
+
+    #[test]
+    fn spot_light_fades_between_its_angles() {
+        let light = SpotLight {
+            position: Point3::new(0.0, 1.0, 0.0),
+            direction: vec3(0.0, -1.0, 0.0),
+            intensity: vec3(1.0, 1.0, 1.0),
+            cone_angle: 30f32.to_radians(),
+            falloff_start: 20f32.to_radians(),
+        };
+        let at = |degrees: f32| {
+            let point = Point3::new(degrees.to_radians().tan(), 0.0, 0.0);
+            let distance2 = (point - light.position).magnitude2();
+            light
+                .sample(&point)
+                .map_or(0.0, |sample| sample.radiance.x * distance2)
+        };
+        assert!((at(10.0) - 1.0).abs() < 1e-5);
+        let middle = at(25.0);
+        assert!(middle > 0.0 && middle < 1.0);
+        assert!(at(35.0) == 0.0);
+    }
+
+    #[test]
+    fn ies_profile_interpolates_candelas() {
+        let profile = IesProfile::parse(PROFILE).unwrap();
+        // Candelas are scaled by the multiplier of 2.
+        assert!((profile.candela(0.0, 0.0) - 200.0).abs() < 1e-4);
+        assert!((profile.candela(22.5, 0.0) - 150.0).abs() < 1e-4);
+        assert!((profile.candela(0.0, 45.0) - 300.0).abs() < 1e-4);
+        // Quadrant symmetry mirrors the horizontal angle back into 0 to 90.
+        assert!((profile.candela(0.0, 135.0) - profile.candela(0.0, 45.0)).abs() < 1e-4);
+        assert!((profile.candela(0.0, 270.0) - 400.0).abs() < 1e-4);
+        assert_eq!(profile.candela(120.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn ies_profile_rejects_bad_files() {
+        assert!(IesProfile::parse("1 2 3").is_err());
+        assert!(IesProfile::parse(&PROFILE.replace("200 100 0\n", "")).is_err());
+        assert!(IesProfile::parse(&PROFILE.replace("0 45 90", "0 45 45")).is_err());
+        assert!(IesProfile::parse(&PROFILE.replace("0 90\n", "90 0\n")).is_err());
+        assert!(IesProfile::parse(&PROFILE.replace("TILT=NONE", "TILT=lamp.tlt")).is_err());
+    }
+
+    #[test]
+    fn ies_light_points_its_nadir_down() {
+        let light = IesLight::new(
+            Point3::new(0.0, 1.0, 0.0),
+            vec3(0.0, -1.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+            IesProfile::parse(PROFILE).unwrap(),
+            vec3(1.0, 1.0, 1.0),
+        );
+        let below = light.sample(&Point3::new(0.0, 0.0, 0.0)).unwrap();
+        assert!((below.radiance.x - 200.0 / LUMENS_PER_WATT).abs() < 1e-4);
+        assert!(light.sample(&Point3::new(0.0, 2.0, 0.0)).is_none());
+    }
+}
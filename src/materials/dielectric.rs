@@ -0,0 +1,182 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+
+use crate::materials::{
+    generate_reflect_probability, reflected_vector, refracted_vector, BsdfSample, Material,
+    Substrate, ThinFilm,
+};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+use crate::spectrum::Ior;
+use crate::textures::Texture;
+
+/// Beer-Lambert absorption inside a medium, given as the colour that remains after light
+/// travels `distance` units through it.
+#[derive(Clone, Copy)]
+pub struct Absorption {
+    pub color: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Absorption {
+    pub fn coefficient(&self) -> Vector3<f32> {
+        let channel = |c: f32| -c.clamp(1e-6, 1.0).ln() / self.distance.max(1e-6);
+        vec3(
+            channel(self.color.x),
+            channel(self.color.y),
+            channel(self.color.z),
+        )
+    }
+
+    pub fn transmittance(&self, length: f32) -> Vector3<f32> {
+        let sigma = self.coefficient() * length;
+        vec3((-sigma.x).exp(), (-sigma.y).exp(), (-sigma.z).exp())
+    }
+}
+
+/// Smooth glass. Clear by default, `absorption` colours it depending on how far light
+/// travels inside and `tint` filters light every time it refracts through the surface.
+/// A dispersive `ior` splits light into its colours in spectral mode and a `thin_film`
+/// coating makes the reflections iridescent.
+pub struct Dielectric {
+    pub ior: Ior,
+    pub absorption: Option<Absorption>,
+    pub tint: Option<Texture>,
+    pub thin_film: Option<ThinFilm>,
+}
+
+impl Dielectric {
+    pub const fn new(refractive_index: f32) -> Self {
+        Dielectric {
+            ior: Ior::Constant(refractive_index),
+            absorption: None,
+            tint: None,
+            thin_film: None,
+        }
+    }
+}
+
+impl Material for Dielectric {
+    fn sample(
+        &self,
+        ray: &Ray,
+        rec: &ShadeRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        // Only the hero wavelength can follow a dispersed refraction.
+        let (refractive_index, dispersed) = match ray.wavelengths {
+            Some(wavelengths) if self.ior.is_dispersive() => (self.ior.at(wavelengths.x), true),
+            _ => (self.ior.nominal(), false),
+        };
+        let reflected = reflected_vector(&ray.direction, &rec.normal);
+        let ni_over_t;
+        let outward_normal;
+        let refracted;
+        let mut reflect_prob;
+        let mut cosine;
+
+        let inside = ray.direction.dot(rec.normal) > 0.0;
+        if inside {
+            outward_normal = -rec.normal;
+            ni_over_t = refractive_index;
+            cosine = ray.direction.dot(rec.normal) / ray.direction.magnitude();
+            cosine = (1.0 - refractive_index * refractive_index * (1.0 - cosine * cosine)).sqrt();
+        } else {
+            outward_normal = rec.normal;
+            ni_over_t = 1.0 / refractive_index;
+            cosine = -ray.direction.dot(rec.normal) / ray.direction.magnitude();
+        }
+
+        if let Some(x) = refracted_vector(&ray.direction, &outward_normal, ni_over_t) {
+            reflect_prob = generate_reflect_probability(cosine, refractive_index);
+            refracted = x;
+        } else {
+            reflect_prob = 1.0;
+            refracted = vec3(1.0, 1.0, 1.0);
+        };
+
+        // The ray that got here travelled through the medium when it hits from the inside.
+        let mut weight = match (&self.absorption, inside) {
+            (Some(absorption), true) => {
+                absorption.transmittance(rec.intersect_parameter * ray.direction.magnitude())
+            }
+            _ => vec3(1.0, 1.0, 1.0),
+        };
+
+        // The film replaces the Schlick term with a coloured reflectance, sampled by its
+        // average and reweighted per channel.
+        let mut reflect_weight = vec3(1.0, 1.0, 1.0);
+        let mut refract_weight = vec3(1.0, 1.0, 1.0);
+        if let (Some(film), true) = (&self.thin_film, reflect_prob < 1.0) {
+            let cos_theta_i = ray.direction.dot(rec.normal).abs() / ray.direction.magnitude();
+            let (eta_i, substrate) = if inside {
+                (refractive_index, Substrate::Dielectric(1.0))
+            } else {
+                (1.0, Substrate::Dielectric(refractive_index))
+            };
+            let reflectance =
+                film.reflectance(&rec.texture_coords(), cos_theta_i, eta_i, &substrate);
+            reflect_prob =
+                ((reflectance.x + reflectance.y + reflectance.z) / 3.0).clamp(1e-4, 1.0 - 1e-4);
+            reflect_weight = reflectance / reflect_prob;
+            refract_weight = (vec3(1.0, 1.0, 1.0) - reflectance) / (1.0 - reflect_prob);
+        }
+
+        let direction = if sampler.get_1d() < reflect_prob {
+            weight = weight.mul_element_wise(reflect_weight);
+            reflected
+        } else {
+            weight = weight.mul_element_wise(refract_weight);
+            if let Some(tint) = &self.tint {
+                weight = weight.mul_element_wise(tint.value(&rec.texture_coords()).to_vec());
+            }
+            refracted
+        };
+
+        Some(BsdfSample {
+            direction,
+            weight,
+            pdf: 0.0,
+            specular: true,
+            terminate_secondary: dispersed,
+            origin: None,
+        })
+    }
+
+    fn albedo(&self, rec: &ShadeRecord) -> Vector3<f32> {
+        match &self.tint {
+            Some(tint) => tint.value(&rec.texture_coords()).to_vec(),
+            None => vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absorption_leaves_the_colour_at_its_distance() {
+        let absorption = Absorption {
+            color: vec3(0.8, 0.5, 0.1),
+            distance: 2.0,
+        };
+        let close = |a: Vector3<f32>, b: Vector3<f32>| (a - b).magnitude() < 1e-5;
+        assert!(close(absorption.transmittance(0.0), vec3(1.0, 1.0, 1.0)));
+        assert!(close(absorption.transmittance(2.0), vec3(0.8, 0.5, 0.1)));
+        assert!(close(absorption.transmittance(4.0), vec3(0.64, 0.25, 0.01)));
+    }
+
+    #[test]
+    fn absorption_coefficient_stays_finite() {
+        let absorption = Absorption {
+            color: vec3(0.0, 1.0, 2.0),
+            distance: 0.0,
+        };
+        let sigma = absorption.coefficient();
+        assert!(sigma.x.is_finite() && sigma.x > 0.0);
+        assert_eq!(sigma.y, 0.0);
+        assert_eq!(sigma.z, 0.0);
+    }
+}
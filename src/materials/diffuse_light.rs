@@ -0,0 +1,147 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+use std::f32::consts::PI;
+
+use crate::materials::{BsdfSample, Material};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+use crate::spectrum::{self, LUMENS_PER_WATT};
+use crate::textures::Texture;
+
+/// How the `strength` of a light is measured.
+#[derive(Clone, Copy)]
+pub enum EmissionUnits {
+    /// Strength scales the emitted radiance directly.
+    Radiance,
+    /// Total emitted power in watts, spread over the area of the object.
+    Watts,
+    /// Total emitted luminous power in lumens, spread over the area of the object.
+    Lumens,
+}
+
+/// Diffuse area light. The emitted colour is `texture`, times the colour of a black body at
+/// `temperature` kelvin if set, scaled by `strength` in the given `units`. One-sided lights
+/// only emit on the side their normal points to.
+pub struct DiffuseLight {
+    pub texture: Texture,
+    pub strength: f32,
+    pub units: EmissionUnits,
+    pub two_sided: bool,
+    pub temperature: Option<f32>,
+}
+
+impl DiffuseLight {
+    /// Light with radiance `texture`, emitting on both sides like all lights used to.
+    pub const fn new(texture: Texture) -> Self {
+        DiffuseLight {
+            texture,
+            strength: 1.0,
+            units: EmissionUnits::Radiance,
+            two_sided: true,
+            temperature: None,
+        }
+    }
+
+    /// Light with radiance `texture` only on the side its normal points to.
+    pub const fn one_sided(texture: Texture) -> Self {
+        DiffuseLight {
+            texture,
+            strength: 1.0,
+            units: EmissionUnits::Radiance,
+            two_sided: false,
+            temperature: None,
+        }
+    }
+
+    /// Factor turning `strength` into radiance for an emitter of `area`.
+    fn radiance_scale(&self, area: f32) -> f32 {
+        let sides = if self.two_sided { 2.0 } else { 1.0 };
+        // A diffuse emitter of radiance L sends out L * PI * area watts per side.
+        let power_to_radiance = 1.0 / (PI * area.max(1e-6) * sides);
+        match self.units {
+            EmissionUnits::Radiance => self.strength,
+            EmissionUnits::Watts => self.strength * power_to_radiance,
+            EmissionUnits::Lumens => self.strength * power_to_radiance / LUMENS_PER_WATT,
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn sample(
+        &self,
+        _ray: &Ray,
+        _rec: &ShadeRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        None
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &ShadeRecord) -> Vector3<f32> {
+        if !self.two_sided && ray.direction.dot(rec.normal) >= 0.0 {
+            return vec3(0.0, 0.0, 0.0);
+        }
+
+        let area = rec.object.map_or(1.0, |object| object.area());
+        let color = self.texture.value(&rec.texture_coords()).to_vec();
+        let color = match self.temperature {
+            Some(kelvin) => color.mul_element_wise(spectrum::blackbody(kelvin)),
+            None => color,
+        };
+        color * self.radiance_scale(area)
+    }
+
+    fn albedo(&self, rec: &ShadeRecord) -> Vector3<f32> {
+        self.texture.value(&rec.texture_coords()).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometric_objects::{GeometricObject, Rect};
+    use cgmath::Point3;
+
+    /// Radiance of the light reaching a camera at `eye` from the middle of a unit square
+    /// made of `light`, facing up.
+    fn seen_from(light: DiffuseLight, eye: Point3<f32>) -> Vector3<f32> {
+        let square = Rect::new(-0.5, 0.5, -0.5, 0.5, 0.0, light);
+        let ray = Ray::new(eye, Point3::new(0.0, 0.0, 0.0) - eye, 0.0);
+        let rec = square.hit(&ray, 1e-3, f32::MAX).unwrap();
+        rec.material.emitted(&ray, &rec)
+    }
+
+    #[test]
+    fn lights_are_two_sided_unless_asked() {
+        let above = Point3::new(0.0, 1.0, 0.0);
+        let below = Point3::new(0.0, -1.0, 0.0);
+        let white = || Texture::scalar(1.0);
+        assert_eq!(seen_from(DiffuseLight::new(white()), above).x, 1.0);
+        assert_eq!(seen_from(DiffuseLight::new(white()), below).x, 1.0);
+        assert_eq!(seen_from(DiffuseLight::one_sided(white()), above).x, 1.0);
+        assert_eq!(seen_from(DiffuseLight::one_sided(white()), below).x, 0.0);
+    }
+
+    #[test]
+    fn power_is_spread_over_area_and_sides() {
+        let above = Point3::new(0.0, 1.0, 0.0);
+        let watts = |two_sided| DiffuseLight {
+            strength: 10.0,
+            units: EmissionUnits::Watts,
+            two_sided,
+            ..DiffuseLight::new(Texture::scalar(1.0))
+        };
+        // A unit square emitting L sends out PI * L watts per side.
+        let one_side = seen_from(watts(false), above).x;
+        assert!((one_side - 10.0 / PI).abs() < 1e-5);
+        let both_sides = seen_from(watts(true), above).x;
+        assert!((both_sides - 0.5 * one_side).abs() < 1e-5);
+
+        let lumens = DiffuseLight {
+            units: EmissionUnits::Lumens,
+            strength: 10.0 * LUMENS_PER_WATT,
+            ..DiffuseLight::one_sided(Texture::scalar(1.0))
+        };
+        assert!((seen_from(lumens, above).x - one_side).abs() < 1e-5);
+    }
+}
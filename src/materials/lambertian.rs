@@ -0,0 +1,169 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+use std::f32;
+
+use crate::frame::Frame;
+use crate::materials::{cosine_sample_hemisphere, BsdfSample, Material};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+use crate::textures::Texture;
+
+/// Ideal diffuse reflector, sampled over the cosine weighted hemisphere.
+pub struct Lambertian {
+    pub texture: Texture,
+}
+
+impl Lambertian {
+    /// Shading normal on the side of the surface the ray arrives from.
+    fn facing_normal(ray: &Ray, rec: &ShadeRecord) -> Vector3<f32> {
+        if ray.direction.dot(rec.normal) > 0.0 {
+            -rec.normal
+        } else {
+            rec.normal
+        }
+    }
+}
+
+impl Material for Lambertian {
+    fn sample(
+        &self,
+        ray: &Ray,
+        rec: &ShadeRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let normal = Lambertian::facing_normal(ray, rec);
+        let (u1, u2) = sampler.get_2d();
+        let local = cosine_sample_hemisphere(u1, u2);
+
+        Some(BsdfSample {
+            direction: Frame::from_normal(&normal).to_world(&local),
+            weight: self.texture.value(&rec.texture_coords()).to_vec(),
+            pdf: local.z / f32::consts::PI,
+            specular: false,
+            terminate_secondary: false,
+            origin: None,
+        })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> Vector3<f32> {
+        let cosine = direction
+            .normalize()
+            .dot(Lambertian::facing_normal(ray, rec));
+        if cosine <= 0.0 {
+            return Vector3::zero();
+        }
+        self.texture.value(&rec.texture_coords()).to_vec() * (cosine / f32::consts::PI)
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> f32 {
+        let cosine = direction
+            .normalize()
+            .dot(Lambertian::facing_normal(ray, rec));
+        cosine.max(0.0) / f32::consts::PI
+    }
+
+    fn albedo(&self, rec: &ShadeRecord) -> Vector3<f32> {
+        self.texture.value(&rec.texture_coords()).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::{IndependentSampler, Sampler, SobolSampler, StratifiedSampler};
+    use cgmath::{vec3, Point3};
+
+    fn record(material: &dyn Material) -> ShadeRecord<'_> {
+        ShadeRecord {
+            normal: vec3(0.0, 1.0, 0.0),
+            tangent: vec3(1.0, 0.0, 0.0),
+            local_hit_point: Point3::new(0.0, 0.0, 0.0),
+            material,
+            object: None,
+            intersect_parameter: 1.0,
+            u: 0.0,
+            v: 0.0,
+            footprint: 0.0,
+            uv_footprint: 0.0,
+        }
+    }
+
+    #[test]
+    fn samples_match_eval_and_pdf() {
+        let material = Lambertian {
+            texture: Texture::scalar(0.5),
+        };
+        let rec = record(&material);
+        let ray = Ray::new(Point3::new(1.0, 1.0, 0.0), vec3(-1.0, -1.0, 0.0), 0.0);
+
+        let mut mean_cosine = 0.0;
+        for _ in 0..1000 {
+            let sample = material
+                .sample(&ray, &rec, &mut IndependentSampler)
+                .unwrap();
+            let cosine = sample.direction.normalize().y;
+            assert!(cosine >= 0.0);
+            assert!((sample.pdf - material.pdf(&ray, &rec, &sample.direction)).abs() < 1e-4);
+            let eval = material.eval(&ray, &rec, &sample.direction);
+            assert!((eval.x / sample.pdf - sample.weight.x).abs() < 1e-3);
+            mean_cosine += cosine / 1000.0;
+        }
+        // The mean cosine of a cosine weighted hemisphere is 2/3.
+        assert!((mean_cosine - 2.0 / 3.0).abs() < 0.03);
+    }
+
+    #[test]
+    fn reflects_on_the_side_the_ray_arrives_from() {
+        let material = Lambertian {
+            texture: Texture::scalar(0.5),
+        };
+        let rec = record(&material);
+        let from_below = Ray::new(Point3::new(0.0, -1.0, 0.0), vec3(0.0, 1.0, 0.0), 0.0);
+        let sample = material
+            .sample(&from_below, &rec, &mut IndependentSampler)
+            .unwrap();
+        assert!(sample.direction.y <= 0.0);
+        assert_eq!(material.pdf(&from_below, &rec, &vec3(0.0, 1.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn bounce_directions_are_stratified() {
+        let material = Lambertian {
+            texture: Texture::scalar(0.5),
+        };
+        let rec = record(&material);
+        let ray = Ray::new(Point3::new(0.0, 1.0, 0.0), vec3(0.0, -1.0, 0.0), 0.0);
+        let frame = Frame::from_normal(&rec.normal);
+
+        let samplers: Vec<Box<dyn Sampler>> = vec![
+            Box::new(StratifiedSampler::new(16)),
+            Box::new(SobolSampler::default()),
+        ];
+        for mut sampler in samplers {
+            let mut cells = vec![0; 16];
+            for index in 0..16 {
+                sampler.start_sample(7, 2, index);
+                // Pixel, lens, time, wavelength and background dimensions come first.
+                sampler.get_2d();
+                sampler.get_2d();
+                sampler.get_1d();
+                sampler.get_1d();
+                sampler.get_2d();
+                let sample = material.sample(&ray, &rec, sampler.as_mut()).unwrap();
+
+                // Undo the cosine weighted mapping to get the pair the material drew.
+                let local = frame.to_local(&sample.direction);
+                let u1 = 1.0 - local.z * local.z;
+                let phi = local.y.atan2(local.x);
+                let u2 = if phi < 0.0 {
+                    phi + 2.0 * f32::consts::PI
+                } else {
+                    phi
+                } / (2.0 * f32::consts::PI);
+                cells[(u1 * 4.0) as usize * 4 + (u2 * 4.0) as usize] += 1;
+            }
+            assert!(cells.iter().all(|&count| count == 1), "{:?}", cells);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+
+use crate::frame::Frame;
+use crate::materials::microfacet::{fresnel_dielectric, reflect, MicrofacetDistribution};
+use crate::materials::{Absorption, BsdfSample, Material};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+
+/// Dielectric coating over any other material, for car paint, lacquered wood and the like.
+///
+/// The coating reflects its Fresnel share of the light, the base only sees what gets through
+/// on the way in and out, optionally tinted by `absorption` over a coating `thickness`.
+/// Directions are not bent by the coating, the base is evaluated as if it were bare.
+pub struct Layered {
+    pub base: Box<dyn Material>,
+    pub ior: f32,
+    /// Microfacet distribution of the coating, `None` for a perfectly smooth one.
+    pub distribution: Option<MicrofacetDistribution>,
+    pub absorption: Option<Absorption>,
+    pub thickness: f32,
+}
+
+impl Layered {
+    /// Smooth, clear coating with index of refraction `ior` over `base`.
+    pub fn new<M: Material + 'static>(base: M, ior: f32) -> Self {
+        Layered {
+            base: Box::new(base),
+            ior,
+            distribution: None,
+            absorption: None,
+            thickness: 0.0,
+        }
+    }
+
+    fn frame(rec: &ShadeRecord, wo: &Vector3<f32>) -> Frame {
+        // The coating is seen through its flipped normal from behind.
+        let normal = if wo.dot(rec.normal) < 0.0 {
+            -rec.normal
+        } else {
+            rec.normal
+        };
+        Frame::from_normal_tangent(&normal, &rec.tangent)
+    }
+
+    /// Fraction of the light crossing the coating at a local cosine of `cos_theta`.
+    fn coating_transmittance(&self, cos_theta: f32) -> Vector3<f32> {
+        let cos_theta = cos_theta.abs();
+        let fresnel = 1.0 - fresnel_dielectric(cos_theta, self.ior);
+
+        let absorbed = match &self.absorption {
+            Some(absorption) => {
+                // Path length through the coating along the refracted direction.
+                let cos_refracted = (1.0 - (1.0 - cos_theta * cos_theta) / (self.ior * self.ior))
+                    .max(1e-4)
+                    .sqrt();
+                absorption.transmittance(self.thickness / cos_refracted)
+            }
+            None => vec3(1.0, 1.0, 1.0),
+        };
+        absorbed * fresnel
+    }
+
+    /// Fraction of the light reaching the base along `wo` that leaves along `wi`, both
+    /// given by their local cosines.
+    fn base_transmittance(&self, cos_o: f32, cos_i: f32) -> Vector3<f32> {
+        self.coating_transmittance(cos_o)
+            .mul_element_wise(self.coating_transmittance(cos_i))
+    }
+
+    fn coating_eval(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        match &self.distribution {
+            Some(distribution) if wo.z > 0.0 && wi.z > 0.0 => {
+                let wm = (wo + wi).normalize();
+                fresnel_dielectric(wo.dot(wm), self.ior)
+                    * distribution.d(&wm)
+                    * distribution.g(wo, wi)
+                    / (4.0 * wo.z)
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn coating_pdf(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        match &self.distribution {
+            Some(distribution) if wo.z > 0.0 && wi.z > 0.0 => {
+                let wm = (wo + wi).normalize();
+                distribution.d_visible(wo, &wm) / (4.0 * wo.dot(wm).abs())
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Probability of sampling the coating rather than the base.
+    fn coating_probability(&self, wo: &Vector3<f32>) -> f32 {
+        fresnel_dielectric(wo.z.abs(), self.ior).clamp(0.0, 1.0)
+    }
+}
+
+impl Material for Layered {
+    fn eval(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> Vector3<f32> {
+        let wo = -ray.direction.normalize();
+        let frame = Layered::frame(rec, &wo);
+        let (wo_local, wi_local) = (frame.to_local(&wo), frame.to_local(&direction.normalize()));
+
+        let coating = self.coating_eval(&wo_local, &wi_local);
+        vec3(coating, coating, coating)
+            + self
+                .base
+                .eval(ray, rec, direction)
+                .mul_element_wise(self.base_transmittance(wo_local.z, wi_local.z))
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> f32 {
+        let wo = -ray.direction.normalize();
+        let frame = Layered::frame(rec, &wo);
+        let (wo_local, wi_local) = (frame.to_local(&wo), frame.to_local(&direction.normalize()));
+        let probability = self.coating_probability(&wo_local);
+
+        probability * self.coating_pdf(&wo_local, &wi_local)
+            + (1.0 - probability) * self.base.pdf(ray, rec, direction)
+    }
+
+    fn sample(
+        &self,
+        ray: &Ray,
+        rec: &ShadeRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let wo = -ray.direction.normalize();
+        let frame = Layered::frame(rec, &wo);
+        let wo_local = frame.to_local(&wo);
+        let probability = self.coating_probability(&wo_local);
+
+        let direction = if sampler.get_1d() < probability {
+            match &self.distribution {
+                Some(distribution) => {
+                    let (u1, u2) = sampler.get_2d();
+                    let wm = distribution.sample_wm(&wo_local, u1, u2);
+                    frame.to_world(&reflect(&wo_local, &wm))
+                }
+                None => {
+                    // The mirror reflection carries exactly the Fresnel share it was picked with.
+                    return Some(BsdfSample {
+                        direction: frame.to_world(&reflect(&wo_local, &vec3(0.0, 0.0, 1.0))),
+                        weight: vec3(1.0, 1.0, 1.0),
+                        pdf: 0.0,
+                        specular: true,
+                        terminate_secondary: false,
+                        origin: None,
+                    });
+                }
+            }
+        } else {
+            let sample = self.base.sample(ray, rec, sampler)?;
+            if sample.specular {
+                let wi_local = frame.to_local(&sample.direction.normalize());
+                let transmittance = self.base_transmittance(wo_local.z, wi_local.z);
+                return Some(BsdfSample {
+                    weight: sample.weight.mul_element_wise(transmittance) / (1.0 - probability),
+                    ..sample
+                });
+            }
+            sample.direction
+        };
+
+        // Both lobes can be evaluated, weight by their combined density.
+        let pdf = self.pdf(ray, rec, &direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            direction,
+            weight: self.eval(ray, rec, &direction) / pdf,
+            pdf,
+            specular: false,
+            terminate_secondary: false,
+            origin: None,
+        })
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &ShadeRecord) -> Vector3<f32> {
+        let wo = -ray.direction.normalize();
+        self.base
+            .emitted(ray, rec)
+            .mul_element_wise(self.coating_transmittance(wo.dot(rec.normal)))
+    }
+
+    fn albedo(&self, rec: &ShadeRecord) -> Vector3<f32> {
+        self.base.albedo(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::Lambertian;
+    use crate::sampler::IndependentSampler;
+    use crate::textures::Texture;
+    use cgmath::Point3;
+
+    fn record(material: &dyn Material) -> ShadeRecord<'_> {
+        ShadeRecord {
+            normal: vec3(0.0, 0.0, 1.0),
+            tangent: vec3(1.0, 0.0, 0.0),
+            local_hit_point: Point3::new(0.0, 0.0, 0.0),
+            material,
+            object: None,
+            intersect_parameter: 1.0,
+            u: 0.0,
+            v: 0.0,
+            footprint: 0.0,
+            uv_footprint: 0.0,
+        }
+    }
+
+    fn lambertian() -> Lambertian {
+        Lambertian {
+            texture: Texture::scalar(0.5),
+        }
+    }
+
+    #[test]
+    fn base_is_seen_through_the_coating_twice() {
+        let layered = Layered::new(lambertian(), 1.5);
+        let bare = lambertian();
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), vec3(0.0, 0.0, -1.0), 0.0);
+        let up = vec3(0.0, 0.0, 1.0);
+
+        let coated = layered.eval(&ray, &record(&layered), &up).x;
+        let base = bare.eval(&ray, &record(&bare), &up).x;
+        assert!((coated - base * 0.96 * 0.96).abs() < 1e-5);
+    }
+
+    #[test]
+    fn absorption_darkens_the_base() {
+        let clear = Layered::new(lambertian(), 1.5);
+        let tinted = Layered {
+            absorption: Some(Absorption {
+                color: vec3(1.0, 0.5, 0.5),
+                distance: 1.0,
+            }),
+            thickness: 0.5,
+            ..Layered::new(lambertian(), 1.5)
+        };
+        let through_clear = clear.base_transmittance(1.0, 1.0);
+        let through_tinted = tinted.base_transmittance(1.0, 1.0);
+        assert!((through_tinted.x - through_clear.x).abs() < 1e-6);
+        // Half a unit in and half a unit out.
+        assert!((through_tinted.y - 0.5 * through_clear.y).abs() < 1e-5);
+    }
+
+    #[test]
+    fn smooth_coating_reflects_like_a_mirror() {
+        let layered = Layered::new(lambertian(), 1.5);
+        let rec = record(&layered);
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 1.0), vec3(1.0, 0.0, -1.0), 0.0);
+
+        let mut mirrored = 0;
+        for _ in 0..2000 {
+            let sample = layered.sample(&ray, &rec, &mut IndependentSampler).unwrap();
+            if sample.specular {
+                mirrored += 1;
+                let direction = sample.direction.normalize();
+                assert!((direction - vec3(1.0, 0.0, 1.0).normalize()).magnitude() < 1e-5);
+            } else {
+                assert!(sample.direction.z > 0.0);
+            }
+        }
+        // Picked with the Fresnel reflectance at 45 degrees, about 5%.
+        let expected = fresnel_dielectric(0.5_f32.sqrt(), 1.5) * 2000.0;
+        assert!((mirrored as f32 - expected).abs() < 40.0);
+    }
+}
@@ -0,0 +1,230 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+
+use crate::materials::{
+    reflected_vector, vec_in_unit_sphere, BsdfSample, Material, Substrate, ThinFilm,
+};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+use crate::textures::Texture;
+
+/// Exact Fresnel reflectance of a conductor with complex index of refraction `eta + i k`,
+/// seen from a dielectric with an index of refraction of one.
+pub fn fresnel_conductor(cos_theta_i: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_theta_i * cos_theta_i;
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+/// Complex index of refraction of a conductor at red, green and blue wavelengths.
+#[derive(Clone, Copy)]
+pub struct ConductorIor {
+    pub eta: Vector3<f32>,
+    pub k: Vector3<f32>,
+}
+
+impl ConductorIor {
+    pub const GOLD: ConductorIor = ConductorIor {
+        eta: vec3(0.183, 0.421, 1.373),
+        k: vec3(3.424, 2.346, 1.770),
+    };
+    pub const COPPER: ConductorIor = ConductorIor {
+        eta: vec3(0.271, 0.677, 1.316),
+        k: vec3(3.609, 2.625, 2.292),
+    };
+    pub const SILVER: ConductorIor = ConductorIor {
+        eta: vec3(0.159, 0.145, 0.135),
+        k: vec3(3.929, 3.190, 2.381),
+    };
+    pub const ALUMINIUM: ConductorIor = ConductorIor {
+        eta: vec3(1.657, 0.880, 0.521),
+        k: vec3(9.224, 6.270, 4.837),
+    };
+
+    /// Interpolates `eta` and `k` at `lambda` nanometers, taking the red, green and blue
+    /// values to be measured at 650, 550 and 450nm.
+    pub fn at(&self, lambda: f32) -> (f32, f32) {
+        let channel = |v: &Vector3<f32>| {
+            let t = ((lambda - 450.0) / 100.0).clamp(0.0, 2.0);
+            if t < 1.0 {
+                v.z + (v.y - v.z) * t
+            } else {
+                v.y + (v.x - v.y) * (t - 1.0)
+            }
+        };
+        (channel(&self.eta), channel(&self.k))
+    }
+
+    pub fn reflectance(&self, cos_theta_i: f32) -> Vector3<f32> {
+        let cos_theta_i = cos_theta_i.abs().min(1.0);
+        vec3(
+            fresnel_conductor(cos_theta_i, self.eta.x, self.k.x),
+            fresnel_conductor(cos_theta_i, self.eta.y, self.k.y),
+            fresnel_conductor(cos_theta_i, self.eta.z, self.k.z),
+        )
+    }
+}
+
+/// Mirror-like reflector. `roughness` fuzzes the reflected direction, zero gives a perfect
+/// mirror, and fuzzed directions that point into the surface are absorbed. Without an `ior`
+/// the reflectance is just `albedo`, otherwise `albedo` tints the conductor Fresnel term. A
+/// `thin_film` on top makes the reflectance iridescent.
+pub struct Metallic {
+    pub albedo: Texture,
+    pub roughness: f32,
+    pub ior: Option<ConductorIor>,
+    pub thin_film: Option<ThinFilm>,
+}
+
+/// Conductor reflectance shared by the smooth and rough metals.
+pub fn conductor_reflectance(
+    tint: Vector3<f32>,
+    ior: Option<ConductorIor>,
+    thin_film: &Option<ThinFilm>,
+    rec: &ShadeRecord,
+    cos_theta_i: f32,
+) -> Vector3<f32> {
+    match (thin_film, ior) {
+        (Some(film), Some(ior)) => tint.mul_element_wise(film.reflectance(
+            &rec.texture_coords(),
+            cos_theta_i,
+            1.0,
+            &Substrate::Conductor(ior),
+        )),
+        (Some(film), None) => film.reflectance(
+            &rec.texture_coords(),
+            cos_theta_i,
+            1.0,
+            &Substrate::Reflector(tint),
+        ),
+        (None, Some(ior)) => tint.mul_element_wise(ior.reflectance(cos_theta_i)),
+        (None, None) => tint,
+    }
+}
+
+impl Material for Metallic {
+    fn sample(
+        &self,
+        ray: &Ray,
+        rec: &ShadeRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let unit_direction = ray.direction.normalize();
+        let reflected = reflected_vector(&unit_direction, &rec.normal);
+        let (u1, u2) = sampler.get_2d();
+        let fuzz = vec_in_unit_sphere(u1, u2, sampler.get_1d());
+        let direction = reflected + self.roughness.clamp(0.0, 1.0) * fuzz;
+
+        // Fuzzed directions that end up below the surface are absorbed.
+        if direction.dot(rec.normal) <= 0.0 {
+            return None;
+        }
+
+        let tint = self.albedo.value(&rec.texture_coords()).to_vec();
+        let reflectance = conductor_reflectance(
+            tint,
+            self.ior,
+            &self.thin_film,
+            rec,
+            unit_direction.dot(rec.normal),
+        );
+
+        Some(BsdfSample {
+            direction,
+            weight: reflectance,
+            pdf: 0.0,
+            specular: true,
+            terminate_secondary: false,
+            origin: None,
+        })
+    }
+
+    fn albedo(&self, rec: &ShadeRecord) -> Vector3<f32> {
+        self.albedo.value(&rec.texture_coords()).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use cgmath::Point3;
+
+    fn record(material: &dyn Material) -> ShadeRecord<'_> {
+        ShadeRecord {
+            normal: vec3(0.0, 1.0, 0.0),
+            tangent: vec3(1.0, 0.0, 0.0),
+            local_hit_point: Point3::new(0.0, 0.0, 0.0),
+            material,
+            object: None,
+            intersect_parameter: 1.0,
+            u: 0.0,
+            v: 0.0,
+            footprint: 0.0,
+            uv_footprint: 0.0,
+        }
+    }
+
+    #[test]
+    fn fuzzed_reflections_below_the_surface_are_absorbed() {
+        let material = Metallic {
+            albedo: Texture::scalar(0.9),
+            roughness: 1.0,
+            ior: None,
+            thin_film: None,
+        };
+        let rec = record(&material);
+        // At grazing incidence much of the fuzz points into the surface.
+        let ray = Ray::new(Point3::new(-1.0, 0.05, 0.0), vec3(1.0, -0.05, 0.0), 0.0);
+
+        let samples: Vec<_> = (0..1000)
+            .filter_map(|_| material.sample(&ray, &rec, &mut IndependentSampler))
+            .collect();
+        assert!(samples.len() < 900);
+        for sample in samples {
+            assert!(sample.direction.y > 0.0);
+            assert_eq!(sample.weight, vec3(0.9, 0.9, 0.9));
+        }
+    }
+
+    #[test]
+    fn conductor_fresnel_at_normal_incidence() {
+        let (eta, k) = (0.183, 3.424);
+        let expected = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+        assert!((fresnel_conductor(1.0, eta, k) - expected).abs() < 1e-5);
+    }
+
+    #[test]
+    fn conductor_fresnel_goes_to_one_at_grazing_angles() {
+        let ior = ConductorIor::COPPER;
+        let reflectance = ior.reflectance(1e-4);
+        assert!(reflectance.x > 0.99 && reflectance.y > 0.99 && reflectance.z > 0.99);
+        // Copper is red: more red than blue is reflected head on.
+        let head_on = ior.reflectance(1.0);
+        assert!(head_on.x > head_on.z);
+    }
+
+    #[test]
+    fn ior_interpolates_between_the_channels() {
+        let ior = ConductorIor::GOLD;
+        assert_eq!(ior.at(650.0), (ior.eta.x, ior.k.x));
+        assert_eq!(ior.at(550.0), (ior.eta.y, ior.k.y));
+        assert_eq!(ior.at(300.0), (ior.eta.z, ior.k.z));
+        let (eta, _) = ior.at(600.0);
+        assert!((eta - 0.5 * (ior.eta.x + ior.eta.y)).abs() < 1e-6);
+    }
+}
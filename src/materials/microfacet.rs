@@ -0,0 +1,621 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+use std::f32;
+use std::f32::consts::PI;
+
+use crate::frame::Frame;
+use crate::materials::{conductor_reflectance, BsdfSample, ConductorIor, Material, ThinFilm};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+use crate::textures::Texture;
+
+// All directions below are in the local shading frame, with the normal along +z, and point
+// away from the surface.
+
+#[inline]
+fn tan2_theta(w: &Vector3<f32>) -> f32 {
+    (1.0 - w.z * w.z).max(0.0) / (w.z * w.z)
+}
+
+#[inline]
+pub fn same_hemisphere(a: &Vector3<f32>, b: &Vector3<f32>) -> bool {
+    a.z * b.z > 0.0
+}
+
+#[inline]
+pub fn reflect(wo: &Vector3<f32>, n: &Vector3<f32>) -> Vector3<f32> {
+    -*wo + n * (2.0 * wo.dot(*n))
+}
+
+/// Refracts `wi` through a surface with normal `n` and relative index of refraction `eta`
+/// (inside over outside). Returns the transmitted direction and the effective relative index.
+fn refract(wi: &Vector3<f32>, n: &Vector3<f32>, eta: f32) -> Option<(Vector3<f32>, f32)> {
+    let (mut n, mut eta) = (*n, eta);
+    let mut cos_theta_i = n.dot(*wi);
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+        n = -n;
+    }
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some((-*wi / eta + n * (cos_theta_i / eta - cos_theta_t), eta))
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface, `eta` is inside over outside.
+pub fn fresnel_dielectric(cos_theta_i: f32, eta: f32) -> f32 {
+    let (mut cos_theta_i, mut eta) = (cos_theta_i.clamp(-1.0, 1.0), eta);
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+/// Error function approximation from Abramowitz and Stegun 7.1.26.
+fn erf(x: f32) -> f32 {
+    let (a1, a2, a3, a4, a5, p) = (
+        0.254_829_6,
+        -0.284_496_74,
+        1.421_413_7,
+        -1.453_152_1,
+        1.061_405_4,
+        0.327_591_1,
+    );
+    let sign = 1.0_f32.copysign(x);
+    let x = x.abs();
+    let t = 1.0 / (1.0 + p * x);
+    let y = 1.0 - (((((a5 * t + a4) * t) + a3) * t + a2) * t + a1) * t * (-x * x).exp();
+    sign * y
+}
+
+/// Inverse error function, Giles' single precision approximation.
+fn erf_inv(x: f32) -> f32 {
+    let x = x.clamp(-0.99999, 0.99999);
+    let mut w = -((1.0 - x) * (1.0 + x)).ln();
+    let p = if w < 5.0 {
+        w -= 2.5;
+        let mut p = 2.810_226_4e-08;
+        p = 3.432_739_4e-07 + p * w;
+        p = -3.523_387_7e-06 + p * w;
+        p = -4.391_506_5e-06 + p * w;
+        p = 0.000_218_580_87 + p * w;
+        p = -0.001_253_725 + p * w;
+        p = -0.004_177_681_6 + p * w;
+        p = 0.246_640_73 + p * w;
+        1.501_409_4 + p * w
+    } else {
+        w = w.sqrt() - 3.0;
+        let mut p = -0.000_200_214_26;
+        p = 0.000_100_950_56 + p * w;
+        p = 0.001_349_343_2 + p * w;
+        p = -0.003_673_428_4 + p * w;
+        p = 0.005_739_507_7 + p * w;
+        p = -0.007_622_461_3 + p * w;
+        p = 0.009_438_870_5 + p * w;
+        p = 1.001_674 + p * w;
+        2.832_976_8 + p * w
+    };
+    p * x
+}
+
+/// Samples the slopes of the visible Beckmann distribution for unit roughness, following
+/// the approach of Jakob's "An Improved Visible Normal Sampling Routine for the Beckmann
+/// Distribution".
+fn beckmann_sample_unit_slopes(cos_theta_i: f32, u1: f32, u2: f32) -> (f32, f32) {
+    if cos_theta_i > 0.9999 {
+        let r = (-(1.0 - u1).ln()).sqrt();
+        let phi = 2.0 * PI * u2;
+        return (r * phi.cos(), r * phi.sin());
+    }
+
+    let sin_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0).sqrt();
+    let tan_theta_i = sin_theta_i / cos_theta_i;
+    let cot_theta_i = 1.0 / tan_theta_i;
+    let sqrt_pi_inv = 1.0 / PI.sqrt();
+
+    let mut a = -1.0;
+    let mut c = erf(cot_theta_i);
+    let sample_x = u1.max(1e-6);
+
+    // Initial guess from a polynomial fit of the inverse CDF.
+    let theta_i = cos_theta_i.acos();
+    let fit = 1.0 + theta_i * (-0.876 + theta_i * (0.4265 - 0.0594 * theta_i));
+    let mut b = c - (1.0 + c) * (1.0 - sample_x).powf(fit);
+
+    let normalization =
+        1.0 / (1.0 + c + sqrt_pi_inv * tan_theta_i * (-cot_theta_i * cot_theta_i).exp());
+
+    // Bisection guarded Newton iterations.
+    for _ in 0..10 {
+        if !(b >= a && b <= c) {
+            b = 0.5 * (a + c);
+        }
+        let inv_erf = erf_inv(b);
+        let value = normalization
+            * (1.0 + b + sqrt_pi_inv * tan_theta_i * (-inv_erf * inv_erf).exp())
+            - sample_x;
+        let derivative = normalization * (1.0 - inv_erf * tan_theta_i);
+
+        if value.abs() < 1e-5 {
+            break;
+        }
+        if value > 0.0 {
+            c = b;
+        } else {
+            a = b;
+        }
+        b -= value / derivative;
+    }
+
+    (erf_inv(b), erf_inv(2.0 * u2.max(1e-6) - 1.0))
+}
+
+#[derive(Clone, Copy)]
+pub enum Distribution {
+    Ggx,
+    Beckmann,
+}
+
+/// Anisotropic microfacet normal distribution with Smith masking-shadowing.
+/// `alpha_x` and `alpha_y` are the roughnesses along the tangent and bitangent.
+#[derive(Clone, Copy)]
+pub struct MicrofacetDistribution {
+    pub kind: Distribution,
+    pub alpha_x: f32,
+    pub alpha_y: f32,
+}
+
+impl MicrofacetDistribution {
+    pub fn new(kind: Distribution, alpha_x: f32, alpha_y: f32) -> Self {
+        MicrofacetDistribution {
+            kind,
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4),
+        }
+    }
+
+    /// Isotropic distribution from a perceptually linear roughness in [0, 1].
+    pub fn from_roughness(kind: Distribution, roughness: f32) -> Self {
+        let alpha = roughness * roughness;
+        MicrofacetDistribution::new(kind, alpha, alpha)
+    }
+
+    pub fn d(&self, wm: &Vector3<f32>) -> f32 {
+        if wm.z <= 0.0 {
+            return 0.0;
+        }
+        let (ax, ay) = (self.alpha_x, self.alpha_y);
+        let slope2 = (wm.x * wm.x) / (ax * ax) + (wm.y * wm.y) / (ay * ay);
+
+        match self.kind {
+            Distribution::Ggx => {
+                let denominator = slope2 + wm.z * wm.z;
+                1.0 / (PI * ax * ay * denominator * denominator)
+            }
+            Distribution::Beckmann => {
+                let cos4_theta = wm.z * wm.z * wm.z * wm.z;
+                (-slope2 / (wm.z * wm.z)).exp() / (PI * ax * ay * cos4_theta)
+            }
+        }
+    }
+
+    pub fn lambda(&self, w: &Vector3<f32>) -> f32 {
+        let tan2 = tan2_theta(w);
+        if !tan2.is_finite() {
+            return 0.0;
+        }
+        let sin2 = (w.x * w.x + w.y * w.y).max(1e-12);
+        let alpha2 = (w.x * w.x * self.alpha_x * self.alpha_x
+            + w.y * w.y * self.alpha_y * self.alpha_y)
+            / sin2;
+
+        match self.kind {
+            Distribution::Ggx => 0.5 * (-1.0 + (1.0 + alpha2 * tan2).sqrt()),
+            Distribution::Beckmann => {
+                let a = 1.0 / (alpha2 * tan2).sqrt();
+                if a >= 1.6 {
+                    0.0
+                } else {
+                    (1.0 - 1.259 * a + 0.396 * a * a) / (3.535 * a + 2.181 * a * a)
+                }
+            }
+        }
+    }
+
+    #[inline]
+    pub fn g1(&self, w: &Vector3<f32>) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated masking-shadowing.
+    #[inline]
+    pub fn g(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Distribution of normals visible from `w`.
+    pub fn d_visible(&self, w: &Vector3<f32>, wm: &Vector3<f32>) -> f32 {
+        self.g1(w) / w.z.abs() * self.d(wm) * w.dot(*wm).abs()
+    }
+
+    /// Samples a microfacet normal from the distribution of normals visible from `w`.
+    pub fn sample_wm(&self, w: &Vector3<f32>, u1: f32, u2: f32) -> Vector3<f32> {
+        let (ax, ay) = (self.alpha_x, self.alpha_y);
+        // Transform to the hemisphere configuration with unit roughness.
+        let mut wh = vec3(ax * w.x, ay * w.y, w.z).normalize();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+
+        match self.kind {
+            Distribution::Ggx => {
+                let t1 = if wh.z < 0.99999 {
+                    vec3(0.0, 0.0, 1.0).cross(wh).normalize()
+                } else {
+                    vec3(1.0, 0.0, 0.0)
+                };
+                let t2 = wh.cross(t1);
+
+                let r = u1.sqrt();
+                let phi = 2.0 * PI * u2;
+                let (px, py) = (r * phi.cos(), r * phi.sin());
+                let h = (1.0 - px * px).max(0.0).sqrt();
+                let py = (1.0 - (1.0 + wh.z) * 0.5) * h + (1.0 + wh.z) * 0.5 * py;
+                let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+
+                let nh = t1 * px + t2 * py + wh * pz;
+                vec3(ax * nh.x, ay * nh.y, nh.z.max(1e-6)).normalize()
+            }
+            Distribution::Beckmann => {
+                let (slope_x, slope_y) = beckmann_sample_unit_slopes(wh.z, u1, u2);
+
+                let phi = wh.y.atan2(wh.x);
+                let (sin_phi, cos_phi) = if wh.z < 0.99999 {
+                    phi.sin_cos()
+                } else {
+                    (0.0, 1.0)
+                };
+                let rotated_x = cos_phi * slope_x - sin_phi * slope_y;
+                let rotated_y = sin_phi * slope_x + cos_phi * slope_y;
+
+                vec3(-ax * rotated_x, -ay * rotated_y, 1.0).normalize()
+            }
+        }
+    }
+}
+
+/// Rough metal using the Cook-Torrance model. The Fresnel term follows
+/// `crate::materials::Metallic`: `albedo` tints the conductor reflectance, or is the
+/// reflectance itself without an `ior`, and an optional `thin_film` coats it.
+pub struct RoughConductor {
+    pub albedo: Texture,
+    pub ior: Option<ConductorIor>,
+    pub thin_film: Option<ThinFilm>,
+    pub distribution: MicrofacetDistribution,
+}
+
+impl RoughConductor {
+    fn frame(rec: &ShadeRecord, wo: &Vector3<f32>) -> Frame {
+        // Rays arriving from behind see the surface through its flipped normal.
+        let normal = if wo.dot(rec.normal) < 0.0 {
+            -rec.normal
+        } else {
+            rec.normal
+        };
+        Frame::from_normal_tangent(&normal, &rec.tangent)
+    }
+
+    fn fresnel(&self, rec: &ShadeRecord, cos_theta: f32) -> Vector3<f32> {
+        let tint = self.albedo.value(&rec.texture_coords()).to_vec();
+        conductor_reflectance(tint, self.ior, &self.thin_film, rec, cos_theta)
+    }
+
+    fn eval_local(&self, rec: &ShadeRecord, wo: &Vector3<f32>, wi: &Vector3<f32>) -> Vector3<f32> {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return vec3(0.0, 0.0, 0.0);
+        }
+        let wm = (wo + wi).normalize();
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(wo, wi);
+
+        self.fresnel(rec, wo.dot(wm)) * (d * g / (4.0 * wo.z))
+    }
+
+    fn pdf_local(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wm = (wo + wi).normalize();
+        self.distribution.d_visible(wo, &wm) / (4.0 * wo.dot(wm).abs())
+    }
+}
+
+impl Material for RoughConductor {
+    fn sample(
+        &self,
+        ray: &Ray,
+        rec: &ShadeRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let wo = -ray.direction.normalize();
+        let frame = RoughConductor::frame(rec, &wo);
+        let wo_local = frame.to_local(&wo);
+        if wo_local.z <= 0.0 {
+            return None;
+        }
+
+        let (u1, u2) = sampler.get_2d();
+        let wm = self.distribution.sample_wm(&wo_local, u1, u2);
+        let wi_local = reflect(&wo_local, &wm);
+        if wi_local.z <= 0.0 {
+            return None;
+        }
+
+        let pdf = self.pdf_local(&wo_local, &wi_local);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: frame.to_world(&wi_local),
+            weight: self.eval_local(rec, &wo_local, &wi_local) / pdf,
+            pdf,
+            specular: false,
+            terminate_secondary: false,
+            origin: None,
+        })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> Vector3<f32> {
+        let wo = -ray.direction.normalize();
+        let frame = RoughConductor::frame(rec, &wo);
+        self.eval_local(
+            rec,
+            &frame.to_local(&wo),
+            &frame.to_local(&direction.normalize()),
+        )
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> f32 {
+        let wo = -ray.direction.normalize();
+        let frame = RoughConductor::frame(rec, &wo);
+        self.pdf_local(
+            &frame.to_local(&wo),
+            &frame.to_local(&direction.normalize()),
+        )
+    }
+
+    fn albedo(&self, rec: &ShadeRecord) -> Vector3<f32> {
+        self.albedo.value(&rec.texture_coords()).to_vec()
+    }
+}
+
+/// Rough glass after Walter et al. 2007, "Microfacet Models for Refraction through Rough
+/// Surfaces". `ior` is the index of refraction on the side the normal points away from.
+pub struct RoughDielectric {
+    pub ior: f32,
+    pub distribution: MicrofacetDistribution,
+}
+
+impl RoughDielectric {
+    fn frame(rec: &ShadeRecord) -> Frame {
+        Frame::from_normal_tangent(&rec.normal, &rec.tangent)
+    }
+
+    /// Generalized half vector facing +z, `None` for degenerate configurations.
+    fn half_vector(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> Option<(Vector3<f32>, f32)> {
+        let reflect = same_hemisphere(wo, wi);
+        let etap = match (reflect, wo.z > 0.0) {
+            (true, _) => 1.0,
+            (false, true) => self.ior,
+            (false, false) => 1.0 / self.ior,
+        };
+
+        let wm = wi * etap + wo;
+        if wo.z == 0.0 || wi.z == 0.0 || wm.magnitude2() == 0.0 {
+            return None;
+        }
+        let wm = wm.normalize();
+        let wm = if wm.z < 0.0 { -wm } else { wm };
+
+        // Discard back-facing microfacets.
+        if wm.dot(*wi) * wi.z < 0.0 || wm.dot(*wo) * wo.z < 0.0 {
+            return None;
+        }
+        Some((wm, etap))
+    }
+
+    /// BSDF times the cosine of `wi` for directions in the local shading frame.
+    pub fn eval_local(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        let (wm, etap) = match self.half_vector(wo, wi) {
+            Some(half) => half,
+            None => return 0.0,
+        };
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(wo, wi);
+        let fresnel = fresnel_dielectric(wo.dot(wm), self.ior);
+
+        if same_hemisphere(wo, wi) {
+            d * g * fresnel / (4.0 * wo.z.abs())
+        } else {
+            let denominator = wi.dot(wm) + wo.dot(wm) / etap;
+            let denominator = denominator * denominator * wo.z;
+            // Radiance is compressed into the denser medium, hence the 1 / etap^2 factor.
+            d * (1.0 - fresnel) * g * (wi.dot(wm) * wo.dot(wm) / denominator).abs() / (etap * etap)
+        }
+    }
+
+    pub fn pdf_local(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        let (wm, etap) = match self.half_vector(wo, wi) {
+            Some(half) => half,
+            None => return 0.0,
+        };
+        let reflectance = fresnel_dielectric(wo.dot(wm), self.ior);
+        let visible = self.distribution.d_visible(wo, &wm);
+
+        if same_hemisphere(wo, wi) {
+            visible / (4.0 * wo.dot(wm).abs()) * reflectance
+        } else {
+            let denominator = wi.dot(wm) + wo.dot(wm) / etap;
+            let dwm_dwi = wi.dot(wm).abs() / (denominator * denominator);
+            visible * dwm_dwi * (1.0 - reflectance)
+        }
+    }
+
+    /// Samples a reflected or refracted direction in the local shading frame.
+    pub fn sample_local(
+        &self,
+        wo: &Vector3<f32>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Vector3<f32>> {
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        let (u1, u2) = sampler.get_2d();
+        let wm = self.distribution.sample_wm(wo, u1, u2);
+        let reflectance = fresnel_dielectric(wo.dot(wm), self.ior);
+
+        if sampler.get_1d() < reflectance {
+            Some(reflect(wo, &wm)).filter(|wi| same_hemisphere(wo, wi))
+        } else {
+            refract(wo, &wm, self.ior)
+                .map(|(wi, _)| wi)
+                .filter(|wi| !same_hemisphere(wo, wi) && wi.z != 0.0)
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn sample(
+        &self,
+        ray: &Ray,
+        rec: &ShadeRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let frame = RoughDielectric::frame(rec);
+        let wo_local = frame.to_local(&-ray.direction.normalize());
+        let wi_local = self.sample_local(&wo_local, sampler)?;
+
+        let pdf = self.pdf_local(&wo_local, &wi_local);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let weight = self.eval_local(&wo_local, &wi_local) / pdf;
+
+        Some(BsdfSample {
+            direction: frame.to_world(&wi_local),
+            weight: vec3(weight, weight, weight),
+            pdf,
+            specular: false,
+            terminate_secondary: false,
+            origin: None,
+        })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> Vector3<f32> {
+        let frame = RoughDielectric::frame(rec);
+        let value = self.eval_local(
+            &frame.to_local(&-ray.direction.normalize()),
+            &frame.to_local(&direction.normalize()),
+        );
+        vec3(value, value, value)
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> f32 {
+        let frame = RoughDielectric::frame(rec);
+        self.pdf_local(
+            &frame.to_local(&-ray.direction.normalize()),
+            &frame.to_local(&direction.normalize()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Integrates `f` over the upper hemisphere with the midpoint rule.
+    fn integrate_hemisphere(f: impl Fn(&Vector3<f32>) -> f32) -> f32 {
+        let (steps_theta, steps_phi) = (400, 64);
+        let (d_theta, d_phi) = (0.5 * PI / steps_theta as f32, 2.0 * PI / steps_phi as f32);
+        let mut sum = 0.0;
+        for i in 0..steps_theta {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..steps_phi {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let w = vec3(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                sum += f(&w) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    fn distributions() -> Vec<MicrofacetDistribution> {
+        vec![
+            MicrofacetDistribution::from_roughness(Distribution::Ggx, 0.5),
+            MicrofacetDistribution::from_roughness(Distribution::Beckmann, 0.5),
+            MicrofacetDistribution::new(Distribution::Ggx, 0.2, 0.4),
+        ]
+    }
+
+    #[test]
+    fn projected_normals_cover_unit_area() {
+        for distribution in distributions() {
+            let area = integrate_hemisphere(|wm| distribution.d(wm) * wm.z);
+            assert!((area - 1.0).abs() < 0.02, "projected area {}", area);
+        }
+    }
+
+    #[test]
+    fn visible_normals_integrate_to_one() {
+        let wo = vec3(0.6, 0.0, 0.8);
+        for distribution in distributions() {
+            let total = integrate_hemisphere(|wm| distribution.d_visible(&wo, wm));
+            assert!((total - 1.0).abs() < 0.03, "visible normals {}", total);
+        }
+    }
+
+    #[test]
+    fn sampled_normals_face_the_viewer() {
+        let wo = vec3(0.0, 0.6, 0.8);
+        for distribution in distributions() {
+            for i in 0..16 {
+                for j in 0..16 {
+                    let (u1, u2) = ((i as f32 + 0.5) / 16.0, (j as f32 + 0.5) / 16.0);
+                    let wm = distribution.sample_wm(&wo, u1, u2);
+                    assert!((wm.magnitude() - 1.0).abs() < 1e-4);
+                    assert!(wm.z > 0.0 && wm.dot(wo) > 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dielectric_fresnel_limits() {
+        let r0 = (0.5_f32 / 2.5).powi(2);
+        assert!((fresnel_dielectric(1.0, 1.5) - r0).abs() < 1e-6);
+        // Seen from the inside, past the critical angle all light is reflected.
+        assert_eq!(fresnel_dielectric(-0.2, 1.5), 1.0);
+        assert!(fresnel_dielectric(1e-4, 1.5) > 0.99);
+    }
+}
@@ -0,0 +1,146 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+use std::f32;
+use std::rc::Rc;
+
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+
+mod dielectric;
+mod diffuse_light;
+mod lambertian;
+mod layered;
+mod metallic;
+pub mod microfacet;
+mod principled;
+mod shadow_catcher;
+mod subsurface;
+mod thin_film;
+
+pub use self::dielectric::{Absorption, Dielectric};
+pub use self::diffuse_light::{DiffuseLight, EmissionUnits};
+pub use self::lambertian::Lambertian;
+pub use self::layered::Layered;
+pub use self::metallic::{conductor_reflectance, fresnel_conductor, ConductorIor, Metallic};
+pub use self::microfacet::{RoughConductor, RoughDielectric};
+pub use self::principled::Principled;
+pub use self::shadow_catcher::ShadowCatcher;
+pub use self::subsurface::Subsurface;
+pub use self::thin_film::{Substrate, ThinFilm};
+
+#[inline]
+pub fn reflected_vector(v: &Vector3<f32>, n: &Vector3<f32>) -> Vector3<f32> {
+    v - n * 2.0 * v.dot(*n)
+}
+
+#[inline]
+pub fn generate_reflect_probability(cosine: f32, refractive_index: f32) -> f32 {
+    let mut r0 = (1.0 - refractive_index) / (1.0 + refractive_index);
+    r0 = r0 * r0;
+    r0 + ((1.0 - r0) * ((1.0 - cosine).powf(5.0)))
+}
+
+pub fn refracted_vector(
+    v: &Vector3<f32>,
+    n: &Vector3<f32>,
+    ni_over_nt: f32,
+) -> Option<Vector3<f32>> {
+    let uv = v.normalize();
+    let dt = uv.dot(*n);
+    let discriminant = 1.0 - (ni_over_nt * ni_over_nt) * (1.0 - (dt * dt));
+    if discriminant > 0.0 {
+        let refracted = ni_over_nt * (uv - n * dt) - (n * (discriminant.sqrt()));
+        Some(refracted)
+    } else {
+        None
+    }
+}
+
+/// Point in the unit sphere at distance `u3` from the centre, in the direction picked by
+/// `u1` and `u2`.
+pub fn vec_in_unit_sphere(u1: f32, u2: f32, u3: f32) -> Vector3<f32> {
+    let z = 1.0 - (2.0 * u1);
+    let r = (1.0 - (z * z)).sqrt();
+    let theta = 2.0 * f32::consts::PI * u2;
+    let x = r * theta.cos();
+    let y = r * theta.sin();
+
+    u3 * vec3(x, y, z)
+}
+
+/// Cosine weighted direction around +z, with a pdf of `z / PI`.
+pub fn cosine_sample_hemisphere(u1: f32, u2: f32) -> Vector3<f32> {
+    let r = u1.sqrt();
+    let phi = 2.0 * f32::consts::PI * u2;
+    vec3(r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt())
+}
+
+/// Direction sampled from a BSDF with its throughput weight, the BSDF times the cosine
+/// term over `pdf`.
+pub struct BsdfSample {
+    pub direction: Vector3<f32>,
+    pub weight: Vector3<f32>,
+    pub pdf: f32,
+    /// Set for singular or otherwise unevaluable scattering. `pdf` is meaningless then and
+    /// the sample can not be combined with light sampling.
+    pub specular: bool,
+    /// Set in spectral mode when `direction` is only valid for the hero wavelength, as with
+    /// dispersion. The other wavelengths of the path are dropped.
+    pub terminate_secondary: bool,
+    /// Where the sampled ray starts when it is not the hit point, as for light leaving a
+    /// translucent object somewhere else.
+    pub origin: Option<Point3<f32>>,
+}
+
+/// Scattering and emission at a surface. Directions are in world space and point away from
+/// the hit point, the outgoing direction is always `-ray.direction`.
+pub trait Material {
+    /// Samples an incoming direction with numbers drawn from `sampler`, `None` when the path
+    /// is absorbed.
+    fn sample(&self, ray: &Ray, rec: &ShadeRecord, sampler: &mut dyn Sampler) -> Option<BsdfSample>;
+
+    /// BSDF times the cosine of `direction`. Zero for purely specular materials.
+    fn eval(&self, _ray: &Ray, _rec: &ShadeRecord, _direction: &Vector3<f32>) -> Vector3<f32> {
+        vec3(0.0, 0.0, 0.0)
+    }
+
+    /// Solid angle density with which `sample` picks `direction`.
+    fn pdf(&self, _ray: &Ray, _rec: &ShadeRecord, _direction: &Vector3<f32>) -> f32 {
+        0.0
+    }
+
+    /// Radiance emitted towards `-ray.direction`.
+    fn emitted(&self, _ray: &Ray, _rec: &ShadeRecord) -> Vector3<f32> {
+        vec3(0.0, 0.0, 0.0)
+    }
+
+    /// Overall colour of the surface, as a guide for denoising and for the albedo pass.
+    /// Clear materials like glass are white.
+    fn albedo(&self, _rec: &ShadeRecord) -> Vector3<f32> {
+        vec3(1.0, 1.0, 1.0)
+    }
+
+    /// Whether camera rays only see the shadows on this surface, see `ShadowCatcher`.
+    fn is_shadow_catcher(&self) -> bool {
+        false
+    }
+}
+
+/// Material an object can be built from: a material of its own, or an `Rc` of one that
+/// several objects share.
+pub trait IntoMaterial {
+    fn into_material(self) -> Rc<dyn Material>;
+}
+
+impl<M: Material + 'static> IntoMaterial for M {
+    fn into_material(self) -> Rc<dyn Material> {
+        Rc::new(self)
+    }
+}
+
+impl IntoMaterial for Rc<dyn Material> {
+    fn into_material(self) -> Rc<dyn Material> {
+        self
+    }
+}
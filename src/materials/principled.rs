@@ -0,0 +1,433 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+use std::f32;
+use std::f32::consts::PI;
+
+use crate::frame::Frame;
+use crate::materials::microfacet::{
+    reflect, same_hemisphere, Distribution, MicrofacetDistribution, RoughDielectric,
+};
+use crate::materials::{cosine_sample_hemisphere, BsdfSample, Material};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+use crate::textures::Texture;
+
+/// Principled "uber" material following the glTF and Blender parameterization, mixing a
+/// Burley diffuse lobe with sheen, a GGX specular lobe, a clearcoat and rough glass
+/// transmission. Scalar parameters read the first channel of their texture.
+pub struct Principled {
+    pub base_color: Texture,
+    pub metallic: Texture,
+    pub roughness: Texture,
+    /// Dielectric specular amount, 0.5 corresponds to a reflectance of 4%.
+    pub specular: Texture,
+    pub specular_tint: Texture,
+    pub sheen: Texture,
+    pub sheen_tint: Texture,
+    pub clearcoat: Texture,
+    pub clearcoat_roughness: Texture,
+    pub transmission: Texture,
+    pub ior: Texture,
+    pub emission: Texture,
+    pub emission_strength: Texture,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Principled {
+            base_color: Texture::scalar(0.8),
+            metallic: Texture::scalar(0.0),
+            roughness: Texture::scalar(0.5),
+            specular: Texture::scalar(0.5),
+            specular_tint: Texture::scalar(0.0),
+            sheen: Texture::scalar(0.0),
+            sheen_tint: Texture::scalar(0.5),
+            clearcoat: Texture::scalar(0.0),
+            clearcoat_roughness: Texture::scalar(0.03),
+            transmission: Texture::scalar(0.0),
+            ior: Texture::scalar(1.45),
+            emission: Texture::scalar(0.0),
+            emission_strength: Texture::scalar(1.0),
+        }
+    }
+}
+
+/// Material parameters evaluated at a single hit point.
+struct Lobes {
+    base_color: Vector3<f32>,
+    roughness: f32,
+    sheen: Vector3<f32>,
+    specular_f0: Vector3<f32>,
+    clearcoat: f32,
+    diffuse_weight: f32,
+    specular_weight: f32,
+    transmission_weight: f32,
+    specular: MicrofacetDistribution,
+    clearcoat_distribution: MicrofacetDistribution,
+    glass: RoughDielectric,
+}
+
+#[inline]
+fn luminance(color: &Vector3<f32>) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+#[inline]
+fn schlick_weight(cos_theta: f32) -> f32 {
+    (1.0 - cos_theta.abs().min(1.0)).powi(5)
+}
+
+#[inline]
+fn lerp(a: Vector3<f32>, b: Vector3<f32>, t: f32) -> Vector3<f32> {
+    a + (b - a) * t
+}
+
+impl Principled {
+    fn lobes(&self, rec: &ShadeRecord) -> Lobes {
+        let coords = rec.texture_coords();
+        let scalar = |texture: &Texture| texture.value(&coords).x;
+
+        let base_color = self.base_color.value(&coords).to_vec();
+        let metallic = scalar(&self.metallic).clamp(0.0, 1.0);
+        let roughness = scalar(&self.roughness).clamp(0.0, 1.0);
+        let transmission = scalar(&self.transmission).clamp(0.0, 1.0);
+        let clearcoat_roughness = scalar(&self.clearcoat_roughness).clamp(0.0, 1.0);
+
+        let base_luminance = luminance(&base_color);
+        let tint = if base_luminance > 0.0 {
+            base_color / base_luminance
+        } else {
+            vec3(1.0, 1.0, 1.0)
+        };
+        let white = vec3(1.0, 1.0, 1.0);
+
+        let dielectric_f0 = lerp(white, tint, scalar(&self.specular_tint))
+            * (0.08 * scalar(&self.specular).max(0.0));
+
+        Lobes {
+            base_color,
+            roughness,
+            sheen: lerp(white, tint, scalar(&self.sheen_tint)) * scalar(&self.sheen).max(0.0),
+            specular_f0: lerp(dielectric_f0, base_color, metallic),
+            clearcoat: 0.25 * scalar(&self.clearcoat).max(0.0),
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            specular_weight: 1.0 - (1.0 - metallic) * transmission,
+            transmission_weight: (1.0 - metallic) * transmission,
+            specular: MicrofacetDistribution::from_roughness(Distribution::Ggx, roughness),
+            clearcoat_distribution: MicrofacetDistribution::from_roughness(
+                Distribution::Ggx,
+                clearcoat_roughness,
+            ),
+            glass: RoughDielectric {
+                ior: scalar(&self.ior).max(1.0 + 1e-3),
+                distribution: MicrofacetDistribution::from_roughness(Distribution::Ggx, roughness),
+            },
+        }
+    }
+
+    /// Probabilities of sampling the diffuse, specular, clearcoat and transmission lobes.
+    fn lobe_probabilities(lobes: &Lobes, wo: &Vector3<f32>) -> [f32; 4] {
+        let fresnel = schlick_weight(wo.z);
+        let specular_f = lerp(lobes.specular_f0, vec3(1.0, 1.0, 1.0), fresnel);
+        let weights = [
+            lobes.diffuse_weight * luminance(&lobes.base_color).max(0.0),
+            lobes.specular_weight * luminance(&specular_f).max(0.0),
+            lobes.clearcoat * (0.04 + 0.96 * fresnel),
+            lobes.transmission_weight,
+        ];
+
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 {
+            return [0.0; 4];
+        }
+        [
+            weights[0] / total,
+            weights[1] / total,
+            weights[2] / total,
+            weights[3] / total,
+        ]
+    }
+
+    /// Frames for the reflection lobes, which always see `wo` from above, and for the
+    /// transmission lobe, which keeps the geometric orientation of the surface.
+    fn frames(rec: &ShadeRecord, wo: &Vector3<f32>) -> (Frame, Frame) {
+        let geometric = Frame::from_normal_tangent(&rec.normal, &rec.tangent);
+        if wo.dot(rec.normal) < 0.0 {
+            (
+                Frame::from_normal_tangent(&-rec.normal, &rec.tangent),
+                geometric,
+            )
+        } else {
+            (geometric, geometric)
+        }
+    }
+
+    fn eval_reflection(lobes: &Lobes, wo: &Vector3<f32>, wi: &Vector3<f32>) -> Vector3<f32> {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return vec3(0.0, 0.0, 0.0);
+        }
+        let wm = (wo + wi).normalize();
+        let cos_d = wi.dot(wm);
+
+        // Burley diffuse with retro-reflection at grazing angles, plus sheen.
+        let fd90 = 0.5 + 2.0 * lobes.roughness * cos_d * cos_d;
+        let diffuse = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+            * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z))
+            / PI;
+        let diffuse = (lobes.base_color * diffuse + lobes.sheen * schlick_weight(cos_d))
+            * lobes.diffuse_weight;
+
+        let fresnel = lerp(
+            lobes.specular_f0,
+            vec3(1.0, 1.0, 1.0),
+            schlick_weight(cos_d),
+        );
+        let specular = fresnel
+            * (lobes.specular.d(&wm) * lobes.specular.g(wo, wi) / (4.0 * wo.z * wi.z))
+            * lobes.specular_weight;
+
+        let clearcoat_fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+        let clearcoat = lobes.clearcoat
+            * clearcoat_fresnel
+            * lobes.clearcoat_distribution.d(&wm)
+            * lobes.clearcoat_distribution.g(wo, wi)
+            / (4.0 * wo.z * wi.z);
+
+        (diffuse + specular + vec3(clearcoat, clearcoat, clearcoat)) * wi.z
+    }
+
+    fn eval_transmission(lobes: &Lobes, wo: &Vector3<f32>, wi: &Vector3<f32>) -> Vector3<f32> {
+        if lobes.transmission_weight <= 0.0 {
+            return vec3(0.0, 0.0, 0.0);
+        }
+        let value = lobes.glass.eval_local(wo, wi) * lobes.transmission_weight;
+        if same_hemisphere(wo, wi) {
+            vec3(value, value, value)
+        } else {
+            lobes.base_color * value
+        }
+    }
+
+    fn pdf_local(
+        lobes: &Lobes,
+        probabilities: &[f32; 4],
+        (wo, wi): (&Vector3<f32>, &Vector3<f32>),
+        (wo_glass, wi_glass): (&Vector3<f32>, &Vector3<f32>),
+    ) -> f32 {
+        let mut pdf = probabilities[3] * lobes.glass.pdf_local(wo_glass, wi_glass);
+        if wo.z > 0.0 && wi.z > 0.0 {
+            let wm = (wo + wi).normalize();
+            let reflected_pdf = |distribution: &MicrofacetDistribution| {
+                distribution.d_visible(wo, &wm) / (4.0 * wo.dot(wm).abs())
+            };
+            pdf += probabilities[0] * wi.z / PI
+                + probabilities[1] * reflected_pdf(&lobes.specular)
+                + probabilities[2] * reflected_pdf(&lobes.clearcoat_distribution);
+        }
+        pdf
+    }
+}
+
+impl Material for Principled {
+    fn eval(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> Vector3<f32> {
+        let (wo, wi) = (-ray.direction.normalize(), direction.normalize());
+        let (wo, wi) = (&wo, &wi);
+        let lobes = self.lobes(rec);
+        let (frame, glass_frame) = Principled::frames(rec, wo);
+
+        Principled::eval_reflection(&lobes, &frame.to_local(wo), &frame.to_local(wi))
+            + Principled::eval_transmission(
+                &lobes,
+                &glass_frame.to_local(wo),
+                &glass_frame.to_local(wi),
+            )
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> f32 {
+        let (wo, wi) = (-ray.direction.normalize(), direction.normalize());
+        let (wo, wi) = (&wo, &wi);
+        let lobes = self.lobes(rec);
+        let (frame, glass_frame) = Principled::frames(rec, wo);
+        let wo_local = frame.to_local(wo);
+        let probabilities = Principled::lobe_probabilities(&lobes, &wo_local);
+
+        Principled::pdf_local(
+            &lobes,
+            &probabilities,
+            (&wo_local, &frame.to_local(wi)),
+            (&glass_frame.to_local(wo), &glass_frame.to_local(wi)),
+        )
+    }
+
+    fn sample(
+        &self,
+        ray: &Ray,
+        rec: &ShadeRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let wo = &-ray.direction.normalize();
+        let lobes = self.lobes(rec);
+        let (frame, glass_frame) = Principled::frames(rec, wo);
+        let wo_local = frame.to_local(wo);
+        let wo_glass = glass_frame.to_local(wo);
+        let probabilities = Principled::lobe_probabilities(&lobes, &wo_local);
+
+        // Pick a lobe to sample and weight by the combined pdf of all lobes.
+        let u = sampler.get_1d();
+        let direction = if u < probabilities[0] {
+            let (u1, u2) = sampler.get_2d();
+            frame.to_world(&cosine_sample_hemisphere(u1, u2))
+        } else if u < probabilities[0] + probabilities[1] {
+            let (u1, u2) = sampler.get_2d();
+            let wm = lobes.specular.sample_wm(&wo_local, u1, u2);
+            frame.to_world(&reflect(&wo_local, &wm))
+        } else if u < probabilities[0] + probabilities[1] + probabilities[2] {
+            let (u1, u2) = sampler.get_2d();
+            let wm = lobes.clearcoat_distribution.sample_wm(&wo_local, u1, u2);
+            frame.to_world(&reflect(&wo_local, &wm))
+        } else if probabilities[3] > 0.0 {
+            glass_frame.to_world(&lobes.glass.sample_local(&wo_glass, sampler)?)
+        } else {
+            return None;
+        };
+
+        let wi_local = frame.to_local(&direction);
+        let wi_glass = glass_frame.to_local(&direction);
+        let pdf = Principled::pdf_local(
+            &lobes,
+            &probabilities,
+            (&wo_local, &wi_local),
+            (&wo_glass, &wi_glass),
+        );
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        let value = Principled::eval_reflection(&lobes, &wo_local, &wi_local)
+            + Principled::eval_transmission(&lobes, &wo_glass, &wi_glass);
+
+        Some(BsdfSample {
+            direction,
+            weight: value / pdf,
+            pdf,
+            specular: false,
+            terminate_secondary: false,
+            origin: None,
+        })
+    }
+
+    fn emitted(&self, _ray: &Ray, rec: &ShadeRecord) -> Vector3<f32> {
+        let coords = rec.texture_coords();
+        self.emission.value(&coords).to_vec() * self.emission_strength.value(&coords).x
+    }
+
+    fn albedo(&self, rec: &ShadeRecord) -> Vector3<f32> {
+        self.base_color.value(&rec.texture_coords()).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Point3;
+
+    fn record(material: &dyn Material) -> ShadeRecord<'_> {
+        ShadeRecord {
+            normal: vec3(0.0, 0.0, 1.0),
+            tangent: vec3(1.0, 0.0, 0.0),
+            local_hit_point: Point3::new(0.0, 0.0, 0.0),
+            material,
+            object: None,
+            intersect_parameter: 1.0,
+            u: 0.0,
+            v: 0.0,
+            footprint: 0.0,
+            uv_footprint: 0.0,
+        }
+    }
+
+    /// Ray arriving at the origin from `wo`.
+    fn ray_from(wo: Vector3<f32>) -> Ray {
+        Ray::new(Point3::from_vec(wo), -wo, 0.0)
+    }
+
+    /// Integrates `f` over the sphere of directions with the midpoint rule.
+    fn integrate_sphere(f: impl Fn(&Vector3<f32>) -> Vector3<f32>) -> Vector3<f32> {
+        let (steps_theta, steps_phi) = (400, 64);
+        let (d_theta, d_phi) = (PI / steps_theta as f32, 2.0 * PI / steps_phi as f32);
+        let mut sum = vec3(0.0, 0.0, 0.0);
+        for i in 0..steps_theta {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..steps_phi {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let w = vec3(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                sum += f(&w) * (theta.sin() * d_theta * d_phi);
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn opaque_pdf_covers_the_upper_hemisphere() {
+        let material = Principled {
+            clearcoat: Texture::scalar(1.0),
+            ..Principled::default()
+        };
+        let rec = record(&material);
+        let ray = ray_from(vec3(0.6, 0.0, 0.8));
+        let total = integrate_sphere(|wi| {
+            let pdf = material.pdf(&ray, &rec, wi);
+            assert!(wi.z > 0.0 || pdf == 0.0);
+            vec3(pdf, 0.0, 0.0)
+        });
+        // Some specular samples reflect below the surface and are lost.
+        assert!(total.x > 0.9 && total.x < 1.01, "total pdf {}", total.x);
+    }
+
+    #[test]
+    fn reflects_no_more_than_it_receives() {
+        let material = Principled {
+            base_color: Texture::scalar(1.0),
+            roughness: Texture::scalar(1.0),
+            ..Principled::default()
+        };
+        let rec = record(&material);
+        let ray = ray_from(vec3(0.0, 0.0, 1.0));
+        let albedo = integrate_sphere(|wi| material.eval(&ray, &rec, wi));
+        assert!(albedo.x > 0.8 && albedo.x < 1.05, "albedo {}", albedo.x);
+    }
+
+    #[test]
+    fn metals_reflect_their_base_colour() {
+        let material = Principled {
+            base_color: Texture::Constant {
+                color: Point3::new(1.0, 0.0, 0.0),
+            },
+            metallic: Texture::scalar(1.0),
+            roughness: Texture::scalar(0.3),
+            ..Principled::default()
+        };
+        let rec = record(&material);
+        let ray = ray_from(vec3(0.0, 0.0, 1.0));
+        let reflected = integrate_sphere(|wi| material.eval(&ray, &rec, wi));
+        assert!(reflected.x > 10.0 * reflected.z);
+    }
+
+    #[test]
+    fn transmission_lets_light_through() {
+        let opaque = Principled::default();
+        let glass = Principled {
+            transmission: Texture::scalar(1.0),
+            ..Principled::default()
+        };
+        let ray = ray_from(vec3(0.0, 0.0, 1.0));
+        let below = vec3(0.0, 0.1, -1.0).normalize();
+        assert_eq!(opaque.eval(&ray, &record(&opaque), &below).x, 0.0);
+        assert!(glass.eval(&ray, &record(&glass), &below).x > 0.0);
+    }
+}
@@ -0,0 +1,41 @@
+use cgmath::Vector3;
+
+use crate::materials::{BsdfSample, Lambertian, Material};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+
+/// Surface that only shows the shadows other objects cast on it, for compositing objects
+/// over a photo or a web page. Camera rays see through it to the background darkened by the
+/// shadow, or get the shadow as alpha over a transparent background. Everything else sees
+/// `base`, so objects still pick up light bounced off the ground they stand on.
+pub struct ShadowCatcher {
+    pub base: Lambertian,
+}
+
+impl Material for ShadowCatcher {
+    fn sample(
+        &self,
+        ray: &Ray,
+        rec: &ShadeRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        self.base.sample(ray, rec, sampler)
+    }
+
+    fn eval(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> Vector3<f32> {
+        self.base.eval(ray, rec, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> f32 {
+        self.base.pdf(ray, rec, direction)
+    }
+
+    fn is_shadow_catcher(&self) -> bool {
+        true
+    }
+
+    fn albedo(&self, rec: &ShadeRecord) -> Vector3<f32> {
+        self.base.albedo(rec)
+    }
+}
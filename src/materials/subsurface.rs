@@ -0,0 +1,226 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+
+use crate::materials::microfacet::fresnel_dielectric;
+use crate::materials::{reflected_vector, refracted_vector, BsdfSample, Material};
+use crate::media::PhaseFunction;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+use crate::textures::Texture;
+
+/// Longest random walk inside an object before the path is given up as absorbed.
+const MAX_WALK_STEPS: usize = 256;
+
+/// Single scattering albedo that makes a random walk come back out with a total albedo of
+/// `albedo` (Chiang et al. 2016, "Practical and Controllable Subsurface Scattering").
+fn single_scattering_albedo(albedo: f32) -> f32 {
+    let a = albedo.clamp(0.0, 0.999);
+    let root = 4.097_12 + 4.208_63 * a - (9.592_17 + 41.680_8 * a + 17.712_6 * a * a).sqrt();
+    1.0 - root * root
+}
+
+#[inline]
+fn average(v: &Vector3<f32>) -> f32 {
+    (v.x + v.y + v.z) / 3.0
+}
+
+#[inline]
+fn exp(v: Vector3<f32>) -> Vector3<f32> {
+    vec3(v.x.exp(), v.y.exp(), v.z.exp())
+}
+
+/// Translucent material for skin, wax and marble. Light refracts through a smooth boundary
+/// and random walks through the inside of the closed object it belongs to until it leaves
+/// again, so the object needs no separate volume.
+///
+/// `albedo` is the overall colour the object ends up with and `mean_free_path` how far light
+/// travels per channel, in world units, before it interacts with the medium.
+pub struct Subsurface {
+    pub albedo: Texture,
+    pub mean_free_path: Vector3<f32>,
+    pub ior: f32,
+    /// Henyey-Greenstein anisotropy of the scattering inside.
+    pub anisotropy: f32,
+}
+
+impl Subsurface {
+    /// Walks from the hit point along `direction` inside `rec.object` until the path leaves
+    /// it, returning the exit point, the outgoing direction and the path weight.
+    fn random_walk(
+        &self,
+        ray: &Ray,
+        rec: &ShadeRecord,
+        direction: Vector3<f32>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let object = rec.object?;
+        let albedo = self.albedo.value(&rec.texture_coords());
+        let sigma_t = vec3(
+            1.0 / self.mean_free_path.x.max(1e-6),
+            1.0 / self.mean_free_path.y.max(1e-6),
+            1.0 / self.mean_free_path.z.max(1e-6),
+        );
+        let sigma_s = sigma_t.mul_element_wise(vec3(
+            single_scattering_albedo(albedo.x),
+            single_scattering_albedo(albedo.y),
+            single_scattering_albedo(albedo.z),
+        ));
+        let phase = PhaseFunction::HenyeyGreenstein(self.anisotropy);
+
+        let mut origin = rec.local_hit_point;
+        let mut direction = direction.normalize();
+        let mut weight = vec3(1.0, 1.0, 1.0);
+        // Steps starting on the boundary skip it, those starting after a scattering event
+        // inside may leave arbitrarily close to where they start.
+        let mut on_boundary = true;
+
+        for _ in 0..MAX_WALK_STEPS {
+            // Distances are sampled for one channel and weighted with the average pdf of
+            // all three, so every channel is estimated from the same walk.
+            let channel = ((sampler.get_1d() * 3.0) as usize).min(2);
+            let distance = -(1.0 - sampler.get_1d()).ln() / sigma_t[channel];
+            let step = Ray::new(origin, direction, ray.time);
+
+            let t_min = if on_boundary { 1e-4 } else { 0.0 };
+            match object.hit(&step, t_min, distance) {
+                Some(exit) => {
+                    let transmittance = exp(-sigma_t * exit.intersect_parameter);
+                    weight = weight.mul_element_wise(transmittance) / average(&transmittance);
+
+                    let normal = if direction.dot(exit.normal) < 0.0 {
+                        -exit.normal
+                    } else {
+                        exit.normal
+                    };
+                    let reflectance = fresnel_dielectric(-direction.dot(normal), self.ior);
+                    let refracted = refracted_vector(&direction, &-normal, self.ior);
+
+                    origin = exit.local_hit_point;
+                    match refracted {
+                        Some(refracted) if sampler.get_1d() >= reflectance => {
+                            return Some(BsdfSample {
+                                direction: refracted,
+                                weight,
+                                pdf: 0.0,
+                                specular: true,
+                                terminate_secondary: false,
+                                origin: Some(origin),
+                            });
+                        }
+                        _ => {
+                            direction = reflected_vector(&direction, &normal);
+                            on_boundary = true;
+                        }
+                    }
+                }
+                None => {
+                    let transmittance = exp(-sigma_t * distance);
+                    weight = weight.mul_element_wise(sigma_s.mul_element_wise(transmittance))
+                        / average(&sigma_t.mul_element_wise(transmittance));
+                    if weight.x <= 0.0 && weight.y <= 0.0 && weight.z <= 0.0 {
+                        return None;
+                    }
+
+                    origin += direction * distance;
+                    let (u1, u2) = sampler.get_2d();
+                    direction = phase.sample(&-direction, u1, u2);
+                    on_boundary = false;
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Material for Subsurface {
+    fn sample(
+        &self,
+        ray: &Ray,
+        rec: &ShadeRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let incoming = ray.direction.normalize();
+        let normal = if incoming.dot(rec.normal) > 0.0 {
+            -rec.normal
+        } else {
+            rec.normal
+        };
+
+        let reflectance = fresnel_dielectric(-incoming.dot(normal), self.ior);
+        let refracted = refracted_vector(&incoming, &normal, 1.0 / self.ior);
+
+        match refracted {
+            Some(refracted) if sampler.get_1d() >= reflectance => {
+                self.random_walk(ray, rec, refracted, sampler)
+            }
+            _ => Some(BsdfSample {
+                direction: reflected_vector(&incoming, &normal),
+                weight: vec3(1.0, 1.0, 1.0),
+                pdf: 0.0,
+                specular: true,
+                terminate_secondary: false,
+                origin: None,
+            }),
+        }
+    }
+
+    fn albedo(&self, rec: &ShadeRecord) -> Vector3<f32> {
+        self.albedo.value(&rec.texture_coords()).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometric_objects::{GeometricObject, Sphere};
+    use crate::sampler::IndependentSampler;
+    use cgmath::Point3;
+
+    fn material(albedo: f32) -> Subsurface {
+        Subsurface {
+            albedo: Texture::scalar(albedo),
+            mean_free_path: vec3(0.05, 0.05, 0.05),
+            ior: 1.4,
+            anisotropy: 0.0,
+        }
+    }
+
+    /// Mean weight of the light coming back out of a unit sphere made of `albedo`,
+    /// counting absorbed paths as zero.
+    fn returned_light(albedo: f32) -> f32 {
+        let center = Point3::new(0.0, 0.0, 0.0);
+        let sphere = Sphere::new(center, 1.0, material(albedo));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 3.0), vec3(0.0, 0.0, -1.0), 0.0);
+        let rec = sphere.hit(&ray, 1e-3, f32::MAX).unwrap();
+
+        let n = 2000;
+        let mut total = 0.0;
+        for _ in 0..n {
+            if let Some(sample) = rec.material.sample(&ray, &rec, &mut IndependentSampler) {
+                let origin = sample.origin.unwrap_or(rec.local_hit_point);
+                assert!(((origin - center).magnitude() - 1.0).abs() < 1e-3);
+                // Light leaves the sphere.
+                assert!(sample.direction.dot(origin - center) > 0.0);
+                total += average(&sample.weight);
+            }
+        }
+        total / n as f32
+    }
+
+    #[test]
+    fn single_scattering_albedo_spans_zero_to_one() {
+        assert!(single_scattering_albedo(0.0).abs() < 1e-3);
+        assert!(single_scattering_albedo(0.999) > 0.99);
+        assert!(single_scattering_albedo(0.5) > 0.5);
+        assert!(single_scattering_albedo(0.5) < single_scattering_albedo(0.8));
+    }
+
+    #[test]
+    fn walks_come_back_out_with_about_the_albedo() {
+        let bright = returned_light(0.8);
+        let dark = returned_light(0.1);
+        assert!(bright > 0.6 && bright < 1.0, "returned {}", bright);
+        assert!(dark < 0.2, "returned {}", dark);
+    }
+}
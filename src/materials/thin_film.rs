@@ -0,0 +1,233 @@
+use cgmath::Vector3;
+use std::f32::consts::PI;
+use std::ops::{Add, Div, Mul, Sub};
+
+use crate::materials::ConductorIor;
+use crate::spectrum;
+use crate::textures::{Texture, TextureCoords};
+
+/// Wavelengths at which the film reflectance is evaluated before converting it to RGB. Fine
+/// enough to resolve the fringes of films up to a micrometer or so.
+const FILM_SPECTRAL_STEPS: usize = 32;
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    const fn new(re: f32, im: f32) -> Self {
+        Complex { re, im }
+    }
+
+    const fn real(re: f32) -> Self {
+        Complex { re, im: 0.0 }
+    }
+
+    fn norm_sqr(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn sqrt(self) -> Self {
+        let norm = self.norm_sqr().sqrt();
+        let re = (0.5 * (norm + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (norm - self.re)).max(0.0).sqrt();
+        Complex::new(re, im.copysign(self.im))
+    }
+
+    /// `exp(i * self)`
+    fn exp_i(self) -> Self {
+        let magnitude = (-self.im).exp();
+        Complex::new(magnitude * self.re.cos(), magnitude * self.re.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, other: Complex) -> Complex {
+        let denominator = other.norm_sqr();
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / denominator,
+            (self.im * other.re - self.re * other.im) / denominator,
+        )
+    }
+}
+
+/// What lies below a thin film.
+pub enum Substrate {
+    /// Dielectric with a real index of refraction.
+    Dielectric(f32),
+    Conductor(ConductorIor),
+    /// Conductor only known by its RGB reflectance, treated as a perfect mirror that keeps
+    /// that fraction of the amplitude.
+    Reflector(Vector3<f32>),
+}
+
+/// Thin transparent layer on top of a surface. Light reflected at its top and bottom
+/// interfaces interferes, giving the iridescence of soap bubbles, oil slicks and coated
+/// lenses.
+pub struct ThinFilm {
+    /// Film thickness in nanometers, read from the red channel.
+    pub thickness: Texture,
+    /// Index of refraction of the film, read from the red channel.
+    pub ior: Texture,
+}
+
+impl ThinFilm {
+    /// RGB reflectance of the film over `substrate`, lit from a medium with index of
+    /// refraction `eta_i` at an angle whose cosine is `cos_theta_i`.
+    pub fn reflectance(
+        &self,
+        coords: &TextureCoords,
+        cos_theta_i: f32,
+        eta_i: f32,
+        substrate: &Substrate,
+    ) -> Vector3<f32> {
+        let thickness = self.thickness.value(coords).x.max(0.0);
+        let eta_film = self.ior.value(coords).x;
+        let cos_theta_i = cos_theta_i.abs().min(1.0);
+
+        spectrum::spectrum_to_rgb(
+            |lambda| airy_reflectance(lambda, cos_theta_i, eta_i, eta_film, thickness, substrate),
+            FILM_SPECTRAL_STEPS,
+        )
+    }
+}
+
+/// Amplitude reflection coefficients (s, p) between two media.
+fn fresnel_amplitudes(
+    eta_1: Complex,
+    cos_1: Complex,
+    eta_2: Complex,
+    cos_2: Complex,
+) -> (Complex, Complex) {
+    let rs = (eta_1 * cos_1 - eta_2 * cos_2) / (eta_1 * cos_1 + eta_2 * cos_2);
+    let rp = (eta_2 * cos_1 - eta_1 * cos_2) / (eta_2 * cos_1 + eta_1 * cos_2);
+    (rs, rp)
+}
+
+/// Reflectance of a single film at `lambda` nanometers, summing all internal reflections with
+/// the Airy formula.
+fn airy_reflectance(
+    lambda: f32,
+    cos_theta_i: f32,
+    eta_i: f32,
+    eta_film: f32,
+    thickness: f32,
+    substrate: &Substrate,
+) -> f32 {
+    let one = Complex::real(1.0);
+    let sin2_i = Complex::real(1.0 - cos_theta_i * cos_theta_i);
+    let (eta_1, cos_1) = (Complex::real(eta_i), Complex::real(cos_theta_i));
+    let eta_2 = Complex::real(eta_film);
+    let cosine_in = |eta: Complex| {
+        let ratio = eta_1 / eta;
+        (one - ratio * ratio * sin2_i).sqrt()
+    };
+    let cos_2 = cosine_in(eta_2);
+
+    let (r12s, r12p) = fresnel_amplitudes(eta_1, cos_1, eta_2, cos_2);
+    let (r23s, r23p) = match substrate {
+        Substrate::Dielectric(eta) => {
+            let eta_3 = Complex::real(*eta);
+            fresnel_amplitudes(eta_2, cos_2, eta_3, cosine_in(eta_3))
+        }
+        Substrate::Conductor(ior) => {
+            let (eta, k) = ior.at(lambda);
+            let eta_3 = Complex::new(eta, k);
+            fresnel_amplitudes(eta_2, cos_2, eta_3, cosine_in(eta_3))
+        }
+        Substrate::Reflector(albedo) => {
+            let r = -spectrum::rgb_to_spectrum(albedo, lambda)
+                .clamp(0.0, 1.0)
+                .sqrt();
+            (Complex::real(r), Complex::real(r))
+        }
+    };
+
+    // Round trip phase difference through the film.
+    let phase = eta_2 * cos_2 * Complex::real(4.0 * PI * thickness / lambda);
+    let shift = phase.exp_i();
+    let airy =
+        |r12: Complex, r23: Complex| ((r12 + r23 * shift) / (one + r12 * r23 * shift)).norm_sqr();
+
+    (0.5 * (airy(r12s, r23s) + airy(r12p, r23p))).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{vec3, Point3};
+
+    const NORMAL_INCIDENCE_GLASS: f32 = 0.04;
+
+    #[test]
+    fn vanishing_film_leaves_the_bare_interface() {
+        let glass = Substrate::Dielectric(1.5);
+        let r = airy_reflectance(550.0, 1.0, 1.0, 1.33, 0.0, &glass);
+        assert!((r - NORMAL_INCIDENCE_GLASS).abs() < 1e-5);
+    }
+
+    #[test]
+    fn index_matched_film_is_invisible() {
+        let glass = Substrate::Dielectric(1.5);
+        for &thickness in &[50.0, 300.0, 1000.0] {
+            let r = airy_reflectance(500.0, 1.0, 1.0, 1.5, thickness, &glass);
+            assert!((r - NORMAL_INCIDENCE_GLASS).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn quarter_wave_coating_cancels_reflection() {
+        let eta_film = 1.5_f32.sqrt();
+        let thickness = 550.0 / (4.0 * eta_film);
+        let glass = Substrate::Dielectric(1.5);
+        assert!(airy_reflectance(550.0, 1.0, 1.0, eta_film, thickness, &glass) < 1e-5);
+        // Other wavelengths are only partially cancelled.
+        assert!(airy_reflectance(400.0, 1.0, 1.0, eta_film, thickness, &glass) > 1e-4);
+    }
+
+    #[test]
+    fn thick_films_are_coloured() {
+        let film = ThinFilm {
+            thickness: Texture::scalar(400.0),
+            ior: Texture::scalar(1.33),
+        };
+        let coords = TextureCoords {
+            u: 0.0,
+            v: 0.0,
+            point: Point3::new(0.0, 0.0, 0.0),
+            footprint: 0.0,
+            uv_footprint: 0.0,
+            normal: vec3(0.0, 1.0, 0.0),
+        };
+        let r = film.reflectance(&coords, 1.0, 1.0, &Substrate::Dielectric(1.0));
+        let (min, max) = (r.x.min(r.y).min(r.z), r.x.max(r.y).max(r.z));
+        assert!(max - min > 0.01);
+    }
+}
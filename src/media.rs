@@ -0,0 +1,693 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+use std::f32;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use crate::frame::Frame;
+use crate::geometric_objects::GeometricObject;
+use crate::materials::{BsdfSample, Material};
+use crate::random;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shade_record::ShadeRecord;
+use crate::textures::{Perlin, Texture};
+
+/// Angular distribution of light scattered inside a medium.
+#[derive(Clone, Copy)]
+pub enum PhaseFunction {
+    Isotropic,
+    /// Henyey-Greenstein lobe, `g` in (-1, 1) goes from back to forward scattering.
+    HenyeyGreenstein(f32),
+}
+
+impl PhaseFunction {
+    /// Density of light travelling along `-wi` being scattered into `wo`, both pointing away
+    /// from the scattering point.
+    pub fn eval(self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        match self {
+            PhaseFunction::Isotropic => 1.0 / (4.0 * PI),
+            PhaseFunction::HenyeyGreenstein(g) => {
+                let cos_theta = -wo.normalize().dot(wi.normalize());
+                let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+                (1.0 - g * g) / (4.0 * PI * denominator * denominator.max(1e-8).sqrt())
+            }
+        }
+    }
+
+    /// Samples `wi` proportionally to `eval`, so the sample weight is one.
+    pub fn sample(self, wo: &Vector3<f32>, u1: f32, u2: f32) -> Vector3<f32> {
+        // Cosine of the angle to the direction the light keeps travelling in.
+        let cos_theta = match self {
+            PhaseFunction::HenyeyGreenstein(g) if g.abs() >= 1e-3 => {
+                let term = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+                ((1.0 + g * g - term * term) / (2.0 * g)).clamp(-1.0, 1.0)
+            }
+            _ => 1.0 - 2.0 * u1,
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        let forward = -wo.normalize();
+        Frame::from_normal(&forward).to_world(&vec3(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+/// Scattering inside a medium. `albedo` is the single scattering albedo, the share of
+/// the extinguished light that gets scattered instead of absorbed. The absorbed share
+/// glows with `emission`, which is looked up with the position of the collision.
+pub struct PhaseMaterial {
+    pub albedo: Texture,
+    pub phase: PhaseFunction,
+    pub emission: Option<Texture>,
+}
+
+impl Material for PhaseMaterial {
+    fn sample(
+        &self,
+        ray: &Ray,
+        rec: &ShadeRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let wo = -ray.direction;
+        let (u1, u2) = sampler.get_2d();
+        let direction = self.phase.sample(&wo, u1, u2);
+
+        Some(BsdfSample {
+            direction,
+            weight: self.albedo.value(&rec.texture_coords()).to_vec(),
+            pdf: self.phase.eval(&wo, &direction),
+            specular: false,
+            terminate_secondary: false,
+            origin: None,
+        })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &ShadeRecord, direction: &Vector3<f32>) -> Vector3<f32> {
+        self.albedo.value(&rec.texture_coords()).to_vec()
+            * self.phase.eval(&-ray.direction, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, _rec: &ShadeRecord, direction: &Vector3<f32>) -> f32 {
+        self.phase.eval(&-ray.direction, direction)
+    }
+
+    fn emitted(&self, _ray: &Ray, rec: &ShadeRecord) -> Vector3<f32> {
+        match &self.emission {
+            Some(emission) => {
+                let coords = rec.texture_coords();
+                let absorbed = vec3(1.0, 1.0, 1.0) - self.albedo.value(&coords).to_vec();
+                emission.value(&coords).to_vec().mul_element_wise(absorbed)
+            }
+            None => vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn albedo(&self, rec: &ShadeRecord) -> Vector3<f32> {
+        self.albedo.value(&rec.texture_coords()).to_vec()
+    }
+}
+
+/// Shade record for a scattering event at parameter `t` inside a medium. There is no surface,
+/// the normal just faces back along the ray.
+pub fn medium_record<'a>(
+    ray: &Ray,
+    t: f32,
+    material: &'a dyn Material,
+    object: Option<&'a dyn GeometricObject>,
+) -> ShadeRecord<'a> {
+    let normal = -ray.direction.normalize();
+    ShadeRecord {
+        normal,
+        tangent: Frame::from_normal(&normal).s,
+        local_hit_point: ray.point_at_parameter(t),
+        material,
+        object,
+        intersect_parameter: t,
+        u: 0.0,
+        v: 0.0,
+        footprint: ray.footprint_at(t),
+        uv_footprint: 0.0,
+    }
+}
+
+/// Samples the distance to the next interaction in a homogeneous medium.
+#[inline]
+pub fn sample_free_flight(density: f32) -> f32 {
+    -(1.0 - random()).ln() / density
+}
+
+/// Homogeneous medium filling a closed `boundary`, like smoke or fog in a box. Rays either
+/// pass through or scatter at a distance sampled from the medium density.
+pub struct ConstantMedium {
+    boundary: Box<dyn GeometricObject>,
+    density: f32,
+    phase_material: PhaseMaterial,
+}
+
+impl ConstantMedium {
+    pub fn new<G: GeometricObject + 'static>(
+        boundary: G,
+        density: f32,
+        albedo: Texture,
+        phase: PhaseFunction,
+    ) -> Self {
+        ConstantMedium {
+            boundary: Box::new(boundary),
+            density,
+            phase_material: PhaseMaterial {
+                albedo,
+                phase,
+                emission: None,
+            },
+        }
+    }
+}
+
+/// Parameters where `ray` enters and leaves `boundary` within `t_min` and `t_max`.
+fn boundary_span(
+    boundary: &dyn GeometricObject,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32)> {
+    // The ray may start inside, so look for the entry behind it too.
+    let entry = boundary.hit(ray, f32::MIN, f32::MAX)?.intersect_parameter;
+    let exit = boundary
+        .hit(ray, entry + 1e-4, f32::MAX)?
+        .intersect_parameter;
+
+    let (entry, exit) = (entry.max(t_min), exit.min(t_max));
+    Some((entry, exit)).filter(|(entry, exit)| entry < exit)
+}
+
+impl GeometricObject for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
+        let (entry, exit) = boundary_span(self.boundary.as_ref(), ray, t_min, t_max)?;
+
+        let speed = ray.direction.magnitude();
+        let t = entry + sample_free_flight(self.density) / speed;
+        if t >= exit {
+            return None;
+        }
+
+        Some(medium_record(ray, t, &self.phase_material, Some(self)))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        match boundary_span(self.boundary.as_ref(), ray, t_min, t_max) {
+            Some((entry, exit)) => {
+                (-self.density * (exit - entry) * ray.direction.magnitude()).exp()
+            }
+            None => 1.0,
+        }
+    }
+
+    fn area(&self) -> f32 {
+        self.boundary.area()
+    }
+
+    fn material(&self) -> &dyn Material {
+        &self.phase_material
+    }
+}
+
+/// Homogeneous fog filling the whole world. Each ray segment only sees fog over its first
+/// `max_distance` units, so the background stays visible.
+pub struct Fog {
+    pub density: f32,
+    pub max_distance: f32,
+    pub material: PhaseMaterial,
+}
+
+impl Fog {
+    /// Parameter along `ray` where it scatters in the fog before reaching `t_max`, if it
+    /// does.
+    pub fn sample_scattering(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<f32> {
+        let speed = ray.direction.magnitude();
+        let t = sample_free_flight(self.density) / speed;
+        Some(t).filter(|&t| t > t_min && t < t_max && t * speed < self.max_distance)
+    }
+
+    /// Fraction of light making it through the fog between `t_min` and `t_max`.
+    pub fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let speed = ray.direction.magnitude();
+        let distance = (t_max * speed).min(self.max_distance) - t_min * speed;
+        (-self.density * distance.max(0.0)).exp()
+    }
+}
+
+/// Dense grid of density samples stretched over an axis aligned box, with x varying fastest.
+pub struct VoxelGrid {
+    resolution: (usize, usize, usize),
+    bounds_min: Point3<f32>,
+    bounds_max: Point3<f32>,
+    data: Vec<f32>,
+    max_value: f32,
+}
+
+impl VoxelGrid {
+    pub fn new(
+        resolution: (usize, usize, usize),
+        bounds_min: Point3<f32>,
+        bounds_max: Point3<f32>,
+        data: Vec<f32>,
+    ) -> Result<Self, String> {
+        let (nx, ny, nz) = resolution;
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err(format!("empty voxel grid {}x{}x{}", nx, ny, nz));
+        }
+        let count = nx
+            .checked_mul(ny)
+            .and_then(|count| count.checked_mul(nz))
+            .ok_or_else(|| format!("voxel grid of {}x{}x{} is too large", nx, ny, nz))?;
+        if data.len() != count {
+            return Err(format!(
+                "voxel grid of {}x{}x{} needs {} values, got {}",
+                nx,
+                ny,
+                nz,
+                count,
+                data.len()
+            ));
+        }
+        let max_value = data.iter().cloned().fold(0.0, f32::max);
+
+        Ok(VoxelGrid {
+            resolution,
+            bounds_min,
+            bounds_max,
+            data,
+            max_value,
+        })
+    }
+
+    /// Reads a raw grid of little endian `f32` values.
+    pub fn from_raw(
+        bytes: &[u8],
+        resolution: (usize, usize, usize),
+        bounds_min: Point3<f32>,
+        bounds_max: Point3<f32>,
+    ) -> Result<Self, String> {
+        let chunks = bytes.chunks_exact(4);
+        if !chunks.remainder().is_empty() {
+            return Err(format!(
+                "raw grid size {} is not a multiple of 4",
+                bytes.len()
+            ));
+        }
+        let data = chunks
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        VoxelGrid::new(resolution, bounds_min, bounds_max, data)
+    }
+
+    /// Reads a grid with a one line text header giving its resolution, `VOL nx ny nz`,
+    /// followed by the raw little endian `f32` values.
+    pub fn parse(
+        bytes: &[u8],
+        bounds_min: Point3<f32>,
+        bounds_max: Point3<f32>,
+    ) -> Result<Self, String> {
+        let header_end = bytes
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| "missing voxel grid header".to_string())?;
+        let header = std::str::from_utf8(&bytes[..header_end])
+            .map_err(|_| "voxel grid header is not valid text".to_string())?;
+
+        let mut fields = header.split_whitespace();
+        if fields.next() != Some("VOL") {
+            return Err(format!("unknown voxel grid header '{}'", header));
+        }
+        let mut dimension = || -> Result<usize, String> {
+            fields
+                .next()
+                .and_then(|field| field.parse().ok())
+                .ok_or_else(|| format!("bad voxel grid resolution in '{}'", header))
+        };
+        let resolution = (dimension()?, dimension()?, dimension()?);
+
+        VoxelGrid::from_raw(&bytes[header_end + 1..], resolution, bounds_min, bounds_max)
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        let (nx, ny, _) = self.resolution;
+        self.data[(z * ny + y) * nx + x]
+    }
+
+    /// Trilinearly interpolated density at `point`, zero outside the grid bounds.
+    pub fn density(&self, point: &Point3<f32>) -> f32 {
+        let extent = self.bounds_max - self.bounds_min;
+        let local = point - self.bounds_min;
+        let (nx, ny, nz) = self.resolution;
+
+        let to_grid = |offset: f32, extent: f32, n: usize| -> Option<(usize, usize, f32)> {
+            let unit = offset / extent;
+            if !(0.0..=1.0).contains(&unit) {
+                return None;
+            }
+            let x = (unit * n as f32 - 0.5).clamp(0.0, (n - 1) as f32);
+            let i = x as usize;
+            Some((i, (i + 1).min(n - 1), x - i as f32))
+        };
+        let (x0, x1, tx) = match to_grid(local.x, extent.x, nx) {
+            Some(cell) => cell,
+            None => return 0.0,
+        };
+        let (y0, y1, ty) = match to_grid(local.y, extent.y, ny) {
+            Some(cell) => cell,
+            None => return 0.0,
+        };
+        let (z0, z1, tz) = match to_grid(local.z, extent.z, nz) {
+            Some(cell) => cell,
+            None => return 0.0,
+        };
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let plane = |z: usize| {
+            lerp(
+                lerp(self.voxel(x0, y0, z), self.voxel(x1, y0, z), tx),
+                lerp(self.voxel(x0, y1, z), self.voxel(x1, y1, z), tx),
+                ty,
+            )
+        };
+        lerp(plane(z0), plane(z1), tz)
+    }
+}
+
+/// Spatially varying density of a heterogeneous medium.
+pub enum DensityField {
+    /// Shared so several media can reuse a large grid.
+    Grid(Rc<VoxelGrid>),
+    /// Perlin turbulence in [0, 1], `scale` sets the size of the puffs.
+    Noise { scale: f32, octaves: u32 },
+}
+
+impl DensityField {
+    pub fn density(&self, point: &Point3<f32>) -> f32 {
+        match self {
+            DensityField::Grid(grid) => grid.density(point),
+            DensityField::Noise { scale, octaves } => {
+                let point = point * *scale;
+                let (turbulence, _) = (0..*octaves).fold((0.0, 1.0), |(sum, weight), _| {
+                    let octave = Perlin::generate_noise(&(point * (1.0 / weight)));
+                    (sum + weight * octave.abs(), weight * 0.5)
+                });
+                turbulence.clamp(0.0, 1.0)
+            }
+        }
+    }
+
+    /// Upper bound of `density`.
+    pub fn max_density(&self) -> f32 {
+        match self {
+            DensityField::Grid(grid) => grid.max_value,
+            DensityField::Noise { .. } => 1.0,
+        }
+    }
+}
+
+/// Medium with varying density inside a closed `boundary`, for smoke, clouds and fire.
+/// Collisions are found with delta tracking (Woodcock) against the largest density and
+/// shadow rays are attenuated with ratio tracking.
+pub struct HeterogeneousMedium {
+    boundary: Box<dyn GeometricObject>,
+    field: DensityField,
+    density_scale: f32,
+    majorant: f32,
+    phase_material: PhaseMaterial,
+}
+
+impl HeterogeneousMedium {
+    /// Medium whose density is `field` times `density_scale`. Give `phase_material` an
+    /// emission to render fire.
+    pub fn new<G: GeometricObject + 'static>(
+        boundary: G,
+        field: DensityField,
+        density_scale: f32,
+        phase_material: PhaseMaterial,
+    ) -> Self {
+        let majorant = field.max_density() * density_scale;
+        HeterogeneousMedium {
+            boundary: Box::new(boundary),
+            field,
+            density_scale,
+            majorant,
+            phase_material,
+        }
+    }
+
+    fn density(&self, point: &Point3<f32>) -> f32 {
+        self.field.density(point) * self.density_scale
+    }
+}
+
+impl GeometricObject for HeterogeneousMedium {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let (entry, exit) = boundary_span(self.boundary.as_ref(), ray, t_min, t_max)?;
+        let speed = ray.direction.magnitude();
+
+        // Delta tracking, tentative collisions against the majorant are real with the
+        // ratio of the local density to it.
+        let mut t = entry;
+        loop {
+            t += sample_free_flight(self.majorant) / speed;
+            if t >= exit {
+                return None;
+            }
+            if random() * self.majorant < self.density(&ray.point_at_parameter(t)) {
+                return Some(medium_record(ray, t, &self.phase_material, Some(self)));
+            }
+        }
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let (entry, exit) = match boundary_span(self.boundary.as_ref(), ray, t_min, t_max) {
+            Some(span) if self.majorant > 0.0 => span,
+            _ => return 1.0,
+        };
+        let speed = ray.direction.magnitude();
+
+        // Ratio tracking, an unbiased estimate without terminating at collisions.
+        let mut transmittance = 1.0;
+        let mut t = entry;
+        loop {
+            t += sample_free_flight(self.majorant) / speed;
+            if t >= exit {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density(&ray.point_at_parameter(t)) / self.majorant;
+        }
+    }
+
+    fn area(&self) -> f32 {
+        self.boundary.area()
+    }
+
+    fn material(&self) -> &dyn Material {
+        &self.phase_material
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometric_objects::Sphere;
+    use crate::materials::Lambertian;
+
+    /// Integrates `f` over the sphere of directions with the midpoint rule.
+    fn integrate_sphere(f: impl Fn(&Vector3<f32>) -> f32) -> f32 {
+        let (steps_theta, steps_phi) = (400, 64);
+        let (d_theta, d_phi) = (PI / steps_theta as f32, 2.0 * PI / steps_phi as f32);
+        let mut sum = 0.0;
+        for i in 0..steps_theta {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..steps_phi {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let w = vec3(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                sum += f(&w) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn phase_functions_are_normalized() {
+        let wo = vec3(0.0, 0.6, 0.8);
+        for &phase in &[
+            PhaseFunction::Isotropic,
+            PhaseFunction::HenyeyGreenstein(0.5),
+            PhaseFunction::HenyeyGreenstein(-0.3),
+        ] {
+            let total = integrate_sphere(|wi| phase.eval(&wo, wi));
+            assert!(
+                (total - 1.0).abs() < 0.01,
+                "phase function integrates to {}",
+                total
+            );
+        }
+    }
+
+    #[test]
+    fn henyey_greenstein_samples_have_mean_cosine_g() {
+        let g = 0.6;
+        let phase = PhaseFunction::HenyeyGreenstein(g);
+        let wo = vec3(0.0, 0.0, -1.0);
+        let n = 64;
+        let mut mean = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let (u1, u2) = ((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+                let wi = phase.sample(&wo, u1, u2);
+                assert!((wi.magnitude() - 1.0).abs() < 1e-4);
+                // Light keeps travelling along -wo.
+                mean += (-wo).dot(wi) / (n * n) as f32;
+            }
+        }
+        assert!((mean - g).abs() < 0.01);
+    }
+
+    #[test]
+    fn constant_medium_transmittance_follows_beer_lambert() {
+        let medium = ConstantMedium::new(
+            Sphere::new(
+                Point3::new(0.0, 0.0, 0.0),
+                1.0,
+                Lambertian {
+                    texture: Texture::scalar(0.5),
+                },
+            ),
+            0.5,
+            Texture::scalar(1.0),
+            PhaseFunction::Isotropic,
+        );
+        let ray = Ray::new(Point3::new(0.0, 0.0, -5.0), vec3(0.0, 0.0, 2.0), 0.0);
+        // Two units of medium, however long the direction is.
+        let through = medium.transmittance(&ray, 0.0, f32::MAX);
+        assert!((through - (-1.0_f32).exp()).abs() < 1e-5);
+        // Stopping in the middle of the sphere only sees half of it.
+        let halfway = medium.transmittance(&ray, 0.0, 2.5);
+        assert!((halfway - (-0.5_f32).exp()).abs() < 1e-5);
+        let missing = Ray::new(Point3::new(0.0, 2.0, -5.0), vec3(0.0, 0.0, 1.0), 0.0);
+        assert_eq!(medium.transmittance(&missing, 0.0, f32::MAX), 1.0);
+    }
+
+    #[test]
+    fn fog_only_reaches_max_distance() {
+        let fog = Fog {
+            density: 0.1,
+            max_distance: 10.0,
+            material: PhaseMaterial {
+                albedo: Texture::scalar(1.0),
+                phase: PhaseFunction::Isotropic,
+                emission: None,
+            },
+        };
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), 0.0);
+        assert!((fog.transmittance(&ray, 0.0, 5.0) - (-0.5_f32).exp()).abs() < 1e-6);
+        assert!((fog.transmittance(&ray, 0.0, 1e6) - (-1.0_f32).exp()).abs() < 1e-6);
+        for _ in 0..1000 {
+            if let Some(t) = fog.sample_scattering(&ray, 0.0, 100.0) {
+                assert!(t > 0.0 && t < 10.0);
+            }
+        }
+    }
+
+    fn grid_bytes(header: &str, values: &[f32]) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        for value in values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn unit_grid(header: &str, values: &[f32]) -> Result<VoxelGrid, String> {
+        VoxelGrid::parse(
+            &grid_bytes(header, values),
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn voxel_grid_parses_its_header() {
+        let values: Vec<f32> = (0..8).map(|i| i as f32).collect();
+        let grid = unit_grid("VOL 2 2 2\n", &values).unwrap();
+        assert_eq!(grid.resolution, (2, 2, 2));
+        assert_eq!(grid.max_value, 7.0);
+        // x varies fastest, then y, then z.
+        assert_eq!(grid.voxel(1, 0, 0), 1.0);
+        assert_eq!(grid.voxel(0, 1, 0), 2.0);
+        assert_eq!(grid.voxel(0, 0, 1), 4.0);
+    }
+
+    #[test]
+    fn voxel_grid_rejects_bad_files() {
+        assert!(unit_grid("VOL 2 2 2", &[]).is_err());
+        assert!(unit_grid("VDB 1 1 1\n", &[1.0]).is_err());
+        assert!(unit_grid("VOL 2 2\n", &[1.0; 4]).is_err());
+        assert!(unit_grid("VOL 0 1 1\n", &[]).is_err());
+        assert!(unit_grid("VOL 2 2 2\n", &[1.0; 7]).is_err());
+        assert!(unit_grid("VOL 4294967296 4294967296 4294967296\n", &[1.0]).is_err());
+        let mut truncated = grid_bytes("VOL 1 1 1\n", &[1.0]);
+        truncated.pop();
+        assert!(VoxelGrid::parse(
+            &truncated,
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 1.0)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn voxel_grid_interpolates_between_voxel_centres() {
+        let grid = unit_grid("VOL 2 1 1\n", &[0.0, 1.0]).unwrap();
+        assert_eq!(grid.density(&Point3::new(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.density(&Point3::new(0.75, 0.5, 0.5)), 1.0);
+        assert!((grid.density(&Point3::new(0.5, 0.5, 0.5)) - 0.5).abs() < 1e-6);
+        // Clamped to the outer voxels inside the box, zero outside it.
+        assert_eq!(grid.density(&Point3::new(0.9, 0.5, 0.5)), 1.0);
+        assert_eq!(grid.density(&Point3::new(1.1, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn ratio_tracking_matches_beer_lambert_in_uniform_grids() {
+        let grid = unit_grid("VOL 1 1 1\n", &[1.0]).unwrap();
+        let medium = HeterogeneousMedium::new(
+            Sphere::new(
+                Point3::new(0.5, 0.5, 0.5),
+                0.5,
+                Lambertian {
+                    texture: Texture::scalar(0.5),
+                },
+            ),
+            DensityField::Grid(Rc::new(grid)),
+            2.0,
+            PhaseMaterial {
+                albedo: Texture::scalar(1.0),
+                phase: PhaseFunction::Isotropic,
+                emission: None,
+            },
+        );
+        // One unit through the middle of the sphere.
+        let ray = Ray::new(Point3::new(0.5, 0.5, -1.0), vec3(0.0, 0.0, 1.0), 0.0);
+        let n = 4000;
+        let mean = (0..n)
+            .map(|_| medium.transmittance(&ray, 0.0, f32::MAX))
+            .sum::<f32>()
+            / n as f32;
+        assert!((mean - (-2.0_f32).exp()).abs() < 0.02);
+    }
+}
@@ -1,3 +1,4 @@
+use cgmath::prelude::*;
 use cgmath::{Point3, Vector3};
 use std::f32;
 
@@ -5,6 +6,12 @@ pub struct Ray {
     pub origin: Point3<f32>,
     pub direction: Vector3<f32>,
     pub time: f32,
+    /// Growth of the ray footprint per unit of travelled distance.
+    /// Zero for rays that do not need texture filtering.
+    pub spread: f32,
+    /// Wavelengths in nanometers carried by the ray in spectral mode, `None` when
+    /// rendering in RGB.
+    pub wavelengths: Option<Vector3<f32>>,
 }
 
 impl Ray {
@@ -13,11 +20,31 @@ impl Ray {
             origin,
             direction,
             time,
+            spread: 0.0,
+            wavelengths: None,
         }
     }
 
+    #[inline]
+    pub const fn with_spread(mut self, spread: f32) -> Self {
+        self.spread = spread;
+        self
+    }
+
+    #[inline]
+    pub const fn with_wavelengths(mut self, wavelengths: Option<Vector3<f32>>) -> Self {
+        self.wavelengths = wavelengths;
+        self
+    }
+
     #[inline]
     pub fn point_at_parameter(&self, t: f32) -> Point3<f32> {
         self.origin + (self.direction * t)
     }
+
+    /// Approximate world-space width covered by the ray at parameter `t`.
+    #[inline]
+    pub fn footprint_at(&self, t: f32) -> f32 {
+        self.spread * t * self.direction.magnitude()
+    }
 }
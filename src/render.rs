@@ -0,0 +1,423 @@
+use cgmath::{vec3, Vector3};
+
+use crate::aov::{Aov, AovBuffer};
+use crate::camera::Camera;
+use crate::denoise::Denoiser;
+use crate::film::Film;
+use crate::filter::Filter;
+use crate::hdr_image::write_exr;
+use crate::integrator::generate_color_for_pixel;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::spectrum;
+use crate::world::World;
+
+/// Spends more samples on pixels whose estimated error is still high after the first round.
+#[derive(Clone, Copy)]
+pub struct AdaptiveSampling {
+    /// Standard error of a pixel, after gamma correction and in units of the full display
+    /// range, below which it gets no more samples.
+    pub noise_threshold: f32,
+    /// Most samples any pixel gets.
+    pub max_samples: u32,
+}
+
+pub struct RenderSettings {
+    pub film: Film,
+    /// Samples of every pixel, adaptive sampling adds them in rounds of this many.
+    pub samples_per_pixel: u32,
+    pub sampler: SamplerKind,
+    /// Reconstruction filter weighting the samples of every pixel.
+    pub filter: Filter,
+    pub spectral: bool,
+    /// Passes to render next to the beauty image.
+    pub aovs: Vec<Aov>,
+    /// Runs the denoiser over the beauty image, rendering the passes it needs.
+    pub denoise: bool,
+    pub adaptive: Option<AdaptiveSampling>,
+}
+
+/// Rendered frame, or the crop of it the film asked for, in premultiplied linear colour and
+/// alpha plus the requested passes.
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    /// Top left pixel of the framebuffer in the full frame.
+    pub origin: (usize, usize),
+    pub color: Vec<Vector3<f32>>,
+    pub alpha: Vec<f32>,
+    pub aovs: Vec<AovBuffer>,
+    /// Samples taken for every pixel.
+    pub sample_counts: Vec<u32>,
+}
+
+impl Framebuffer {
+    pub fn aov(&self, aov: Aov) -> Option<&AovBuffer> {
+        self.aovs.iter().find(|buffer| buffer.aov == aov)
+    }
+
+    /// Pixels for a canvas: gamma corrected 8 bit RGBA with straight alpha.
+    pub fn to_rgba8(&self) -> Vec<u32> {
+        self.color
+            .iter()
+            .zip(&self.alpha)
+            .map(|(&color, &alpha)| {
+                // Canvas pixels have straight alpha, the samples add up to premultiplied colour.
+                let Vector3 { x: r, y: g, z: b } = if alpha > 0.0 { color / alpha } else { color };
+
+                // Canvas `ImageData` is read byte by byte and wasm is little endian.
+                u32::from_le_bytes([
+                    (r.sqrt() * 255.99) as u8,
+                    (g.sqrt() * 255.99) as u8,
+                    (b.sqrt() * 255.99) as u8,
+                    (alpha * 255.99) as u8,
+                ])
+            })
+            .collect()
+    }
+
+    /// Canvas pixels showing how many samples every pixel took, from dark blue for the
+    /// fewest through green to red for the most.
+    pub fn sample_heatmap(&self) -> Vec<u32> {
+        let min = self.sample_counts.iter().copied().min().unwrap_or(0);
+        let max = self.sample_counts.iter().copied().max().unwrap_or(0);
+        let range = (max - min).max(1) as f32;
+
+        self.sample_counts
+            .iter()
+            .map(|&count| {
+                let t = (count - min) as f32 / range;
+                let (r, g, b) = if t < 0.5 {
+                    (0.0, 2.0 * t, 1.0 - 2.0 * t)
+                } else {
+                    (2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+                };
+                u32::from_le_bytes([
+                    (r * 255.99) as u8,
+                    (g * 255.99) as u8,
+                    (b * 255.99) as u8,
+                    255,
+                ])
+            })
+            .collect()
+    }
+
+    /// OpenEXR file with the beauty image as premultiplied RGBA, the sample counts and every
+    /// pass as a layer.
+    pub fn to_exr(&self) -> Vec<u8> {
+        let mut channels = vec![
+            ("R".to_string(), self.color.iter().map(|c| c.x).collect()),
+            ("G".to_string(), self.color.iter().map(|c| c.y).collect()),
+            ("B".to_string(), self.color.iter().map(|c| c.z).collect()),
+            ("A".to_string(), self.alpha.clone()),
+            (
+                "samples.Y".to_string(),
+                self.sample_counts
+                    .iter()
+                    .map(|&count| count as f32)
+                    .collect(),
+            ),
+        ];
+        for buffer in &self.aovs {
+            let names = buffer.aov.channels();
+            for (index, channel) in names.iter().enumerate() {
+                channels.push((
+                    format!("{}.{}", buffer.aov.name(), channel),
+                    buffer
+                        .data
+                        .iter()
+                        .skip(index)
+                        .step_by(names.len())
+                        .copied()
+                        .collect(),
+                ));
+            }
+        }
+        write_exr(self.width, self.height, &channels)
+    }
+}
+
+/// Running mean and variance of the luminance of the samples of a pixel (Welford).
+#[derive(Clone, Copy, Default)]
+struct PixelStats {
+    count: u32,
+    mean: f32,
+    m2: f32,
+}
+
+impl PixelStats {
+    fn add(&mut self, value: f32) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Standard error of the mean after gamma correction.
+    fn display_error(&self) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY;
+        }
+        let n = self.count as f32;
+        let standard_error = (self.m2 / (n - 1.0) / n).sqrt();
+        // The slope of the square root gamma curve at the mean.
+        standard_error / (2.0 * self.mean.max(1e-4).sqrt())
+    }
+}
+
+#[inline]
+fn luminance(rgb: &Vector3<f32>) -> f32 {
+    0.2126 * rgb.x + 0.7152 * rgb.y + 0.0722 * rgb.z
+}
+
+struct Renderer<'a> {
+    camera: &'a Camera,
+    world: &'a World,
+    settings: &'a RenderSettings,
+    sampler: Box<dyn Sampler>,
+    /// Sum of the filter weights of the samples of every pixel.
+    weights: Vec<f32>,
+    pixel_spread: f32,
+}
+
+impl<'a> Renderer<'a> {
+    /// Traces one round of samples through `pixel`, adding them to the weighted sums in
+    /// `framebuffer`.
+    fn trace_pixel(&mut self, framebuffer: &mut Framebuffer, stats: &mut PixelStats, pixel: usize) {
+        let film = &self.settings.film;
+        let (x, y) = film.frame_position(pixel);
+
+        for _ in 0..self.settings.samples_per_pixel {
+            let index = framebuffer.sample_counts[pixel];
+            self.sampler.start_sample(x as u32, y as u32, index);
+
+            let (offset, weight) = self.settings.filter.sample(self.sampler.get_2d());
+            let dx = (x as f32 + 0.5 + offset.0) / f32::from(film.width);
+            let dy = (y as f32 + 0.5 + offset.1) / f32::from(film.height);
+            let lens = self.sampler.get_2d();
+            let time = self.sampler.get_1d();
+            let wavelength = self.sampler.get_1d();
+
+            let wavelengths = if self.settings.spectral {
+                Some(spectrum::sample_wavelengths(wavelength))
+            } else {
+                None
+            };
+            let direction = self
+                .camera
+                .get_ray(dx, dy, lens, time)
+                .with_spread(self.pixel_spread)
+                .with_wavelengths(wavelengths);
+            let path =
+                generate_color_for_pixel(direction, self.world, (dx, dy), self.sampler.as_mut());
+
+            framebuffer.color[pixel] += path.color * weight;
+            framebuffer.alpha[pixel] += path.alpha * weight;
+            self.weights[pixel] += weight;
+            framebuffer.sample_counts[pixel] += 1;
+            stats.add(luminance(&path.color));
+
+            for buffer in framebuffer.aovs.iter_mut() {
+                buffer.add(pixel, &path, index as usize);
+            }
+        }
+    }
+}
+
+/// Traces every pixel of the crop of the film with the sampler of `settings`.
+pub fn render(camera: &Camera, world: &World, settings: &RenderSettings) -> Framebuffer {
+    let crop = settings.film.crop();
+    let pixels = crop.pixels();
+
+    let mut aovs = settings.aovs.clone();
+    if settings.denoise {
+        for guide in &[Aov::Depth, Aov::Normal, Aov::Albedo] {
+            if !aovs.contains(guide) {
+                aovs.push(*guide);
+            }
+        }
+    }
+
+    let mut framebuffer = Framebuffer {
+        width: crop.width,
+        height: crop.height,
+        origin: (crop.x, crop.y),
+        color: vec![vec3(0.0, 0.0, 0.0); pixels],
+        alpha: vec![0.0; pixels],
+        aovs: aovs
+            .iter()
+            .map(|&aov| AovBuffer::new(aov, pixels))
+            .collect(),
+        sample_counts: vec![0; pixels],
+    };
+    let mut stats = vec![PixelStats::default(); pixels];
+    let mut renderer = Renderer {
+        camera,
+        world,
+        settings,
+        sampler: settings.sampler.build(settings.samples_per_pixel),
+        weights: vec![0.0; pixels],
+        pixel_spread: camera.pixel_spread(settings.film.height),
+    };
+
+    for (pixel, stats) in stats.iter_mut().enumerate() {
+        renderer.trace_pixel(&mut framebuffer, stats, pixel);
+    }
+
+    // Further rounds only for the pixels that are still too noisy.
+    if let Some(adaptive) = settings.adaptive {
+        let round = settings.samples_per_pixel;
+        loop {
+            let mut traced = false;
+            for (pixel, stats) in stats.iter_mut().enumerate() {
+                if stats.display_error() > adaptive.noise_threshold
+                    && stats.count + round <= adaptive.max_samples
+                {
+                    renderer.trace_pixel(&mut framebuffer, stats, pixel);
+                    traced = true;
+                }
+            }
+            if !traced || round == 0 {
+                break;
+            }
+        }
+    }
+
+    for ((color, alpha), &weight) in framebuffer
+        .color
+        .iter_mut()
+        .zip(framebuffer.alpha.iter_mut())
+        .zip(&renderer.weights)
+    {
+        // Negative lobes can cancel out the few samples of a pixel.
+        if weight > 0.0 {
+            *color /= weight;
+            *alpha /= weight;
+        } else {
+            *color = vec3(0.0, 0.0, 0.0);
+            *alpha = 0.0;
+        }
+    }
+    for buffer in framebuffer.aovs.iter_mut() {
+        buffer.finish(&framebuffer.sample_counts);
+    }
+
+    if settings.denoise {
+        Denoiser::default()
+            .apply(&mut framebuffer)
+            .expect("the denoiser passes are always rendered");
+    }
+    framebuffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Point3;
+
+    use crate::background::Background;
+    use crate::geometric_objects::Sphere;
+    use crate::materials::Lambertian;
+    use crate::textures::Texture;
+
+    fn framebuffer(color: Vec<Vector3<f32>>, alpha: Vec<f32>) -> Framebuffer {
+        Framebuffer {
+            width: color.len(),
+            height: 1,
+            origin: (0, 0),
+            sample_counts: vec![1; color.len()],
+            color,
+            alpha,
+            aovs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn canvas_pixels_are_rgba_bytes_in_memory_order() {
+        let pixels = framebuffer(
+            vec![vec3(1.0, 0.25, 0.0), vec3(0.125, 0.125, 0.125)],
+            vec![1.0, 0.5],
+        )
+        .to_rgba8();
+
+        assert_eq!(pixels[0].to_le_bytes(), [255, 127, 0, 255]);
+        // Premultiplied colour comes out straight, then gamma corrected.
+        assert_eq!(pixels[1].to_le_bytes(), [127, 127, 127, 127]);
+    }
+
+    #[test]
+    fn canvas_pixels_saturate() {
+        let pixels = framebuffer(vec![vec3(4.0, -1.0, f32::NAN)], vec![1.0]).to_rgba8();
+        assert_eq!(pixels[0].to_le_bytes(), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn pixel_error_shrinks_with_samples() {
+        let mut constant = PixelStats::default();
+        let mut noisy = PixelStats::default();
+        assert!(constant.display_error().is_infinite());
+
+        for i in 0..100 {
+            constant.add(0.5);
+            noisy.add(if i % 2 == 0 { 0.25 } else { 0.75 });
+            if i == 9 {
+                assert!(noisy.display_error() > 0.05);
+            }
+        }
+        assert_eq!(constant.display_error(), 0.0);
+        // Standard error of 0.25 / 10 at a mean of 0.5, through the square root.
+        let expected = 0.25 * (100.0_f32 / 99.0).sqrt() / 10.0 / (2.0 * 0.5_f32.sqrt());
+        assert!((noisy.display_error() - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn adaptive_sampling_spends_samples_on_noisy_pixels() {
+        // A diffuse ball lit by a sky going from black to white, the background itself
+        // barely changes within a pixel.
+        let mut world = World::new();
+        world.add_object(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Lambertian {
+                texture: Texture::scalar(0.5),
+            },
+        )));
+        world.set_background(Background::gradient(
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 1.0, 1.0),
+        ));
+        let camera = Camera::new(
+            &Point3::new(0.0, 0.0, 1.0),
+            &Point3::new(0.0, 0.0, -1.0),
+            &vec3(0.0, 1.0, 0.0),
+            45.0,
+            1.0,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        );
+        let settings = RenderSettings {
+            film: Film::new(16, 16),
+            samples_per_pixel: 4,
+            sampler: SamplerKind::Independent,
+            filter: Filter::default(),
+            spectral: false,
+            aovs: Vec::new(),
+            denoise: false,
+            adaptive: Some(AdaptiveSampling {
+                noise_threshold: 0.01,
+                max_samples: 16,
+            }),
+        };
+        let framebuffer = render(&camera, &world, &settings);
+
+        let corner = framebuffer.sample_counts[0];
+        let center = framebuffer.sample_counts[8 * 16 + 8];
+        assert_eq!(corner, 4);
+        assert_eq!(center, 16);
+        assert!(framebuffer
+            .sample_counts
+            .iter()
+            .all(|&count| (4..=16).contains(&count) && count % 4 == 0));
+    }
+}
@@ -0,0 +1,611 @@
+use lazy_static::lazy_static;
+
+use crate::random;
+
+/// Largest `f32` below one, samples stay in `[0, 1)`.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Bases of the Halton dimensions, later dimensions cycle through them with a new scramble.
+const PRIMES: [u32; 16] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53];
+
+/// Side of the tiling blue noise mask.
+const MASK_SIZE: usize = 64;
+
+/// Source of the random numbers of every sample, one dimension at a time. The renderer
+/// takes the pixel position, lens position, time and wavelength in that order, then every
+/// bounce a pair for sampling the background followed by whatever the material needs to
+/// pick a lobe and a direction.
+pub trait Sampler {
+    /// Starts sample number `index` of the pixel at `x`, `y`, with the dimensions back at
+    /// the first one.
+    fn start_sample(&mut self, x: u32, y: u32, index: u32);
+
+    /// Next dimension, in `[0, 1)`.
+    fn get_1d(&mut self) -> f32;
+
+    /// Next two dimensions, stratified against each other where the sampler can.
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+/// Which `Sampler` a render uses.
+#[derive(Clone, Copy, PartialEq)]
+pub enum SamplerKind {
+    /// Plain random numbers.
+    Independent,
+    /// Jittered strata, correlated multi-jittered for pairs so any count works.
+    Stratified,
+    /// Owen scrambled Halton sequence.
+    Halton,
+    /// Owen scrambled Sobol sequence.
+    Sobol,
+    /// The same Sobol points in every pixel, shifted by a blue noise mask so the error
+    /// left over is blue noise too.
+    BlueNoise,
+}
+
+impl SamplerKind {
+    /// Sampler with the given name, the same one `name` gives.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let kind = match name.trim() {
+            "independent" => SamplerKind::Independent,
+            "stratified" => SamplerKind::Stratified,
+            "halton" => SamplerKind::Halton,
+            "sobol" => SamplerKind::Sobol,
+            "blue-noise" => SamplerKind::BlueNoise,
+            name => return Err(format!("unknown sampler '{}'", name)),
+        };
+        Ok(kind)
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::BlueNoise => "blue-noise",
+        }
+    }
+
+    /// Sampler for `samples_per_pixel` samples a round, stratified ones stratify within
+    /// every round.
+    pub fn build(self, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::default()),
+            SamplerKind::Sobol => Box::new(SobolSampler::default()),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::default()),
+        }
+    }
+}
+
+/// Integer hash with good avalanche (lowbias32 by Chris Wellons).
+#[inline]
+const fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+#[inline]
+const fn hash_combine(seed: u32, value: u32) -> u32 {
+    hash(
+        seed ^ value
+            .wrapping_add(0x9e37_79b9)
+            .wrapping_add(seed << 6)
+            .wrapping_add(seed >> 2),
+    )
+}
+
+#[inline]
+const fn pixel_seed(x: u32, y: u32) -> u32 {
+    hash_combine(hash(x), y)
+}
+
+/// Float in `[0, 1)` from the top 24 bits of `bits`.
+#[inline]
+fn to_unit(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Element `index` of a random permutation of `0..len` picked by `seed`, after Kensler,
+/// "Correlated Multi-Jittered Sampling" (2013).
+const fn permute(mut index: u32, len: u32, seed: u32) -> u32 {
+    let mut mask = len - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < len {
+            break;
+        }
+    }
+    (index.wrapping_add(seed)) % len
+}
+
+/// Random bijection of 32 bit integers in which every bit only depends on the bits below
+/// it (Laine and Karras 2011).
+#[inline]
+const fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+/// Owen scrambling of a base two fraction in `x`, after Burley, "Practical Hash-based Owen
+/// Scrambling" (2020).
+#[inline]
+const fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// First two dimensions of the Sobol sequence as base two fractions.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let mut y = 0;
+    let mut direction = 1 << 31;
+    for bit in 0..32 {
+        if (index >> bit) & 1 == 1 {
+            y ^= direction;
+        }
+        direction ^= direction >> 1;
+    }
+    (index.reverse_bits(), y)
+}
+
+/// Owen scrambled Sobol point of the pair of dimensions picked by `seed`. The index is
+/// scrambled as well, which shuffles the points so pairs with different seeds are not
+/// correlated.
+fn scrambled_sobol_2d(index: u32, seed: u32) -> (f32, f32) {
+    let index = nested_uniform_scramble(index, seed);
+    let (x, y) = sobol_2d(index);
+    (
+        to_unit(nested_uniform_scramble(x, hash_combine(seed, 0))),
+        to_unit(nested_uniform_scramble(y, hash_combine(seed, 1))),
+    )
+}
+
+/// Radical inverse of `index` in `base` with every digit permuted depending on the digits
+/// before it, which Owen scrambles the Halton sequence.
+fn scrambled_radical_inverse(base: u32, mut index: u32, seed: u32) -> f32 {
+    let inverse_base = 1.0 / f64::from(base);
+    let mut scale = inverse_base;
+    let mut prefix = seed;
+    let mut result = 0.0;
+    // Keep going past the last non-zero digit, the zeros get scrambled too.
+    while scale > 1e-8 {
+        let digit = index % base;
+        index /= base;
+        result += f64::from(permute(digit, base, hash(prefix))) * scale;
+        prefix = hash_combine(prefix, digit);
+        scale *= inverse_base;
+    }
+    (result as f32).min(ONE_MINUS_EPSILON)
+}
+
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, _x: u32, _y: u32, _index: u32) {}
+
+    fn get_1d(&mut self) -> f32 {
+        random().min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// Jittered sampling with as many strata as samples in a round. Single dimensions are
+/// split into that many intervals, pairs into a grid of exactly that many cells with
+/// correlated multi-jittering (Kensler 2013), so both coordinates are stratified on their
+/// own too. Prime counts get a single column, across which the multi-jittering still
+/// spreads the samples. Every round of samples and every dimension shuffles the strata
+/// differently.
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    /// Grid of `columns` by `rows` cells for pairs, as many as samples in a round.
+    columns: u32,
+    rows: u32,
+    seed: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        // The largest divisor up to the square root keeps the cells closest to square.
+        let columns = (1..=samples_per_pixel)
+            .take_while(|&columns| columns * columns <= samples_per_pixel)
+            .filter(|&columns| samples_per_pixel % columns == 0)
+            .last()
+            .unwrap_or(1);
+        StratifiedSampler {
+            samples_per_pixel,
+            columns,
+            rows: samples_per_pixel / columns,
+            seed: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Stratum of the current sample and the seed shuffling the strata of this dimension.
+    const fn next_stratum(&mut self) -> (u32, u32) {
+        let round = self.index / self.samples_per_pixel;
+        let seed = hash_combine(hash_combine(self.seed, self.dimension), round);
+        self.dimension += 1;
+        let stratum = permute(
+            self.index % self.samples_per_pixel,
+            self.samples_per_pixel,
+            seed,
+        );
+        (stratum, seed)
+    }
+
+    /// Offset within a stratum, different for every sample, dimension and `axis`.
+    fn jitter(&self, seed: u32, axis: u32) -> f32 {
+        to_unit(hash_combine(hash_combine(seed, self.index), axis))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.seed = pixel_seed(x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (stratum, seed) = self.next_stratum();
+        ((stratum as f32 + self.jitter(seed, 0)) / self.samples_per_pixel as f32)
+            .min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (stratum, seed) = self.next_stratum();
+        let (columns, rows) = (self.columns, self.rows);
+        let (column, row) = (stratum % columns, stratum / columns);
+
+        // The offset within the cell is itself stratified along the other axis.
+        let sub_x = permute(row, rows, seed.wrapping_mul(0x68bc_21eb));
+        let sub_y = permute(column, columns, seed.wrapping_mul(0x02e5_be93));
+        let x =
+            (column as f32 + (sub_x as f32 + self.jitter(seed, 0)) / rows as f32) / columns as f32;
+        let y = (row as f32 + (sub_y as f32 + self.jitter(seed, 1)) / columns as f32) / rows as f32;
+        (x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+    }
+}
+
+/// Halton sequence with a prime base per dimension, Owen scrambled with a different seed
+/// in every pixel so neighbouring pixels don't repeat the same points.
+#[derive(Default)]
+pub struct HaltonSampler {
+    seed: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.seed = pixel_seed(x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let base = PRIMES[self.dimension as usize % PRIMES.len()];
+        let seed = hash_combine(self.seed, self.dimension);
+        self.dimension += 1;
+        scrambled_radical_inverse(base, self.index, seed)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// Sobol sequence, every dimension or pair of them an Owen scrambled and shuffled copy of
+/// the first two Sobol dimensions.
+#[derive(Default)]
+pub struct SobolSampler {
+    seed: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.seed = pixel_seed(x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.get_2d().0
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let seed = hash_combine(self.seed, self.dimension);
+        self.dimension += 1;
+        scrambled_sobol_2d(self.index, seed)
+    }
+}
+
+lazy_static! {
+    /// Ranks of a void and cluster blue noise mask (Ulichney 1993), as values in `[0, 1)`.
+    static ref BLUE_NOISE: Vec<f32> = void_and_cluster(MASK_SIZE);
+}
+
+/// Blue noise mask of `size` by `size` pixels that tiles. Starting from a sparse evenly
+/// spread pattern, points are ranked by repeatedly taking away the one in the tightest
+/// cluster and then by filling in the largest void.
+fn void_and_cluster(size: usize) -> Vec<f32> {
+    let pixels = size * size;
+    let sigma = 1.5_f32;
+    // Gaussian energy by wrapped offset between two pixels.
+    let kernel: Vec<f32> = (0..pixels)
+        .map(|i| {
+            let (dx, dy) = (i % size, i / size);
+            let dx = dx.min(size - dx) as f32;
+            let dy = dy.min(size - dy) as f32;
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let update = |energy: &mut Vec<f32>, pixel: usize, sign: f32| {
+        let (px, py) = (pixel % size, pixel / size);
+        for (i, e) in energy.iter_mut().enumerate() {
+            let dx = (i % size + size - px) % size;
+            let dy = (i / size + size - py) % size;
+            *e += sign * kernel[dy * size + dx];
+        }
+    };
+    let tightest_cluster = |points: &[bool], energy: &[f32]| {
+        (0..pixels)
+            .filter(|&i| points[i])
+            .max_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap())
+            .unwrap()
+    };
+    let largest_void = |points: &[bool], energy: &[f32]| {
+        (0..pixels)
+            .filter(|&i| !points[i])
+            .min_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap())
+            .unwrap()
+    };
+
+    // Random initial pattern, hashed so the mask is the same every time.
+    let initial = pixels / 10;
+    let mut points = vec![false; pixels];
+    let mut energy = vec![0.0; pixels];
+    let mut seed = 0;
+    let mut placed = 0;
+    while placed < initial {
+        seed += 1;
+        let pixel = hash(seed) as usize % pixels;
+        if !points[pixel] {
+            points[pixel] = true;
+            update(&mut energy, pixel, 1.0);
+            placed += 1;
+        }
+    }
+
+    // Move points from clusters into voids until it is spread evenly.
+    for _ in 0..pixels {
+        let cluster = tightest_cluster(&points, &energy);
+        points[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+        let void = largest_void(&points, &energy);
+        points[void] = true;
+        update(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; pixels];
+    let (mut remaining, mut remaining_energy) = (points.clone(), energy.clone());
+    for rank in (0..initial).rev() {
+        let cluster = tightest_cluster(&remaining, &remaining_energy);
+        remaining[cluster] = false;
+        update(&mut remaining_energy, cluster, -1.0);
+        ranks[cluster] = rank;
+    }
+    for rank in initial..pixels {
+        let void = largest_void(&points, &energy);
+        points[void] = true;
+        update(&mut energy, void, 1.0);
+        ranks[void] = rank;
+    }
+
+    ranks
+        .into_iter()
+        .map(|rank| (rank as f32 + 0.5) / pixels as f32)
+        .collect()
+}
+
+/// Owen scrambled Sobol points shared by every pixel, each dimension shifted by the value
+/// of a blue noise mask at the pixel (Georgiev and Fajardo 2016). Nearby pixels then
+/// get points far apart, pushing the error to high frequencies where it is less visible
+/// and easier to filter. Every dimension reads the mask at a different offset.
+#[derive(Default)]
+pub struct BlueNoiseSampler {
+    x: u32,
+    y: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl BlueNoiseSampler {
+    fn shift(&self, dimension: u32) -> f32 {
+        let offset = hash(dimension.wrapping_add(1));
+        let x = (self.x as usize + (offset & 0xffff) as usize) % MASK_SIZE;
+        let y = (self.y as usize + (offset >> 16) as usize) % MASK_SIZE;
+        BLUE_NOISE[y * MASK_SIZE + x]
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.x = x;
+        self.y = y;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.get_2d().0
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (x, y) = scrambled_sobol_2d(self.index, hash(self.dimension));
+        let shift = (
+            self.shift(2 * self.dimension),
+            self.shift(2 * self.dimension + 1),
+        );
+        self.dimension += 1;
+        (
+            (x + shift.0).fract().min(ONE_MINUS_EPSILON),
+            (y + shift.1).fract().min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// First pair of dimensions of samples `first..first + count` of a pixel.
+    fn points(sampler: &mut dyn Sampler, first: u32, count: u32) -> Vec<(f32, f32)> {
+        (first..first + count)
+            .map(|index| {
+                sampler.start_sample(3, 5, index);
+                sampler.get_2d()
+            })
+            .collect()
+    }
+
+    /// Whether exactly one of `points` falls into every cell of a `columns` by `rows` grid.
+    fn one_per_cell(points: &[(f32, f32)], columns: u32, rows: u32) -> bool {
+        let mut counts = vec![0; (columns * rows) as usize];
+        for &(x, y) in points {
+            assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+            let cell = (y * rows as f32) as u32 * columns + (x * columns as f32) as u32;
+            counts[cell as usize] += 1;
+        }
+        counts.iter().all(|&count| count == 1)
+    }
+
+    #[test]
+    fn stratified_pairs_cover_every_cell_once() {
+        for &spp in &[1, 2, 3, 4, 5, 7, 8, 9, 12, 16] {
+            let mut sampler = StratifiedSampler::new(spp);
+            let (columns, rows) = (sampler.columns, sampler.rows);
+            assert_eq!(columns * rows, spp);
+            for round in 0..3 {
+                let points = points(&mut sampler, round * spp, spp);
+                assert!(one_per_cell(&points, columns, rows), "{} samples", spp);
+                // Each coordinate on its own is stratified as well.
+                assert!(one_per_cell(&points, spp, 1), "{} samples", spp);
+                assert!(one_per_cell(&points, 1, spp), "{} samples", spp);
+            }
+        }
+        assert_eq!(
+            (
+                StratifiedSampler::new(8).columns,
+                StratifiedSampler::new(8).rows
+            ),
+            (2, 4)
+        );
+    }
+
+    #[test]
+    fn stratified_dimensions_cover_every_interval_once() {
+        let spp = 6;
+        let mut sampler = StratifiedSampler::new(spp);
+        let mut strata = vec![vec![0; spp as usize]; 4];
+        for index in 0..spp {
+            sampler.start_sample(0, 0, index);
+            for stratum in strata.iter_mut() {
+                stratum[(sampler.get_1d() * spp as f32) as usize] += 1;
+            }
+        }
+        assert!(strata.iter().flatten().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn sobol_points_are_a_net() {
+        let mut sampler = SobolSampler::default();
+        let points = points(&mut sampler, 0, 16);
+        for &(columns, rows) in &[(16, 1), (8, 2), (4, 4), (2, 8), (1, 16)] {
+            assert!(one_per_cell(&points, columns, rows), "{}x{}", columns, rows);
+        }
+    }
+
+    #[test]
+    fn halton_dimensions_stratify_in_their_base() {
+        let mut sampler = HaltonSampler::default();
+        // Base 2 for the first dimension, base 3 for the second.
+        let points = points(&mut sampler, 0, 18);
+        assert!(one_per_cell(&points[..16], 16, 1));
+        assert!(one_per_cell(&points[..9], 1, 9));
+        assert!(one_per_cell(&points[..18], 2, 9));
+    }
+
+    #[test]
+    fn samplers_differ_between_pixels() {
+        let kinds = [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+            SamplerKind::BlueNoise,
+        ];
+        for &kind in &kinds {
+            let mut sampler = kind.build(4);
+            sampler.start_sample(0, 0, 0);
+            let a = sampler.get_2d();
+            sampler.start_sample(1, 0, 0);
+            let b = sampler.get_2d();
+            assert!(a != b, "{}", kind.name());
+            assert!(SamplerKind::parse(kind.name()) == Ok(kind));
+        }
+    }
+
+    #[test]
+    fn blue_noise_mask_ranks_every_pixel_once() {
+        let size = 16;
+        let mut ranks: Vec<usize> = void_and_cluster(size)
+            .iter()
+            .map(|&value| (value * (size * size) as f32) as usize)
+            .collect();
+        ranks.sort_unstable();
+        assert!(ranks.iter().copied().eq(0..size * size));
+    }
+}
@@ -1,62 +1,597 @@
 use cascade::cascade;
 use cgmath::prelude::*;
 use cgmath::{vec3, Point3, Vector3};
-use std::{f32, u16, usize};
+use wbg_rand::{Rng, SeedableRng, XorShiftRng};
 
 use crate::{
+    assets,
+    background::Background,
     camera::Camera,
     geometric_objects::{MovingSphere, Sphere, Rect},
-    materials::{Material, Texture},
-    random,
+    lights::{DirectionalLight, IesLight, IesProfile, PointLight, SpotLight},
+    materials::{
+        microfacet::{Distribution, MicrofacetDistribution},
+        ConductorIor, Dielectric, DiffuseLight, Lambertian, Layered, Metallic, Principled,
+        RoughConductor, RoughDielectric, ShadowCatcher, Subsurface, ThinFilm,
+    },
+    media::{
+        ConstantMedium, DensityField, Fog, HeterogeneousMedium, PhaseFunction, PhaseMaterial,
+    },
+    textures::{CheckerMode, Texture},
+    sky::PhysicalSky,
     world::World,
 };
 
+/// Scene called `name`, one of those in the scene picker of the page. `seed` picks the
+/// spheres of the random scene, the other scenes are the same whatever the seed.
+pub fn get_scene(
+    name: &str,
+    canvas_width: u16,
+    canvas_height: u16,
+    seed: u32,
+) -> Result<(Camera, World), String> {
+    match name {
+        "predefined" => Ok(get_predefined_scene(canvas_width, canvas_height)),
+        "random" => Ok(get_random_scene(canvas_width, canvas_height, 20, seed)),
+        "materials" => Ok(get_materials_scene(canvas_width, canvas_height)),
+        "media" => Ok(get_media_scene(canvas_width, canvas_height)),
+        "lights" => get_lights_scene(canvas_width, canvas_height),
+        "environment" => Ok(get_environment_scene(canvas_width, canvas_height)),
+        "sky" => Ok(get_sky_scene(canvas_width, canvas_height)),
+        "file" => match assets::scene_description() {
+            Some(description) => description.build(canvas_width, canvas_height),
+            None => Err("no scene file loaded".to_string()),
+        },
+        name => Err(format!("unknown scene '{}'", name)),
+    }
+}
+
+/// Pinhole camera looking from `look_from` at `look_at` with a shutter open from 0 to 1.
+fn look_at_camera(
+    canvas_width: u16,
+    canvas_height: u16,
+    look_from: Point3<f32>,
+    look_at: Point3<f32>,
+    v_fov: f32,
+) -> Camera {
+    Camera::new(
+        &look_from,
+        &look_at,
+        &vec3(0.0, 1.0, 0.0),
+        v_fov,
+        f32::from(canvas_width) / f32::from(canvas_height),
+        0.0,
+        (look_from - look_at).magnitude(),
+        0.0,
+        1.0,
+    )
+}
+
+/// Row of spheres showing off the materials and texture nodes under an area light.
+pub fn get_materials_scene(canvas_width: u16, canvas_height: u16) -> (Camera, World) {
+    let mut world = World::new();
+
+    // Checker tiles projected along the axes, broken up by noise.
+    let tiles = Texture::Triplanar {
+        texture: Box::new(Texture::Checkerboard {
+            left: Box::new(Texture::scalar(0.8)),
+            right: Box::new(Texture::scalar(0.3)),
+            scale: vec3(1.0, 1.0, 0.0),
+            offset: vec3(0.0, 0.0, 0.0),
+            mode: CheckerMode::Uv,
+        }),
+        scale: 2.0,
+        sharpness: 4.0,
+    };
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.35, -1.0),
+        1000.0,
+        Lambertian {
+            texture: Texture::Multiply {
+                a: Box::new(tiles),
+                b: Box::new(Texture::Mix {
+                    a: Box::new(Texture::scalar(1.0)),
+                    b: Box::new(Texture::scalar(0.7)),
+                    factor_texture: Box::new(Texture::Noise),
+                }),
+            },
+        },
+    )));
+    world.add_object(Box::new(Rect::new(
+        -3.0,
+        3.0,
+        -2.0,
+        0.0,
+        2.5,
+        DiffuseLight::new(Texture::scalar(2.0)),
+    )));
+
+    // Marble from noise run through a colour ramp.
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(-2.4, 0.0, -1.0),
+        0.35,
+        Lambertian {
+            texture: Texture::ColorRamp {
+                input: Box::new(Texture::Noise),
+                stops: vec![
+                    (0.2, Point3::new(0.1, 0.1, 0.15)),
+                    (0.5, Point3::new(0.8, 0.75, 0.7)),
+                    (0.9, Point3::new(0.95, 0.95, 0.95)),
+                ],
+            },
+        },
+    )));
+
+    // Brushed gold, tinted by its conductor Fresnel.
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(-1.6, 0.0, -1.0),
+        0.35,
+        Metallic {
+            albedo: Texture::scalar(1.0),
+            roughness: 0.2,
+            ior: Some(ConductorIor::GOLD),
+            thin_film: None,
+        },
+    )));
+
+    // GGX copper and frosted glass.
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(-0.8, 0.0, -1.0),
+        0.35,
+        RoughConductor {
+            albedo: Texture::scalar(1.0),
+            ior: Some(ConductorIor::COPPER),
+            thin_film: None,
+            distribution: MicrofacetDistribution::from_roughness(Distribution::Ggx, 0.4),
+        },
+    )));
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.35,
+        RoughDielectric {
+            ior: 1.5,
+            distribution: MicrofacetDistribution::from_roughness(Distribution::Beckmann, 0.3),
+        },
+    )));
+
+    // Car paint: a sheen over blue, under a clearcoat.
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(0.8, 0.0, -1.0),
+        0.35,
+        Principled {
+            base_color: Texture::Constant {
+                color: Point3::new(0.05, 0.15, 0.6),
+            },
+            roughness: Texture::scalar(0.4),
+            sheen: Texture::scalar(0.5),
+            clearcoat: Texture::scalar(1.0),
+            ..Principled::default()
+        },
+    )));
+
+    // Anodized metal, the oxide layer varying in thickness.
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(1.6, 0.0, -1.0),
+        0.35,
+        Metallic {
+            albedo: Texture::scalar(0.9),
+            roughness: 0.05,
+            ior: None,
+            thin_film: Some(ThinFilm {
+                thickness: Texture::Mix {
+                    a: Box::new(Texture::scalar(250.0)),
+                    b: Box::new(Texture::scalar(450.0)),
+                    factor_texture: Box::new(Texture::Noise),
+                },
+                ior: Texture::scalar(1.8),
+            }),
+        },
+    )));
+
+    // Lacquered wood: a rough clear coat over a grain pattern.
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(2.4, 0.0, -1.0),
+        0.35,
+        Layered {
+            distribution: Some(MicrofacetDistribution::from_roughness(
+                Distribution::Ggx,
+                0.15,
+            )),
+            ..Layered::new(
+                Lambertian {
+                    texture: Texture::ColorRamp {
+                        input: Box::new(Texture::Noise),
+                        stops: vec![
+                            (0.3, Point3::new(0.25, 0.1, 0.04)),
+                            (0.7, Point3::new(0.55, 0.3, 0.12)),
+                        ],
+                    },
+                },
+                1.5,
+            )
+        },
+    )));
+
+    // Jade behind the row, light scattering through it tinted green.
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(-0.4, 0.1, -2.2),
+        0.45,
+        Subsurface {
+            albedo: Texture::Constant {
+                color: Point3::new(0.4, 0.85, 0.5),
+            },
+            mean_free_path: vec3(0.15, 0.3, 0.2),
+            ior: 1.6,
+            anisotropy: 0.0,
+        },
+    )));
+
+    let camera = look_at_camera(
+        canvas_width,
+        canvas_height,
+        Point3::new(0.0, 1.2, 5.5),
+        Point3::new(0.0, 0.0, -1.0),
+        35.0,
+    );
+    (camera, world)
+}
+
+/// Box the voxel grid loaded from the page fills in the media scene.
+pub const VOXEL_GRID_MIN: Point3<f32> = Point3::new(0.25, -0.45, -1.45);
+pub const VOXEL_GRID_MAX: Point3<f32> = Point3::new(1.15, 0.45, -0.55);
+
+/// Smoke and fog under an area light. The cloud on the right is the voxel grid loaded
+/// from the page, or noise until there is one.
+pub fn get_media_scene(canvas_width: u16, canvas_height: u16) -> (Camera, World) {
+    let mut world = World::new();
+
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.5, -1.0),
+        1000.0,
+        Lambertian {
+            texture: Texture::scalar(0.5),
+        },
+    )));
+    world.add_object(Box::new(Rect::new(
+        -1.0,
+        1.0,
+        -2.0,
+        0.0,
+        2.0,
+        DiffuseLight::new(Texture::Constant {
+            color: Point3::new(4.0, 3.6, 3.0),
+        }),
+    )));
+
+    // A ball of forward scattering smoke.
+    world.add_object(Box::new(ConstantMedium::new(
+        Sphere::new(Point3::new(-0.6, 0.0, -1.0), 0.5, Dielectric::new(1.0)),
+        4.0,
+        Texture::Constant {
+            color: Point3::new(0.8, 0.85, 0.9),
+        },
+        PhaseFunction::HenyeyGreenstein(0.5),
+    )));
+
+    let field = match assets::voxel_grid() {
+        Some(grid) => DensityField::Grid(grid),
+        None => DensityField::Noise {
+            scale: 3.0,
+            octaves: 4,
+        },
+    };
+    world.add_object(Box::new(HeterogeneousMedium::new(
+        Sphere::new(
+            Point3::from_vec((VOXEL_GRID_MIN.to_vec() + VOXEL_GRID_MAX.to_vec()) * 0.5),
+            0.8,
+            Dielectric::new(1.0),
+        ),
+        field,
+        8.0,
+        PhaseMaterial {
+            albedo: Texture::scalar(0.95),
+            phase: PhaseFunction::HenyeyGreenstein(0.3),
+            emission: None,
+        },
+    )));
+
+    // Thin haze over everything.
+    world.set_fog(Fog {
+        density: 0.04,
+        max_distance: 20.0,
+        material: PhaseMaterial {
+            albedo: Texture::scalar(0.9),
+            phase: PhaseFunction::Isotropic,
+            emission: None,
+        },
+    });
+
+    let camera = look_at_camera(
+        canvas_width,
+        canvas_height,
+        Point3::new(0.0, 0.6, 4.0),
+        Point3::new(0.0, 0.0, -1.0),
+        35.0,
+    );
+    (camera, world)
+}
+
+/// Downlight throwing most of its light 30 to 40 degrees off the nadir, for IES lights
+/// when no IES file has been loaded.
+const DEFAULT_IES_PROFILE: &str = "IESNA:LM-63-2002
+[TEST] built in downlight
+TILT=NONE
+1 -1 1 10 1 1 2 0 0 0
+1 1 40
+0 10 20 30 40 50 60 70 80 90
+0
+800 900 1100 1300 1200 700 300 100 30 0
+";
+
+/// The IES profile loaded from the page, or the built in downlight without one.
+pub fn ies_profile() -> Result<IesProfile, String> {
+    match assets::ies_profile() {
+        Some(profile) => Ok((*profile).clone()),
+        None => IesProfile::parse(DEFAULT_IES_PROFILE),
+    }
+}
+
+/// Objects lit only by lights without geometry: a point light, a spot, moonlight and a
+/// luminaire with a measured IES profile.
+pub fn get_lights_scene(
+    canvas_width: u16,
+    canvas_height: u16,
+) -> Result<(Camera, World), String> {
+    let mut world = World::new();
+
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.5, -1.0),
+        1000.0,
+        Lambertian {
+            texture: Texture::scalar(0.6),
+        },
+    )));
+    // Back wall to catch the pattern of the IES light.
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1003.0),
+        1000.0,
+        Lambertian {
+            texture: Texture::scalar(0.6),
+        },
+    )));
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(-1.2, -0.1, -1.0),
+        0.4,
+        Lambertian {
+            texture: Texture::Constant {
+                color: Point3::new(0.7, 0.2, 0.2),
+            },
+        },
+    )));
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(0.0, -0.1, -1.0),
+        0.4,
+        Metallic {
+            albedo: Texture::scalar(0.9),
+            roughness: 0.1,
+            ior: None,
+            thin_film: None,
+        },
+    )));
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(1.2, -0.1, -1.0),
+        0.4,
+        Lambertian {
+            texture: Texture::Constant {
+                color: Point3::new(0.2, 0.4, 0.7),
+            },
+        },
+    )));
+
+    world.set_background(Background::Solid(vec3(0.0, 0.0, 0.0)));
+    world.add_light(Box::new(PointLight {
+        position: Point3::new(-1.6, 0.8, 0.2),
+        intensity: vec3(1.2, 0.9, 0.6),
+    }));
+    world.add_light(Box::new(SpotLight {
+        position: Point3::new(0.0, 2.2, -0.4),
+        direction: vec3(0.0, -2.3, -0.6),
+        intensity: vec3(6.0, 6.0, 6.0),
+        cone_angle: 20f32.to_radians(),
+        falloff_start: 14f32.to_radians(),
+    }));
+    world.add_light(Box::new(DirectionalLight {
+        direction: vec3(0.4, -1.0, -0.6),
+        irradiance: vec3(0.05, 0.07, 0.12),
+        angular_diameter: 0.02,
+    }));
+
+    world.add_light(Box::new(IesLight::new(
+        Point3::new(1.4, 1.2, -2.4),
+        vec3(0.0, -1.0, 0.0),
+        vec3(0.0, 0.0, 1.0),
+        ies_profile()?,
+        vec3(1.0, 0.95, 0.85),
+    )));
+
+    let camera = look_at_camera(
+        canvas_width,
+        canvas_height,
+        Point3::new(0.0, 0.8, 4.0),
+        Point3::new(0.0, 0.0, -1.0),
+        40.0,
+    );
+    Ok((camera, world))
+}
+
+/// Shiny objects lit only by their surroundings: the environment map loaded from the page,
+/// or a soft studio gradient without one. A backplate loaded from the page shows behind
+/// them, the ground only catching their shadows so they sit in the photo.
+pub fn get_environment_scene(canvas_width: u16, canvas_height: u16) -> (Camera, World) {
+    let mut world = World::new();
+
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.5, -1.0),
+        1000.0,
+        ShadowCatcher {
+            base: Lambertian {
+                texture: Texture::scalar(0.5),
+            },
+        },
+    )));
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(-1.1, 0.0, -1.0),
+        0.5,
+        Metallic {
+            albedo: Texture::scalar(0.95),
+            roughness: 0.0,
+            ior: None,
+            thin_film: None,
+        },
+    )));
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        Dielectric::new(1.5),
+    )));
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(1.1, 0.0, -1.0),
+        0.5,
+        Principled {
+            base_color: Texture::Constant {
+                color: Point3::new(0.8, 0.3, 0.1),
+            },
+            roughness: Texture::scalar(0.25),
+            ..Principled::default()
+        },
+    )));
+
+    let lighting = match assets::environment_map() {
+        Some(map) => Background::Environment((*map).clone()),
+        None => Background::gradient3(
+            vec3(0.2, 0.2, 0.2),
+            vec3(0.9, 0.9, 0.85),
+            vec3(0.4, 0.5, 0.7),
+        ),
+    };
+    world.set_background(match assets::backplate() {
+        Some(image) => Background::Backplate {
+            image: (*image).clone(),
+            lighting: Box::new(lighting),
+        },
+        None => lighting,
+    });
+
+    let camera = look_at_camera(
+        canvas_width,
+        canvas_height,
+        Point3::new(0.0, 0.5, 3.0),
+        Point3::new(0.0, 0.0, -1.0),
+        40.0,
+    );
+    (camera, world)
+}
+
+/// Late afternoon daylight from the analytic sky, the low sun casting long shadows.
+pub fn get_sky_scene(canvas_width: u16, canvas_height: u16) -> (Camera, World) {
+    let mut world = World::new();
+
+    world.add_object(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.5, -1.0),
+        1000.0,
+        Lambertian {
+            texture: Texture::Constant {
+                color: Point3::new(0.35, 0.3, 0.25),
+            },
+        },
+    )));
+    for (i, &color) in [
+        Point3::new(0.8, 0.8, 0.8),
+        Point3::new(0.7, 0.15, 0.1),
+        Point3::new(0.1, 0.3, 0.6),
+    ]
+    .iter()
+    .enumerate()
+    {
+        world.add_object(Box::new(Sphere::new(
+            Point3::new(-1.2 + 1.2 * i as f32, 0.0, -1.0 - 0.8 * i as f32),
+            0.5,
+            Lambertian {
+                texture: Texture::Constant { color },
+            },
+        )));
+    }
+
+    world.set_sky(
+        PhysicalSky::new(
+            15f32.to_radians(),
+            -60f32.to_radians(),
+            3.0,
+            vec3(0.3, 0.28, 0.25),
+        )
+        .with_intensity(0.3),
+    );
+
+    let camera = look_at_camera(
+        canvas_width,
+        canvas_height,
+        Point3::new(0.0, 0.3, 3.5),
+        Point3::new(0.0, 0.3, -1.0),
+        45.0,
+    );
+    (camera, world)
+}
+
 pub fn get_predefined_scene(canvas_width: u16, canvas_height: u16) -> (Camera, World) {
     let world = cascade! {
         World::new();
         ..add_object(Box::new(Sphere::new(
         Point3::new(0.0, -1000.5, -1.0),
         1000.0,
-        Material::Lambertian { texture: Texture::Checkerboard{
+        Lambertian { texture: Texture::Checkerboard{
             left: Box::new(Texture::Constant{
                 color: Point3::new(0.2, 0.3, 0.1)}),
             right: Box::new(Texture::Constant{
-                color: Point3::new(0.9, 0.9, 0.9)})
+                color: Point3::new(0.9, 0.9, 0.9)}),
+            // flat in y so the cells don't bend with the curvature of the ground sphere
+            scale: vec3(3.2, 0.0, 3.2),
+            offset: vec3(0.0, 0.0, 0.0),
+            mode: CheckerMode::Solid,
         }},
         )));
 
         ..add_object(Box::new(Sphere::new(
         Point3::new(0.0, 0.1, -1.0),
         0.6,
-        Material::Lambertian {
+        Lambertian {
             texture: Texture::Constant{
                 color: Point3::new(0.9, 0.1, 0.2)
                 }})));
     ..add_object(Box::new(Sphere::new(
         Point3::new(1.1, 0.0, -1.0),
         0.5,
-        Material::Dielectric{refractive_index: 1.7}
+        Dielectric::new(1.7)
     )));
     ..add_object(Box::new(Sphere::new(
         Point3::new(-0.95, 0.5, -1.0),
         0.45,
-        Material::Lambertian{ texture: Texture::Noise }
+        Lambertian{ texture: Texture::Noise }
     )));
     ..add_object(Box::new(Sphere::new(
         Point3::new(-1.2, -0.2, -1.0),
         0.3,
-        Material::Lambertian {
+        Lambertian {
             texture: Texture::Constant{
                 color: Point3::new(0.9, 0.9, 0.2)
         }},
     )));
     ..add_object(Box::new(MovingSphere::new(
         Point3::new(0.6, -0.1, 0.1),
-        Point3::new(0.6, -0.1 + (0.35 * random()), 0.1),
+        Point3::new(0.6, 0.1, 0.1),
         0.0,
         1.0,
         0.2,
-        Material::Lambertian {
+        Lambertian {
             texture: Texture::Constant{
                 color: Point3::new(0.25, 0.45, 0.8)
         }},
@@ -64,35 +599,32 @@ pub fn get_predefined_scene(canvas_width: u16, canvas_height: u16) -> (Camera, W
     ..add_object(Box::new(Sphere::new(
         Point3::new(-0.6, -0.30, 0.4),
         0.20,
-          Material::Metallic {
-            r: 0.8,
-            g: 0.8,
-            b: 0.8,
+          Metallic {
+            albedo: Texture::Constant {
+                color: Point3::new(0.8, 0.8, 0.8)
+            },
+            roughness: 0.5,
+            ior: None,
+            thin_film: None,
         },
     )));
    ..add_object(Box::new(Rect::new(-1.7, -0.7, -0.5, 0.5, 0.9,
-          Material::DiffuseLight{
-              texture: Texture::Constant {
-                  color: Point3::new(1.0, 1.0, 1.0)
-              }
-          }
+          DiffuseLight::new(Texture::Constant {
+              color: Point3::new(1.0, 1.0, 1.0)
+          })
     )));
    ..add_object(Box::new(Rect::new(-0.5, 0.5, -0.5, 0.5, 0.9,
-          Material::DiffuseLight{
-              texture: Texture::Constant {
-                  color: Point3::new(1.0, 1.0, 1.0)
-              }
-          }
+          DiffuseLight::new(Texture::Constant {
+              color: Point3::new(1.0, 1.0, 1.0)
+          })
     )));
     ..add_object(Box::new(Rect::new(0.7, 1.7, -0.5, 0.5, 0.9,
-          Material::DiffuseLight{
-              texture: Texture::Constant {
-                  color: Point3::new(1.0, 1.0, 1.0)
-              }
-          }
+          DiffuseLight::new(Texture::Constant {
+              color: Point3::new(1.0, 1.0, 1.0)
+          })
     )));
     // ..add_object(Box::new(Rect::new(-10.0, 10.0, -10.0, 10.0, 0.91,
-    //       Material::Lambertian{
+    //       Lambertian{
     //           texture: Texture::Constant {
     //               color: Point3::new(0.1, 0.1, 0.1)
     //           }
@@ -125,7 +657,10 @@ pub fn get_random_scene(
     canvas_width: u16,
     canvas_height: u16,
     number_of_spheres: usize,
+    seed: u32,
 ) -> (Camera, World) {
+    let mut rng = XorShiftRng::from_seed([seed, 0x9e37_79b9, 0x7f4a_7c15, 0xf39c_c060]);
+    let mut random = || rng.gen::<f32>();
     let (r, g, b) = (random(), random(), random());
 
     let centre_of_the_world = Point3::new(0.0, -1000.5, -1.0);
@@ -135,7 +670,7 @@ pub fn get_random_scene(
         ..add_object(Box::new(Sphere::new(
         centre_of_the_world,
         1000.0,
-        Material::Lambertian { texture: Texture::Constant{ color: Point3::new(r, g, b) }}
+        Lambertian { texture: Texture::Constant{ color: Point3::new(r, g, b) }}
         )));
     };
 
@@ -146,7 +681,7 @@ pub fn get_random_scene(
         world.add_object(Box::new(Sphere::new(
             Point3::new(direction.x, direction.y, direction.z),
             radius,
-            Material::Lambertian {
+            Lambertian {
                 texture: Texture::Constant {
                     color: Point3::new(random(), random(), random()),
                 },
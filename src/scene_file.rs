@@ -0,0 +1,802 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+
+use crate::{
+    background::Background,
+    camera::Camera,
+    geometric_objects::{MovingSphere, Rect, Sphere},
+    lights::{DirectionalLight, IesLight, PointLight, SpotLight},
+    materials::{
+        microfacet::{Distribution, MicrofacetDistribution},
+        ConductorIor, Dielectric, DiffuseLight, Lambertian, Material, Metallic, Principled,
+        RoughConductor, RoughDielectric,
+    },
+    scene::ies_profile,
+    spectrum::Ior,
+    textures::{CheckerMode, Texture},
+    world::World,
+};
+
+/// Expression of a scene file: a number, a name or a parenthesised list.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f32),
+    Symbol(String),
+    List(Vec<Expr>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Number(value) => write!(f, "{}", value),
+            Expr::Symbol(name) => write!(f, "{}", name),
+            Expr::List(items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Reads the expressions of `text`. A `;` starts a comment running to the end of the line.
+pub fn parse(text: &str) -> Result<Vec<Expr>, String> {
+    let mut lists: Vec<Vec<Expr>> = vec![Vec::new()];
+    for (number, line) in text.lines().enumerate() {
+        let code = line.split(';').next().unwrap_or("");
+        let spaced = code.replace('(', " ( ").replace(')', " ) ");
+        for token in spaced.split_whitespace() {
+            let expr = match token {
+                "(" => {
+                    lists.push(Vec::new());
+                    continue;
+                }
+                ")" if lists.len() > 1 => Expr::List(lists.pop().unwrap()),
+                ")" => return Err(format!("line {}: unexpected ')'", number + 1)),
+                token if token.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c)) => {
+                    let value = token
+                        .parse::<f32>()
+                        .map_err(|_| format!("line {}: bad number '{}'", number + 1, token))?;
+                    Expr::Number(value)
+                }
+                token => Expr::Symbol(token.to_string()),
+            };
+            lists.last_mut().unwrap().push(expr);
+        }
+    }
+    if lists.len() > 1 {
+        return Err("scene file ended before a closing ')'".to_string());
+    }
+    Ok(lists.pop().unwrap())
+}
+
+/// Scene read from a scene file, built into a new camera and world for every render.
+///
+/// Each top level list defines a texture or material by name, adds an object or a light or
+/// sets the camera or background. Angles are in degrees. Their settings are `(key values...)` lists, and a texture can be a
+/// grey level, three RGB numbers, the name of a texture defined earlier or a texture node:
+///
+/// ```text
+/// (texture tiles (checker (left 0.8) (right 0.3) (scale 2 2 2)))
+/// (material floor (lambertian (texture (multiply (a tiles) (b (mix (a 1) (b 0.7) (factor noise)))))))
+/// (sphere (center 0 -1000 0) (radius 1000) (material floor))
+/// (spot-light (position 0 3 0) (direction 0 -1 0) (intensity 8) (cone-angle 25))
+/// (camera (from 0 1 4) (at 0 0 0) (fov 40))
+/// ```
+pub struct SceneDescription {
+    forms: Vec<Expr>,
+}
+
+impl SceneDescription {
+    /// Parses `text` and builds its scene once, so mistakes show up when the file is loaded
+    /// rather than at render time.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let description = SceneDescription {
+            forms: parse(text)?,
+        };
+        description.build(1, 1)?;
+        Ok(description)
+    }
+
+    pub fn build(&self, canvas_width: u16, canvas_height: u16) -> Result<(Camera, World), String> {
+        let aspect = f32::from(canvas_width) / f32::from(canvas_height);
+        let mut scope = Scope::default();
+        let mut world = World::new();
+        let mut camera = None;
+        for form in &self.forms {
+            let (head, rest) = split(form)?;
+            scope
+                .add(head, rest, aspect, &mut world, &mut camera)
+                .map_err(|error| format!("in ({} ...): {}", head, error))?;
+        }
+        let camera = camera.ok_or_else(|| "scene file has no camera".to_string())?;
+        Ok((camera, world))
+    }
+}
+
+/// Textures and materials named so far while building a scene.
+#[derive(Default)]
+struct Scope {
+    /// Textures are not `Clone`, so each use of a name builds its definition again.
+    textures: HashMap<String, Vec<Expr>>,
+    /// Objects using a named material share it, and with it its material ID.
+    materials: HashMap<String, Rc<dyn Material>>,
+}
+
+impl Scope {
+    fn add(
+        &mut self,
+        head: &str,
+        rest: &[Expr],
+        aspect: f32,
+        world: &mut World,
+        camera: &mut Option<Camera>,
+    ) -> Result<(), String> {
+        match head {
+            "texture" | "material" => {
+                let (name, values) = match rest.split_first() {
+                    Some((Expr::Symbol(name), values)) => (name, values),
+                    _ => return Err(format!("expected a name after {}", head)),
+                };
+                if self.textures.contains_key(name) || self.materials.contains_key(name) {
+                    return Err(format!("'{}' is defined twice", name));
+                }
+                if head == "texture" {
+                    self.texture(values)?;
+                    self.textures.insert(name.clone(), values.to_vec());
+                } else {
+                    let material = self.material(values)?;
+                    self.materials.insert(name.clone(), material);
+                }
+            }
+            "sphere" => {
+                let node = Node::new(head, rest, &["center", "radius", "material"])?;
+                world.add_object(Box::new(Sphere::new(
+                    node.point("center")?,
+                    number(node.required("radius")?)?,
+                    self.material(node.required("material")?)?,
+                )));
+            }
+            "moving-sphere" => {
+                let keys = ["center-start", "center-end", "radius", "material"];
+                let node = Node::new(head, rest, &keys)?;
+                world.add_object(Box::new(MovingSphere::new(
+                    node.point("center-start")?,
+                    node.point("center-end")?,
+                    0.0,
+                    1.0,
+                    number(node.required("radius")?)?,
+                    self.material(node.required("material")?)?,
+                )));
+            }
+            "rect" => {
+                let node = Node::new(head, rest, &["x", "z", "y", "material"])?;
+                let x = numbers(node.required("x")?, 2)?;
+                let z = numbers(node.required("z")?, 2)?;
+                world.add_object(Box::new(Rect::new(
+                    x[0],
+                    x[1],
+                    z[0],
+                    z[1],
+                    number(node.required("y")?)?,
+                    self.material(node.required("material")?)?,
+                )));
+            }
+            "point-light" => {
+                let node = Node::new(head, rest, &["position", "intensity"])?;
+                world.add_light(Box::new(PointLight {
+                    position: node.point("position")?,
+                    intensity: color(node.required("intensity")?)?,
+                }));
+            }
+            "spot-light" => {
+                let keys = [
+                    "position",
+                    "direction",
+                    "intensity",
+                    "cone-angle",
+                    "falloff-start",
+                ];
+                let node = Node::new(head, rest, &keys)?;
+                let cone_angle = node.number("cone-angle", 30.0)?;
+                world.add_light(Box::new(SpotLight {
+                    position: node.point("position")?,
+                    direction: node.vector("direction", vec3(0.0, -1.0, 0.0))?,
+                    intensity: color(node.required("intensity")?)?,
+                    cone_angle: cone_angle.to_radians(),
+                    falloff_start: node.number("falloff-start", cone_angle)?.to_radians(),
+                }));
+            }
+            "directional-light" => {
+                let keys = ["direction", "irradiance", "angular-diameter"];
+                let node = Node::new(head, rest, &keys)?;
+                world.add_light(Box::new(DirectionalLight {
+                    direction: node.vector("direction", vec3(0.0, -1.0, 0.0))?,
+                    irradiance: color(node.required("irradiance")?)?,
+                    angular_diameter: node.number("angular-diameter", 0.0)?.to_radians(),
+                }));
+            }
+            "ies-light" => {
+                // The profile is the IES file loaded from the page, or a built in downlight.
+                let node = Node::new(head, rest, &["position", "down", "front", "color"])?;
+                world.add_light(Box::new(IesLight::new(
+                    node.point("position")?,
+                    node.vector("down", vec3(0.0, -1.0, 0.0))?,
+                    node.vector("front", vec3(0.0, 0.0, 1.0))?,
+                    ies_profile()?,
+                    node.get("color").map_or(Ok(vec3(1.0, 1.0, 1.0)), color)?,
+                )));
+            }
+            "camera" => {
+                let keys = ["from", "at", "up", "fov", "aperture", "focus"];
+                let node = Node::new(head, rest, &keys)?;
+                let look_from = node.point("from")?;
+                let look_at = node.point("at")?;
+                *camera = Some(Camera::new(
+                    &look_from,
+                    &look_at,
+                    &node.vector("up", vec3(0.0, 1.0, 0.0))?,
+                    node.number("fov", 40.0)?,
+                    aspect,
+                    node.number("aperture", 0.0)?,
+                    node.number("focus", (look_from - look_at).magnitude())?,
+                    0.0,
+                    1.0,
+                ));
+            }
+            "background" => {
+                let background = match rest {
+                    [expr] => background(expr)?,
+                    _ => return Err("expected one background".to_string()),
+                };
+                world.set_background(background);
+            }
+            head => return Err(format!("unknown entry '{}'", head)),
+        }
+        Ok(())
+    }
+
+    /// Texture from the values of a setting.
+    fn texture(&self, values: &[Expr]) -> Result<Texture, String> {
+        match values {
+            [Expr::Symbol(name)] if self.textures.contains_key(name) => {
+                self.texture(&self.textures[name])
+            }
+            [expr @ Expr::Symbol(_)] | [expr @ Expr::List(_)] => self.texture_node(expr),
+            values => Ok(Texture::Constant {
+                color: Point3::from_vec(color(values)?),
+            }),
+        }
+    }
+
+    fn texture_node(&self, expr: &Expr) -> Result<Texture, String> {
+        let (head, rest) = split(expr)?;
+        let texture = |node: &Node, key| -> Result<Box<Texture>, String> {
+            Ok(Box::new(self.texture(node.required(key)?)?))
+        };
+        Ok(match head {
+            "checker" => {
+                let keys = ["left", "right", "scale", "offset", "mode"];
+                let node = Node::new(head, rest, &keys)?;
+                Texture::Checkerboard {
+                    left: Box::new(self.optional_texture(&node, "left", 1.0)?),
+                    right: Box::new(self.optional_texture(&node, "right", 0.0)?),
+                    scale: node.vector("scale", vec3(1.0, 1.0, 1.0))?,
+                    offset: node.vector("offset", vec3(0.0, 0.0, 0.0))?,
+                    mode: match node.symbol("mode", "solid")? {
+                        "solid" => CheckerMode::Solid,
+                        "uv" => CheckerMode::Uv,
+                        mode => return Err(format!("unknown checker mode '{}'", mode)),
+                    },
+                }
+            }
+            "noise" => {
+                Node::new(head, rest, &[])?;
+                Texture::Noise
+            }
+            "mix" => {
+                let node = Node::new(head, rest, &["a", "b", "factor"])?;
+                Texture::Mix {
+                    a: texture(&node, "a")?,
+                    b: texture(&node, "b")?,
+                    factor_texture: texture(&node, "factor")?,
+                }
+            }
+            "multiply" => {
+                let node = Node::new(head, rest, &["a", "b"])?;
+                Texture::Multiply {
+                    a: texture(&node, "a")?,
+                    b: texture(&node, "b")?,
+                }
+            }
+            "add" => {
+                let node = Node::new(head, rest, &["a", "b"])?;
+                Texture::Add {
+                    a: texture(&node, "a")?,
+                    b: texture(&node, "b")?,
+                }
+            }
+            "scale" => {
+                let node = Node::new(head, rest, &["texture", "factor"])?;
+                Texture::Scale {
+                    texture: texture(&node, "texture")?,
+                    factor: color(node.required("factor")?)?,
+                }
+            }
+            "invert" => {
+                let node = Node::new(head, rest, &["texture"])?;
+                Texture::Invert {
+                    texture: texture(&node, "texture")?,
+                }
+            }
+            "color-ramp" => {
+                let node = Node::new(head, rest, &["input", "stop"])?;
+                let stops = node
+                    .all("stop")
+                    .map(|values| match values.split_first() {
+                        Some((position, color_values)) => Ok((
+                            number(std::slice::from_ref(position))?,
+                            Point3::from_vec(color(color_values)?),
+                        )),
+                        None => Err("expected a position and a color in stop".to_string()),
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                if stops.is_empty() {
+                    return Err("color-ramp needs at least one (stop ...)".to_string());
+                }
+                Texture::ColorRamp {
+                    input: texture(&node, "input")?,
+                    stops,
+                }
+            }
+            "triplanar" => {
+                let node = Node::new(head, rest, &["texture", "scale", "sharpness"])?;
+                Texture::Triplanar {
+                    texture: texture(&node, "texture")?,
+                    scale: node.number("scale", 1.0)?,
+                    sharpness: node.number("sharpness", 4.0)?,
+                }
+            }
+            head => return Err(format!("unknown texture '{}'", head)),
+        })
+    }
+
+    fn optional_texture(&self, node: &Node, key: &str, default: f32) -> Result<Texture, String> {
+        match node.get(key) {
+            Some(values) => self.texture(values),
+            None => Ok(Texture::scalar(default)),
+        }
+    }
+
+    /// Material from the values of a setting, shared with earlier objects when named.
+    fn material(&self, values: &[Expr]) -> Result<Rc<dyn Material>, String> {
+        let expr = match values {
+            [Expr::Symbol(name)] => {
+                return self
+                    .materials
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| format!("unknown material '{}'", name))
+            }
+            [expr] => expr,
+            _ => return Err("expected one material".to_string()),
+        };
+        let (head, rest) = split(expr)?;
+        Ok(match head {
+            "lambertian" => {
+                let node = Node::new(head, rest, &["texture"])?;
+                Rc::new(Lambertian {
+                    texture: self.texture(node.required("texture")?)?,
+                })
+            }
+            "metallic" => {
+                let node = Node::new(head, rest, &["albedo", "roughness", "ior"])?;
+                Rc::new(Metallic {
+                    albedo: self.optional_texture(&node, "albedo", 1.0)?,
+                    roughness: node.number("roughness", 0.0)?,
+                    ior: conductor_ior(&node)?,
+                    thin_film: None,
+                })
+            }
+            "rough-conductor" => {
+                let keys = ["albedo", "roughness", "ior", "distribution"];
+                let node = Node::new(head, rest, &keys)?;
+                Rc::new(RoughConductor {
+                    albedo: self.optional_texture(&node, "albedo", 1.0)?,
+                    ior: conductor_ior(&node)?,
+                    thin_film: None,
+                    distribution: distribution(&node)?,
+                })
+            }
+            "dielectric" => {
+                let node = Node::new(head, rest, &["ior", "tint"])?;
+                let ior = match node.get("ior") {
+                    Some([Expr::Symbol(name)]) => match name.as_str() {
+                        "bk7" => Ior::BK7,
+                        "fused-silica" => Ior::FUSED_SILICA,
+                        "diamond" => Ior::DIAMOND,
+                        "water" => Ior::WATER,
+                        name => return Err(format!("unknown glass '{}'", name)),
+                    },
+                    _ => Ior::Constant(node.number("ior", 1.5)?),
+                };
+                let tint = match node.get("tint") {
+                    Some(values) => Some(self.texture(values)?),
+                    None => None,
+                };
+                Rc::new(Dielectric {
+                    ior,
+                    tint,
+                    ..Dielectric::new(1.5)
+                })
+            }
+            "rough-dielectric" => {
+                let node = Node::new(head, rest, &["ior", "roughness", "distribution"])?;
+                Rc::new(RoughDielectric {
+                    ior: node.number("ior", 1.5)?,
+                    distribution: distribution(&node)?,
+                })
+            }
+            "diffuse-light" => {
+                let node = Node::new(head, rest, &["texture", "strength"])?;
+                Rc::new(DiffuseLight {
+                    strength: node.number("strength", 1.0)?,
+                    ..DiffuseLight::new(self.texture(node.required("texture")?)?)
+                })
+            }
+            "principled" => {
+                let keys = [
+                    "base-color",
+                    "metallic",
+                    "roughness",
+                    "specular",
+                    "specular-tint",
+                    "sheen",
+                    "sheen-tint",
+                    "clearcoat",
+                    "clearcoat-roughness",
+                    "transmission",
+                    "ior",
+                    "emission",
+                    "emission-strength",
+                ];
+                let node = Node::new(head, rest, &keys)?;
+                let mut material = Principled::default();
+                for &(key, values) in &node.args {
+                    let texture = self.texture(values)?;
+                    match key {
+                        "base-color" => material.base_color = texture,
+                        "metallic" => material.metallic = texture,
+                        "roughness" => material.roughness = texture,
+                        "specular" => material.specular = texture,
+                        "specular-tint" => material.specular_tint = texture,
+                        "sheen" => material.sheen = texture,
+                        "sheen-tint" => material.sheen_tint = texture,
+                        "clearcoat" => material.clearcoat = texture,
+                        "clearcoat-roughness" => material.clearcoat_roughness = texture,
+                        "transmission" => material.transmission = texture,
+                        "ior" => material.ior = texture,
+                        "emission" => material.emission = texture,
+                        "emission-strength" => material.emission_strength = texture,
+                        _ => unreachable!(),
+                    }
+                }
+                Rc::new(material)
+            }
+            head => return Err(format!("unknown material '{}'", head)),
+        })
+    }
+}
+
+/// List expression `(head (key values...)...)`, its keys checked against those the head
+/// accepts.
+struct Node<'a> {
+    head: &'a str,
+    args: Vec<(&'a str, &'a [Expr])>,
+}
+
+impl<'a> Node<'a> {
+    fn new(head: &'a str, rest: &'a [Expr], keys: &[&str]) -> Result<Self, String> {
+        let args = rest
+            .iter()
+            .map(|arg| match arg {
+                Expr::List(items) => match items.split_first() {
+                    Some((Expr::Symbol(key), values)) if keys.contains(&key.as_str()) => {
+                        Ok((key.as_str(), values))
+                    }
+                    _ => Err(format!(
+                        "unexpected {} in {}, expected one of: {}",
+                        arg,
+                        head,
+                        keys.join(", ")
+                    )),
+                },
+                arg => Err(format!(
+                    "expected a (key values...) list in {}, found {}",
+                    head, arg
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Node { head, args })
+    }
+
+    fn get(&self, key: &str) -> Option<&'a [Expr]> {
+        self.all(key).next()
+    }
+
+    fn all<'b>(&'b self, key: &'b str) -> impl Iterator<Item = &'a [Expr]> + 'b {
+        self.args
+            .iter()
+            .filter(move |&&(name, _)| name == key)
+            .map(|&(_, values)| values)
+    }
+
+    fn required(&self, key: &str) -> Result<&'a [Expr], String> {
+        self.get(key)
+            .ok_or_else(|| format!("{} needs a ({} ...)", self.head, key))
+    }
+
+    fn number(&self, key: &str, default: f32) -> Result<f32, String> {
+        self.get(key).map_or(Ok(default), number)
+    }
+
+    fn vector(&self, key: &str, default: Vector3<f32>) -> Result<Vector3<f32>, String> {
+        self.get(key).map_or(Ok(default), |values| {
+            numbers(values, 3).map(|v| vec3(v[0], v[1], v[2]))
+        })
+    }
+
+    fn point(&self, key: &str) -> Result<Point3<f32>, String> {
+        let v = numbers(self.required(key)?, 3)?;
+        Ok(Point3::new(v[0], v[1], v[2]))
+    }
+
+    fn symbol(&self, key: &str, default: &'a str) -> Result<&'a str, String> {
+        match self.get(key) {
+            Some([Expr::Symbol(name)]) => Ok(name),
+            Some(_) => Err(format!("expected a name in ({} ...)", key)),
+            None => Ok(default),
+        }
+    }
+}
+
+/// Head name and arguments of a list, a bare name standing for a list without arguments.
+fn split(expr: &Expr) -> Result<(&str, &[Expr]), String> {
+    match expr {
+        Expr::Symbol(name) => Ok((name, &[])),
+        Expr::List(items) => match items.split_first() {
+            Some((Expr::Symbol(head), rest)) => Ok((head, rest)),
+            _ => Err(format!("expected a name at the start of {}", expr)),
+        },
+        Expr::Number(_) => Err(format!("expected a list, found {}", expr)),
+    }
+}
+
+fn numbers(values: &[Expr], count: usize) -> Result<Vec<f32>, String> {
+    let numbers = values
+        .iter()
+        .map(|value| match value {
+            Expr::Number(value) => Ok(*value),
+            value => Err(format!("expected a number, found {}", value)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if numbers.len() != count {
+        return Err(format!(
+            "expected {} numbers, found {}",
+            count,
+            numbers.len()
+        ));
+    }
+    Ok(numbers)
+}
+
+fn number(values: &[Expr]) -> Result<f32, String> {
+    numbers(values, 1).map(|v| v[0])
+}
+
+/// Grey level or RGB triple.
+fn color(values: &[Expr]) -> Result<Vector3<f32>, String> {
+    if values.len() == 1 {
+        number(values).map(|grey| vec3(grey, grey, grey))
+    } else {
+        numbers(values, 3).map(|v| vec3(v[0], v[1], v[2]))
+    }
+}
+
+fn conductor_ior(node: &Node) -> Result<Option<ConductorIor>, String> {
+    Ok(match node.symbol("ior", "none")? {
+        "none" => None,
+        "gold" => Some(ConductorIor::GOLD),
+        "copper" => Some(ConductorIor::COPPER),
+        "silver" => Some(ConductorIor::SILVER),
+        "aluminium" => Some(ConductorIor::ALUMINIUM),
+        name => return Err(format!("unknown metal '{}'", name)),
+    })
+}
+
+fn distribution(node: &Node) -> Result<MicrofacetDistribution, String> {
+    let kind = match node.symbol("distribution", "ggx")? {
+        "ggx" => Distribution::Ggx,
+        "beckmann" => Distribution::Beckmann,
+        name => return Err(format!("unknown microfacet distribution '{}'", name)),
+    };
+    Ok(MicrofacetDistribution::from_roughness(
+        kind,
+        node.number("roughness", 0.3)?,
+    ))
+}
+
+fn background(expr: &Expr) -> Result<Background, String> {
+    let (head, rest) = split(expr)?;
+    match head {
+        "solid" => Ok(Background::Solid(color(rest)?)),
+        "gradient" => {
+            let node = Node::new(head, rest, &["bottom", "horizon", "top"])?;
+            let bottom = color(node.required("bottom")?)?;
+            let top = color(node.required("top")?)?;
+            Ok(match node.get("horizon") {
+                Some(values) => Background::gradient3(bottom, color(values)?, top),
+                None => Background::gradient(bottom, top),
+            })
+        }
+        head => Err(format!("unknown background '{}'", head)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::textures::TextureCoords;
+
+    const CAMERA: &str = "(camera (from 0 0 5) (at 0 0 0))";
+
+    fn coords() -> TextureCoords {
+        TextureCoords {
+            u: 0.25,
+            v: 0.75,
+            point: Point3::new(0.3, 0.2, 0.1),
+            footprint: 0.0,
+            uv_footprint: 0.0,
+            normal: vec3(0.0, 1.0, 0.0),
+        }
+    }
+
+    fn texture(text: &str) -> Result<Texture, String> {
+        let scope = Scope::default();
+        scope.texture(&parse(text)?)
+    }
+
+    #[test]
+    fn parses_nested_lists_numbers_and_comments() {
+        let forms = parse("(a 1 -2.5 ; comment (\n (b)) c").unwrap();
+        assert_eq!(
+            forms,
+            vec![
+                Expr::List(vec![
+                    Expr::Symbol("a".to_string()),
+                    Expr::Number(1.0),
+                    Expr::Number(-2.5),
+                    Expr::List(vec![Expr::Symbol("b".to_string())]),
+                ]),
+                Expr::Symbol("c".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unbalanced_parentheses_are_errors() {
+        assert!(parse("(a (b)").is_err());
+        assert!(parse("(a))").is_err());
+        assert!(parse("(a 1x)").is_err());
+    }
+
+    #[test]
+    fn builds_texture_nodes() {
+        let ramp = texture(
+            "(color-ramp (input (mix (a 0) (b 1) (factor 0.5))) (stop 0 0) (stop 1 0.2 0.4 0.6))",
+        )
+        .unwrap();
+        let color = ramp.value(&coords());
+        assert!((color - Point3::new(0.1, 0.2, 0.3)).magnitude() < 1e-5);
+
+        let product =
+            texture("(multiply (a 0.5) (b (invert (texture (add (a 0.1) (b 0.2))))))").unwrap();
+        assert!((product.value(&coords()).x - 0.35).abs() < 1e-5);
+
+        for text in &[
+            "(checker (left 1 0 0) (right noise) (scale 4 4 0) (mode uv))",
+            "(triplanar (texture (scale (texture noise) (factor 2))) (scale 3) (sharpness 8))",
+        ] {
+            assert!(texture(text).is_ok(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn named_textures_are_reused() {
+        let text = format!(
+            "(texture half 0.5)
+             (texture quarter (multiply (a half) (b half)))
+             (material grey (lambertian (texture quarter)))
+             {}",
+            CAMERA
+        );
+        assert!(SceneDescription::parse(&text).is_ok());
+    }
+
+    #[test]
+    fn mistakes_are_reported() {
+        assert!(SceneDescription::parse("(material grey (lambertian (texture 0.5)))").is_err());
+        for text in &[
+            "(texture a (mix (a 0) (b 1)))",
+            "(texture a (checker (colour 1)))",
+            "(texture a 0.5) (texture a 0.2)",
+            "(material m (metallic (ior unobtainium)))",
+            "(sphere (center 0 0) (radius 1) (material (lambertian (texture 0.5))))",
+            "(sphere (center 0 0 0) (radius 1) (material missing))",
+            "(teapot)",
+        ] {
+            let text = format!("{} {}", text, CAMERA);
+            assert!(SceneDescription::parse(&text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn adds_lights() {
+        let text = format!(
+            "(point-light (position 0 2 0) (intensity 1 0.9 0.8))
+             (spot-light (position 0 3 0) (intensity 8) (cone-angle 25) (falloff-start 20))
+             (directional-light (direction 1 -1 0) (irradiance 0.1) (angular-diameter 0.5))
+             (ies-light (position 1 2 0) (color 1 0.9 0.8))
+             {}",
+            CAMERA
+        );
+        let (_, world) = SceneDescription::parse(&text)
+            .unwrap()
+            .build(16, 16)
+            .unwrap();
+        assert_eq!(world.lights().len(), 4);
+
+        // Full intensity straight below the spot, nothing past its cone.
+        let below = world.lights()[1]
+            .sample(&Point3::new(0.0, 0.0, 0.0))
+            .unwrap();
+        assert!((below.radiance - vec3(8.0, 8.0, 8.0) / 9.0).magnitude() < 1e-4);
+        let outside = Point3::new(3.0 * 30f32.to_radians().tan(), 0.0, 0.0);
+        assert!(world.lights()[1].sample(&outside).is_none());
+    }
+
+    #[test]
+    fn objects_share_named_materials() {
+        let text = format!(
+            "(material grey (lambertian (texture 0.5)))
+             (sphere (center -2 0 0) (radius 0.5) (material grey))
+             (sphere (center 0 0 0) (radius 0.5) (material (lambertian (texture 0.5))))
+             (sphere (center 2 0 0) (radius 0.5) (material grey))
+             {}",
+            CAMERA
+        );
+        let (_, world) = SceneDescription::parse(&text)
+            .unwrap()
+            .build(16, 16)
+            .unwrap();
+        let ids: Vec<_> = [-2.0, 0.0, 2.0]
+            .iter()
+            .map(|&x| {
+                let ray = Ray::new(Point3::new(x, 0.0, 5.0), vec3(0.0, 0.0, -1.0), 0.0);
+                let rec = world.trace(&ray).unwrap();
+                world.material_index(rec.material)
+            })
+            .collect();
+        assert_eq!(ids, vec![Some(0), Some(1), Some(0)]);
+    }
+}
@@ -1,10 +1,33 @@
 use cgmath::{Point3, Vector3};
 
+use crate::geometric_objects::GeometricObject;
 use crate::materials::Material;
+use crate::textures::TextureCoords;
 
 pub struct ShadeRecord<'a> {
     pub normal: Vector3<f32>,
+    /// Direction of increasing `u`, orients anisotropic materials.
+    pub tangent: Vector3<f32>,
     pub local_hit_point: Point3<f32>,
-    pub material: &'a Material,
+    pub material: &'a dyn Material,
+    /// The object that was hit, `None` for scattering in the world fog.
+    pub object: Option<&'a dyn GeometricObject>,
     pub intersect_parameter: f32,
+    pub u: f32,
+    pub v: f32,
+    pub footprint: f32,
+    pub uv_footprint: f32,
+}
+
+impl<'a> ShadeRecord<'a> {
+    pub const fn texture_coords(&self) -> TextureCoords {
+        TextureCoords {
+            u: self.u,
+            v: self.v,
+            point: self.local_hit_point,
+            footprint: self.footprint,
+            uv_footprint: self.uv_footprint,
+            normal: self.normal,
+        }
+    }
 }
@@ -0,0 +1,270 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+use std::f32::consts::PI;
+
+use crate::lights::DirectionalLight;
+use crate::spectrum;
+
+/// Angular diameter of the sun seen from the earth, in radians.
+const SUN_ANGULAR_DIAMETER: f32 = 0.009_35;
+
+/// Illuminance of the sun above the atmosphere, in lux.
+const SUN_ILLUMINANCE: f32 = 128_000.0;
+
+/// Photometric to scene units. A clear zenith of a few thousand cd/m² ends up around one.
+const PHOTOMETRIC_SCALE: f32 = 1e-4;
+
+/// Coefficients of the Perez sky luminance distribution.
+#[derive(Clone, Copy)]
+struct Perez([f32; 5]);
+
+impl Perez {
+    /// Relative luminance at `theta` from the zenith and `gamma` from the sun.
+    fn eval(&self, cos_theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = self.0;
+        let cos_gamma = gamma.cos();
+        (1.0 + a * (b / cos_theta.max(0.01)).exp())
+            * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+}
+
+/// Analytic daylight sky after Preetham, Shirley and Smits, "A Practical Analytic Model for
+/// Daylight" (1999), with a ground plane of `ground_albedo` below the horizon lit by it.
+///
+/// The sun itself is not part of the sky radiance, add `sun()` to the world as a light for
+/// it. Angles are in radians, the azimuth turns from `-z` towards `+x`.
+pub struct PhysicalSky {
+    sun_direction: Vector3<f32>,
+    turbidity: f32,
+    ground_albedo: Vector3<f32>,
+    /// Multiplier for both sky and sun.
+    intensity: f32,
+    /// Perez coefficients for luminance Y and chromaticity x and y.
+    perez: [Perez; 3],
+    /// Zenith Y, x and y divided by the Perez function at the zenith.
+    zenith: Vector3<f32>,
+    ground_radiance: Vector3<f32>,
+}
+
+impl PhysicalSky {
+    /// Sky for a sun `elevation` above the horizon at `azimuth`. `turbidity` goes from about
+    /// 2 for a very clear sky to 10 for hazy summer days.
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32, ground_albedo: Vector3<f32>) -> Self {
+        let elevation = elevation.clamp(0.0, PI / 2.0);
+        let sun_direction = vec3(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        let t = turbidity.clamp(1.7, 10.0);
+        let theta_s = PI / 2.0 - elevation;
+
+        let perez = [
+            Perez([
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ]),
+            Perez([
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ]),
+            Perez([
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ]),
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192) * 1000.0;
+        let (s, s2, s3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+        let zenith = vec3(
+            luminance.max(0.0) / perez[0].eval(1.0, theta_s),
+            x / perez[1].eval(1.0, theta_s),
+            y / perez[2].eval(1.0, theta_s),
+        );
+
+        let mut sky = PhysicalSky {
+            sun_direction,
+            turbidity: t,
+            ground_albedo,
+            intensity: 1.0,
+            perez,
+            zenith,
+            ground_radiance: vec3(0.0, 0.0, 0.0),
+        };
+        sky.ground_radiance = sky.ground_albedo.mul_element_wise(sky.ground_irradiance()) / PI;
+        sky
+    }
+
+    /// Scales the brightness of both sky and sun.
+    pub const fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Unit direction towards the sun.
+    #[inline]
+    pub const fn sun_direction(&self) -> Vector3<f32> {
+        self.sun_direction
+    }
+
+    /// Sky radiance above the horizon, without the sun and the intensity.
+    fn sky_radiance(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        let cos_theta = direction.y.max(0.0);
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let luminance = self.zenith.x * self.perez[0].eval(cos_theta, gamma);
+        let x = self.zenith.y * self.perez[1].eval(cos_theta, gamma);
+        let y = self.zenith.z * self.perez[2].eval(cos_theta, gamma);
+        if y <= 0.0 {
+            return vec3(0.0, 0.0, 0.0);
+        }
+
+        let xyz = vec3(x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+        let rgb = spectrum::xyz_to_rgb(&xyz) * PHOTOMETRIC_SCALE;
+        vec3(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+    }
+
+    /// Irradiance on the horizontal ground from the sky dome and the sun.
+    fn ground_irradiance(&self) -> Vector3<f32> {
+        const THETA_STEPS: usize = 16;
+        const PHI_STEPS: usize = 32;
+        let (d_theta, d_phi) = (0.5 * PI / THETA_STEPS as f32, 2.0 * PI / PHI_STEPS as f32);
+
+        let mut irradiance = vec3(0.0, 0.0, 0.0);
+        for i in 0..THETA_STEPS {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..PHI_STEPS {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let direction = vec3(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                irradiance +=
+                    self.sky_radiance(&direction) * (theta.cos() * theta.sin() * d_theta * d_phi);
+            }
+        }
+        irradiance + self.sun_irradiance() * self.sun_direction.y
+    }
+
+    /// Sun irradiance perpendicular to its direction, after the atmosphere, without the
+    /// intensity.
+    fn sun_irradiance(&self) -> Vector3<f32> {
+        let theta_s = PI / 2.0 - self.sun_direction.y.clamp(0.0, 1.0).asin();
+        // Relative optical mass of the air the sunlight goes through.
+        let mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+        let beta = 0.046_08 * self.turbidity - 0.045_86;
+
+        let white = vec3(1.0, 1.0, 1.0);
+        let color = spectrum::spectrum_to_rgb(
+            |lambda| {
+                let micrometers = lambda / 1000.0;
+                let rayleigh = (-0.008_735 * micrometers.powf(-4.08) * mass).exp();
+                let aerosol = (-beta * micrometers.powf(-1.3) * mass).exp();
+                spectrum::rgb_to_spectrum(&white, lambda) * rayleigh * aerosol
+            },
+            32,
+        );
+        color * (SUN_ILLUMINANCE * PHOTOMETRIC_SCALE)
+    }
+
+    /// Radiance of the sky, or the ground below the horizon, seen along `direction`.
+    pub fn radiance(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        let direction = direction.normalize();
+        let radiance = if direction.y < 0.0 {
+            self.ground_radiance
+        } else {
+            self.sky_radiance(&direction)
+        };
+        radiance * self.intensity
+    }
+
+    /// Directional light for the sun disk matching this sky.
+    pub fn sun(&self) -> DirectionalLight {
+        DirectionalLight {
+            direction: -self.sun_direction,
+            irradiance: self.sun_irradiance() * self.intensity,
+            angular_diameter: SUN_ANGULAR_DIAMETER,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear_sky(elevation_degrees: f32) -> PhysicalSky {
+        PhysicalSky::new(
+            elevation_degrees.to_radians(),
+            0.0,
+            2.5,
+            vec3(0.3, 0.3, 0.3),
+        )
+    }
+
+    #[test]
+    fn sun_direction_follows_elevation_and_azimuth() {
+        let sky = PhysicalSky::new(0.0, PI / 2.0, 3.0, vec3(0.0, 0.0, 0.0));
+        assert!((sky.sun_direction() - vec3(1.0, 0.0, 0.0)).magnitude() < 1e-5);
+        let sky = PhysicalSky::new(PI / 2.0, 0.0, 3.0, vec3(0.0, 0.0, 0.0));
+        assert!((sky.sun_direction() - vec3(0.0, 1.0, 0.0)).magnitude() < 1e-5);
+        assert!((sky.sun().direction + sky.sun_direction()).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn clear_sky_is_blue_and_brightest_around_the_sun() {
+        let sky = clear_sky(40.0);
+        let zenith = sky.radiance(&vec3(0.0, 1.0, 0.0));
+        assert!(zenith.z > zenith.x);
+
+        let toward_sun = sky.radiance(&vec3(0.0, 0.3, -1.0));
+        let away_from_sun = sky.radiance(&vec3(0.0, 0.3, 1.0));
+        assert!(toward_sun.y > away_from_sun.y);
+    }
+
+    #[test]
+    fn low_sun_is_dimmer_and_redder() {
+        let high = clear_sky(60.0).sun().irradiance;
+        let low = clear_sky(5.0).sun().irradiance;
+        assert!(low.y < high.y);
+        assert!(low.x / low.z > high.x / high.z);
+    }
+
+    #[test]
+    fn ground_reflects_what_reaches_it() {
+        let sky = clear_sky(30.0);
+        let ground = sky.radiance(&vec3(0.0, -1.0, 0.0));
+        let expected = sky.ground_irradiance() * 0.3 / PI;
+        assert!((ground - expected).magnitude() < 1e-5);
+
+        let dark = PhysicalSky::new(0.5, 0.0, 2.5, vec3(0.0, 0.0, 0.0));
+        assert_eq!(dark.radiance(&vec3(0.0, -1.0, 0.0)), vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn intensity_scales_sky_and_sun() {
+        let direction = vec3(0.3, 0.5, -0.2);
+        let sky = clear_sky(30.0);
+        let brighter = clear_sky(30.0).with_intensity(2.0);
+        assert!(
+            (brighter.radiance(&direction) - sky.radiance(&direction) * 2.0).magnitude() < 1e-4
+        );
+        assert!((brighter.sun().irradiance - sky.sun().irradiance * 2.0).magnitude() < 1e-3);
+    }
+}
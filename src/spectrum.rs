@@ -0,0 +1,299 @@
+use cgmath::{vec3, Vector3};
+use lazy_static::lazy_static;
+
+/// Range of wavelengths, in nanometers, sampled in spectral mode.
+pub const LAMBDA_MIN: f32 = 360.0;
+pub const LAMBDA_MAX: f32 = 830.0;
+
+/// Luminous efficacy, in lumens per watt, used to convert photometric quantities like lumens
+/// and candelas to radiometric ones.
+pub const LUMENS_PER_WATT: f32 = 683.0;
+
+/// Integral of the CIE y colour matching function, normalises luminance so that a
+/// constant unit spectrum has Y = 1.
+const CIE_Y_INTEGRAL: f32 = 106.856_895;
+
+/// Hero wavelength sampling (Wilkie et al. 2014). One uniformly sampled wavelength plus two
+/// more rotated by a third of the range, stored in x, y and z.
+pub fn sample_wavelengths(u: f32) -> Vector3<f32> {
+    let range = LAMBDA_MAX - LAMBDA_MIN;
+    let rotate = |offset: f32| LAMBDA_MIN + ((u + offset) % 1.0) * range;
+    vec3(rotate(0.0), rotate(1.0 / 3.0), rotate(2.0 / 3.0))
+}
+
+/// Smits' basis spectra, 10 equal bins over 380-720nm.
+const SMITS_WHITE: [f32; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f32; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f32; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f32; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f32; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f32; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f32; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Linearly interpolates a Smits basis between bin centers.
+fn smits_basis(basis: &[f32; 10], lambda: f32) -> f32 {
+    let x = ((lambda - 380.0) / 34.0 - 0.5).clamp(0.0, 9.0);
+    let i = (x as usize).min(8);
+    let t = x - i as f32;
+    basis[i] * (1.0 - t) + basis[i + 1] * t
+}
+
+/// Value at `lambda` of a smooth spectrum whose colour is `rgb` (Smits 1999).
+pub fn rgb_to_spectrum(rgb: &Vector3<f32>, lambda: f32) -> f32 {
+    let Vector3 { x: r, y: g, z: b } = *rgb;
+    let basis = |spectrum: &[f32; 10]| smits_basis(spectrum, lambda);
+
+    if r <= g && r <= b {
+        r * basis(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+            } else {
+                (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+            } else {
+                (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+            }
+    } else {
+        b * basis(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+            } else {
+                (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+            }
+    }
+}
+
+/// Upsamples an RGB colour to its spectral values at the three `wavelengths`.
+pub fn uplift(rgb: &Vector3<f32>, wavelengths: &Vector3<f32>) -> Vector3<f32> {
+    vec3(
+        rgb_to_spectrum(rgb, wavelengths.x),
+        rgb_to_spectrum(rgb, wavelengths.y),
+        rgb_to_spectrum(rgb, wavelengths.z),
+    )
+}
+
+fn piecewise_gaussian(x: f32, mu: f32, sigma_low: f32, sigma_high: f32) -> f32 {
+    let t = (x - mu) / if x < mu { sigma_low } else { sigma_high };
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 colour matching functions, multi-lobe fit by Wyman, Sloan and Shirley 2013.
+pub fn cie_xyz(lambda: f32) -> Vector3<f32> {
+    vec3(
+        1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+            + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2),
+        0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+            + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1),
+        1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+            + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+/// CIE XYZ to linear sRGB (D65).
+pub fn xyz_to_rgb(xyz: &Vector3<f32>) -> Vector3<f32> {
+    vec3(
+        3.240_454 * xyz.x - 1.537_138 * xyz.y - 0.498_531 * xyz.z,
+        -0.969_266 * xyz.x + 1.876_011 * xyz.y + 0.041_556 * xyz.z,
+        0.055_643 * xyz.x - 0.204_026 * xyz.y + 1.057_225 * xyz.z,
+    )
+}
+
+/// Monte Carlo estimate of CIE XYZ from radiance sampled at uniformly distributed
+/// `wavelengths`.
+fn spectrum_to_xyz(values: &Vector3<f32>, wavelengths: &Vector3<f32>) -> Vector3<f32> {
+    let inv_pdf = LAMBDA_MAX - LAMBDA_MIN;
+    (cie_xyz(wavelengths.x) * values.x
+        + cie_xyz(wavelengths.y) * values.y
+        + cie_xyz(wavelengths.z) * values.z)
+        * (inv_pdf / (3.0 * CIE_Y_INTEGRAL))
+}
+
+/// Integrates `f` against the CIE colour matching functions with the midpoint rule.
+fn integrate_xyz<F: Fn(f32) -> f32>(f: F, steps: usize) -> Vector3<f32> {
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f32;
+    (0..steps).fold(vec3(0.0, 0.0, 0.0), |sum, i| {
+        let lambda = LAMBDA_MIN + (i as f32 + 0.5) * step;
+        sum + cie_xyz(lambda) * f(lambda)
+    }) * (step / CIE_Y_INTEGRAL)
+}
+
+lazy_static! {
+    /// Linear RGB that the uplifted white spectrum integrates to. Dividing by it makes
+    /// RGB (1, 1, 1) round trip to white instead of the pink tint of illuminant E.
+    static ref WHITE_BALANCE: Vector3<f32> = {
+        let white = vec3(1.0, 1.0, 1.0);
+        xyz_to_rgb(&integrate_xyz(|lambda| rgb_to_spectrum(&white, lambda), 470))
+    };
+}
+
+fn white_balance(rgb: &Vector3<f32>) -> Vector3<f32> {
+    vec3(
+        rgb.x / WHITE_BALANCE.x,
+        rgb.y / WHITE_BALANCE.y,
+        rgb.z / WHITE_BALANCE.z,
+    )
+}
+
+/// Converts radiance sampled at `wavelengths` back to white balanced linear sRGB.
+pub fn to_rgb(values: &Vector3<f32>, wavelengths: &Vector3<f32>) -> Vector3<f32> {
+    white_balance(&xyz_to_rgb(&spectrum_to_xyz(values, wavelengths)))
+}
+
+/// White balanced linear sRGB of the spectrum `f`, evaluated at `steps` wavelengths.
+pub fn spectrum_to_rgb<F: Fn(f32) -> f32>(f: F, steps: usize) -> Vector3<f32> {
+    white_balance(&xyz_to_rgb(&integrate_xyz(f, steps)))
+}
+
+/// Wavelength dependent index of refraction.
+#[derive(Clone, Copy)]
+pub enum Ior {
+    Constant(f32),
+    /// `a + b / λ²` with λ in micrometers.
+    Cauchy {
+        a: f32,
+        b: f32,
+    },
+    /// `n² = 1 + Σ bᵢλ² / (λ² - cᵢ)` with λ in micrometers.
+    Sellmeier {
+        b: [f32; 3],
+        c: [f32; 3],
+    },
+}
+
+impl Ior {
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.039_612, 0.231_792_34, 1.010_469_5],
+        c: [0.006_000_699, 0.020_017_914, 103.560_65],
+    };
+    pub const FUSED_SILICA: Ior = Ior::Sellmeier {
+        b: [0.696_166_3, 0.407_942_6, 0.897_479_4],
+        c: [0.004_679_148, 0.013_512_063, 97.934_003],
+    };
+    pub const DIAMOND: Ior = Ior::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030_625, 0.011_236, 0.0],
+    };
+    pub const WATER: Ior = Ior::Cauchy {
+        a: 1.3246,
+        b: 0.003_09,
+    };
+
+    /// Index of refraction at `lambda` nanometers.
+    pub fn at(&self, lambda: f32) -> f32 {
+        let micrometers = lambda / 1000.0;
+        let l2 = micrometers * micrometers;
+        match self {
+            Ior::Constant(eta) => *eta,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => (1.0
+                + b.iter()
+                    .zip(c.iter())
+                    .map(|(b, c)| b * l2 / (l2 - c))
+                    .sum::<f32>())
+            .sqrt(),
+        }
+    }
+
+    /// Index of refraction at the sodium d line, used when rendering in RGB.
+    pub fn nominal(&self) -> f32 {
+        self.at(587.6)
+    }
+
+    pub const fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+/// Linear sRGB colour of a black body at `kelvin`, normalized to unit luminance.
+pub fn blackbody(kelvin: f32) -> Vector3<f32> {
+    // Planck's law up to a constant factor, with wavelengths in micrometers.
+    let planck = |lambda: f32| {
+        let micrometers = lambda / 1000.0;
+        1.0 / (micrometers.powi(5) * ((14_387.77 / (micrometers * kelvin.max(1.0))).exp() - 1.0))
+    };
+    let rgb = spectrum_to_rgb(planck, 64);
+    let luminance = 0.2126 * rgb.x + 0.7152 * rgb.y + 0.0722 * rgb.z;
+    if luminance > 0.0 {
+        rgb / luminance
+    } else {
+        vec3(0.0, 0.0, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn glasses_match_their_catalogue_index() {
+        assert!((Ior::BK7.nominal() - 1.5168).abs() < 1e-3);
+        assert!((Ior::FUSED_SILICA.nominal() - 1.4585).abs() < 1e-3);
+        assert!((Ior::DIAMOND.nominal() - 2.417).abs() < 5e-3);
+        assert!((Ior::WATER.nominal() - 1.333).abs() < 1e-3);
+        assert_eq!(Ior::Constant(1.7).at(400.0), 1.7);
+    }
+
+    #[test]
+    fn blue_bends_more_than_red() {
+        for ior in &[Ior::BK7, Ior::FUSED_SILICA, Ior::DIAMOND, Ior::WATER] {
+            assert!(ior.is_dispersive());
+            assert!(ior.at(450.0) > ior.at(550.0) && ior.at(550.0) > ior.at(650.0));
+        }
+        assert!(!Ior::Constant(1.5).is_dispersive());
+    }
+
+    #[test]
+    fn hero_wavelengths_stay_in_range() {
+        for i in 0..100 {
+            let wavelengths = sample_wavelengths(i as f32 / 100.0);
+            for lambda in &[wavelengths.x, wavelengths.y, wavelengths.z] {
+                assert!(*lambda >= LAMBDA_MIN && *lambda < LAMBDA_MAX);
+            }
+        }
+    }
+
+    #[test]
+    fn white_round_trips() {
+        let white = vec3(1.0, 1.0, 1.0);
+        let rgb = spectrum_to_rgb(|lambda| rgb_to_spectrum(&white, lambda), 470);
+        assert!((rgb - white).magnitude() < 1e-3);
+
+        // Averaged over stratified hero wavelengths the estimate converges to white too.
+        let n = 1000;
+        let mean = (0..n).fold(vec3(0.0, 0.0, 0.0), |sum, i| {
+            let wavelengths = sample_wavelengths((i as f32 + 0.5) / n as f32);
+            sum + to_rgb(&uplift(&white, &wavelengths), &wavelengths) / n as f32
+        });
+        assert!((mean - white).magnitude() < 0.02);
+    }
+
+    #[test]
+    fn blackbody_colour_follows_temperature() {
+        let warm = blackbody(2000.0);
+        let cool = blackbody(10000.0);
+        assert!(warm.x > warm.z);
+        assert!(cool.z > cool.x);
+        let luminance = 0.2126 * warm.x + 0.7152 * warm.y + 0.0722 * warm.z;
+        assert!((luminance - 1.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,459 @@
+use arr_macro::arr;
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+use lazy_static::lazy_static;
+use std::f32;
+use wbg_rand::{Rng, SeedableRng, XorShiftRng};
+
+/// Surface parameters a texture lookup can depend on.
+#[derive(Clone, Copy)]
+pub struct TextureCoords {
+    pub u: f32,
+    pub v: f32,
+    pub point: Point3<f32>,
+    /// Approximate world-space width of the pixel footprint at `point`, zero when unfiltered.
+    pub footprint: f32,
+    /// The same footprint measured in (u, v) units.
+    pub uv_footprint: f32,
+    pub normal: Vector3<f32>,
+}
+
+#[derive(Clone, Copy)]
+pub enum CheckerMode {
+    /// Solid checker evaluated on the object-space hit point.
+    Solid,
+    /// Flat checker evaluated on the surface (u, v) parameterization.
+    Uv,
+}
+
+pub enum Texture {
+    Constant {
+        color: Point3<f32>,
+    },
+    /// Alternates between `left` and `right` in cells of `1 / scale` units. In `Uv` mode only
+    /// the x and y components of `scale` and `offset` are used. A zero scale component
+    /// flattens the pattern along that axis.
+    Checkerboard {
+        left: Box<Texture>,
+        right: Box<Texture>,
+        scale: Vector3<f32>,
+        offset: Vector3<f32>,
+        mode: CheckerMode,
+    },
+    Noise,
+    /// Per channel blend from `a` (factor 0) to `b` (factor 1) using `factor_texture` as mask.
+    Mix {
+        a: Box<Texture>,
+        b: Box<Texture>,
+        factor_texture: Box<Texture>,
+    },
+    Multiply {
+        a: Box<Texture>,
+        b: Box<Texture>,
+    },
+    Add {
+        a: Box<Texture>,
+        b: Box<Texture>,
+    },
+    Scale {
+        texture: Box<Texture>,
+        factor: Vector3<f32>,
+    },
+    Invert {
+        texture: Box<Texture>,
+    },
+    /// Maps the luminance of `input` onto a gradient. `stops` are `(position, color)` pairs
+    /// sorted by position; values outside the first and last stop are clamped.
+    ColorRamp {
+        input: Box<Texture>,
+        stops: Vec<(f32, Point3<f32>)>,
+    },
+    /// Projects `texture` along the three world axes, using the hit point scaled by `scale`
+    /// as (u, v), and blends the projections by the surface normal raised to `sharpness`.
+    Triplanar {
+        texture: Box<Texture>,
+        scale: f32,
+        sharpness: f32,
+    },
+}
+
+#[inline]
+fn luminance(color: &Point3<f32>) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+fn color_ramp(stops: &[(f32, Point3<f32>)], t: f32) -> Point3<f32> {
+    match stops.iter().position(|(position, _)| *position > t) {
+        _ if stops.is_empty() => Point3::new(0.0, 0.0, 0.0),
+        Some(0) => stops[0].1,
+        None => stops[stops.len() - 1].1,
+        Some(i) => {
+            let ((p0, c0), (p1, c1)) = (stops[i - 1], stops[i]);
+            let s = (t - p0) / (p1 - p0);
+            c0 + (c1 - c0) * s
+        }
+    }
+}
+
+/// Box filtered square wave that is 1.0 on even and -1.0 on odd cells, with `width`
+/// measured in cells. Averages out to zero once the filter covers many cells.
+fn filtered_parity(x: f32, width: f32) -> f32 {
+    // Integral of the square wave, a triangle wave with a period of two cells.
+    let integral = |x: f32| {
+        let half = x * 0.5;
+        let fraction = half - half.floor();
+        2.0 * fraction.min(1.0 - fraction)
+    };
+
+    if width <= 1e-4 {
+        if (x.floor() as i32) % 2 == 0 {
+            1.0
+        } else {
+            -1.0
+        }
+    } else {
+        (integral(x + 0.5 * width) - integral(x - 0.5 * width)) / width
+    }
+}
+
+pub struct Perlin {
+    pub scale_factor: f32,
+    pub random_vecs: [Vector3<f32>; 256],
+    pub random_x_direction: [i32; 256],
+    pub random_y_direction: [i32; 256],
+    pub random_z_direction: [i32; 256],
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Perlin::new()
+    }
+}
+
+/// Seed of the noise tables, fixed so the noise is the same on every render.
+const PERLIN_SEED: [u32; 4] = [0x193a_6754, 0xa8a7_d469, 0x9783_0e05, 0x113b_a7bb];
+
+// TODO: Refactor perlin implementation
+impl Perlin {
+    pub fn new() -> Self {
+        let mut rng = XorShiftRng::from_seed(PERLIN_SEED);
+        Perlin {
+            scale_factor: 5.0,
+            random_vecs: Perlin::perlin_generate(&mut rng),
+            random_x_direction: Perlin::generate_perm(&mut rng),
+            random_y_direction: Perlin::generate_perm(&mut rng),
+            random_z_direction: Perlin::generate_perm(&mut rng),
+        }
+    }
+
+    #[inline]
+    pub fn perlin_generate<R: Rng>(rng: &mut R) -> [Vector3<f32>; 256] {
+        arr![vec3(-1.0 + 2.0 * rng.gen::<f32>(), -1.0 + 2.0 * rng.gen::<f32>(), -1.0 + 2.0 * rng.gen::<f32>()).normalize(); 256]
+    }
+
+    pub fn generate_perm<R: Rng>(rng: &mut R) -> [i32; 256] {
+        let mut i = -1_i32;
+        let mut shuffled_array = arr![{ i += 1; i}; 256];
+        rng.shuffle(&mut shuffled_array);
+        shuffled_array
+    }
+
+    pub fn generate_noise(point: &Point3<f32>) -> f32 {
+        let scaled_point = point * 1.0;
+        let u = scaled_point.x - scaled_point.x.floor();
+        let v = scaled_point.y - scaled_point.y.floor();
+        let w = scaled_point.z - scaled_point.z.floor();
+
+        let i = scaled_point.x.floor() as i32;
+        let j = scaled_point.y.floor() as i32;
+        let k = scaled_point.z.floor() as i32;
+
+        let mut c: [[[Vector3<f32>; 2]; 2]; 2] = [
+            [
+                [vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0)],
+                [vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0)],
+            ],
+            [
+                [vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0)],
+                [vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0)],
+            ],
+        ];
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let x_idx = ((i + di) & 255) as usize;
+                    let y_idx = ((j + dj) & 255) as usize;
+                    let z_idx = ((k + dk) & 255) as usize;
+
+                    let rand_x = PERLIN_STATIC_REF.random_x_direction[x_idx];
+                    let rand_y = PERLIN_STATIC_REF.random_y_direction[y_idx];
+                    let rand_z = PERLIN_STATIC_REF.random_z_direction[z_idx];
+
+                    let index = (rand_x ^ rand_y ^ rand_z) as usize;
+
+                    c[di as usize][dj as usize][dk as usize] = PERLIN_STATIC_REF.random_vecs[index];
+                }
+            }
+        }
+
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let mut acc = 0.0;
+
+        for i in 0..2 {
+            for j in 0..2 {
+                for k in 0..2 {
+                    let weight_v = vec3(u - i as f32, v - j as f32, w - k as f32);
+                    acc += (i as f32 * uu + (1.0 - i as f32) * (1.0 - uu))
+                        * (j as f32 * vv + (1.0 - j as f32) * (1.0 - vv))
+                        * (k as f32 * ww + (1.0 - k as f32) * (1.0 - ww))
+                        * weight_v.dot(c[i as usize][j as usize][k as usize]);
+                }
+            }
+        }
+
+        acc
+    }
+}
+
+lazy_static! {
+    static ref PERLIN_STATIC_REF: Perlin = Perlin::new();
+}
+
+impl Texture {
+    /// Grey constant, handy for textures that drive scalar material parameters.
+    pub const fn scalar(value: f32) -> Self {
+        Texture::Constant {
+            color: Point3::new(value, value, value),
+        }
+    }
+
+    pub fn value(&self, coords: &TextureCoords) -> Point3<f32> {
+        match self {
+            Texture::Constant { color } => *color,
+            Texture::Checkerboard {
+                left,
+                right,
+                scale,
+                offset,
+                mode,
+            } => {
+                let parity = match mode {
+                    CheckerMode::Solid => {
+                        let p = coords.point.to_vec();
+                        (0..3)
+                            .map(|i| {
+                                filtered_parity(
+                                    p[i] * scale[i] + offset[i],
+                                    coords.footprint * scale[i].abs(),
+                                )
+                            })
+                            .product::<f32>()
+                    }
+                    CheckerMode::Uv => {
+                        filtered_parity(
+                            coords.u * scale.x + offset.x,
+                            coords.uv_footprint * scale.x.abs(),
+                        ) * filtered_parity(
+                            coords.v * scale.y + offset.y,
+                            coords.uv_footprint * scale.y.abs(),
+                        )
+                    }
+                };
+
+                // Weight of `left`; sharp cells give exactly 0 or 1 and skip the other branch.
+                let t = 0.5 - 0.5 * parity;
+                if t <= 0.0 {
+                    right.value(coords)
+                } else if t >= 1.0 {
+                    left.value(coords)
+                } else {
+                    Point3::from_vec(
+                        left.value(coords).to_vec() * t + right.value(coords).to_vec() * (1.0 - t),
+                    )
+                }
+            }
+            Texture::Noise => {
+                let point = &coords.point;
+                let mut acc = 0.0;
+                let mut temp_p = *point;
+                let mut weight = 1.0;
+                for _i in 0..7 {
+                    acc += weight * Perlin::generate_noise(&temp_p);
+                    weight *= 0.5;
+                    temp_p *= 2.0;
+                }
+
+                Point3::new(1.0, 1.0, 1.0)
+                    * 0.5
+                    * (1.0 + f32::sin(PERLIN_STATIC_REF.scale_factor * point.z + 10.0 * acc))
+            }
+            Texture::Mix {
+                a,
+                b,
+                factor_texture,
+            } => {
+                let factor = factor_texture.value(coords).to_vec();
+                let (a, b) = (a.value(coords), b.value(coords));
+                a + (b - a).mul_element_wise(factor)
+            }
+            Texture::Multiply { a, b } => a.value(coords).mul_element_wise(b.value(coords)),
+            Texture::Add { a, b } => a.value(coords) + b.value(coords).to_vec(),
+            Texture::Scale { texture, factor } => {
+                Point3::from_vec(texture.value(coords).to_vec().mul_element_wise(*factor))
+            }
+            Texture::Invert { texture } => {
+                Point3::from_vec(Point3::new(1.0, 1.0, 1.0) - texture.value(coords))
+            }
+            Texture::ColorRamp { input, stops } => {
+                color_ramp(stops, luminance(&input.value(coords)))
+            }
+            Texture::Triplanar {
+                texture,
+                scale,
+                sharpness,
+            } => {
+                let p = coords.point.to_vec() * *scale;
+                let weights = vec3(
+                    coords.normal.x.abs().powf(*sharpness),
+                    coords.normal.y.abs().powf(*sharpness),
+                    coords.normal.z.abs().powf(*sharpness),
+                );
+                let weights = weights / weights.sum().max(f32::EPSILON);
+                let projections = [(p.y, p.z), (p.x, p.z), (p.x, p.y)];
+
+                let blended = (0..3)
+                    .filter(|&axis| weights[axis] > 0.0)
+                    .map(|axis| {
+                        let (u, v) = projections[axis];
+                        let projected = TextureCoords {
+                            u,
+                            v,
+                            uv_footprint: coords.footprint * *scale,
+                            ..*coords
+                        };
+                        texture.value(&projected).to_vec() * weights[axis]
+                    })
+                    .sum::<Vector3<f32>>();
+                Point3::from_vec(blended)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coords(u: f32, v: f32, point: Point3<f32>, footprint: f32) -> TextureCoords {
+        TextureCoords {
+            u,
+            v,
+            point,
+            footprint,
+            uv_footprint: footprint,
+            normal: vec3(0.0, 1.0, 0.0),
+        }
+    }
+
+    fn checker(mode: CheckerMode) -> Texture {
+        Texture::Checkerboard {
+            left: Box::new(Texture::scalar(1.0)),
+            right: Box::new(Texture::scalar(0.0)),
+            scale: vec3(2.0, 2.0, 2.0),
+            offset: vec3(0.0, 0.0, 0.0),
+            mode,
+        }
+    }
+
+    #[test]
+    fn parity_alternates_between_cells() {
+        assert_eq!(filtered_parity(0.5, 0.0), 1.0);
+        assert_eq!(filtered_parity(1.5, 0.0), -1.0);
+        assert_eq!(filtered_parity(-0.5, 0.0), -1.0);
+    }
+
+    #[test]
+    fn wide_filter_averages_out() {
+        assert!(filtered_parity(0.3, 40.0).abs() < 0.05);
+        // Half a cell to either side of a cell edge is an even mix.
+        assert!(filtered_parity(1.0, 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn uv_checker_picks_cells_by_uv() {
+        let texture = checker(CheckerMode::Uv);
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let first = texture.value(&coords(0.1, 0.1, origin, 0.0)).x;
+        assert_eq!(texture.value(&coords(0.6, 0.1, origin, 0.0)).x, 1.0 - first);
+        assert_eq!(texture.value(&coords(0.1, 0.6, origin, 0.0)).x, 1.0 - first);
+        assert_eq!(texture.value(&coords(0.6, 0.6, origin, 0.0)).x, first);
+    }
+
+    #[test]
+    fn solid_checker_blurs_to_grey_far_away() {
+        let texture = checker(CheckerMode::Solid);
+        let point = Point3::new(0.1, 0.2, 0.3);
+        let sharp = texture.value(&coords(0.0, 0.0, point, 0.0)).x;
+        assert!(sharp == 0.0 || sharp == 1.0);
+        let blurred = texture.value(&coords(0.0, 0.0, point, 50.0)).x;
+        assert!((blurred - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn arithmetic_nodes_combine_per_channel() {
+        let coords = coords(0.0, 0.0, Point3::new(0.0, 0.0, 0.0), 0.0);
+        let a = || {
+            Box::new(Texture::Constant {
+                color: Point3::new(0.2, 0.4, 0.8),
+            })
+        };
+        let b = || Box::new(Texture::scalar(0.5));
+        let multiply = Texture::Multiply { a: a(), b: b() }.value(&coords);
+        assert_eq!(multiply, Point3::new(0.1, 0.2, 0.4));
+        let add = Texture::Add { a: a(), b: b() }.value(&coords);
+        assert_eq!(add, Point3::new(0.7, 0.9, 1.3));
+        let inverted = Texture::Invert { texture: a() }.value(&coords);
+        assert!((inverted - Point3::new(0.8, 0.6, 0.2)).magnitude() < 1e-6);
+        let mixed = Texture::Mix {
+            a: a(),
+            b: Box::new(Texture::scalar(1.0)),
+            factor_texture: b(),
+        }
+        .value(&coords);
+        assert!((mixed - Point3::new(0.6, 0.7, 0.9)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn color_ramp_clamps_and_interpolates() {
+        let stops = [
+            (0.25, Point3::new(0.0, 0.0, 0.0)),
+            (0.75, Point3::new(1.0, 0.5, 0.0)),
+        ];
+        assert_eq!(color_ramp(&stops, 0.0), stops[0].1);
+        assert_eq!(color_ramp(&stops, 1.0), stops[1].1);
+        assert_eq!(color_ramp(&stops, 0.5), Point3::new(0.5, 0.25, 0.0));
+        assert_eq!(color_ramp(&[], 0.5), Point3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn triplanar_projects_along_the_normal() {
+        let texture = Texture::Triplanar {
+            texture: Box::new(checker(CheckerMode::Uv)),
+            scale: 1.0,
+            sharpness: 4.0,
+        };
+        // Facing up, the texture only depends on x and z.
+        let at = |x, y, z| {
+            texture
+                .value(&coords(0.0, 0.0, Point3::new(x, y, z), 0.0))
+                .x
+        };
+        let first = at(0.1, 0.0, 0.1);
+        assert_eq!(at(0.1, 7.3, 0.1), first);
+        assert_eq!(at(0.6, 0.0, 0.1), 1.0 - first);
+        assert_eq!(at(0.1, 0.0, 0.6), 1.0 - first);
+    }
+}
@@ -1,28 +1,119 @@
-use std::f32;
+use std::{f32, mem};
 
-use crate::{geometric_objects::GeometricObject, ray::Ray, shade_record::ShadeRecord};
+use crate::{
+    background::Background,
+    geometric_objects::GeometricObject,
+    lights::Light,
+    materials::Material,
+    media::{medium_record, Fog},
+    ray::Ray,
+    shade_record::ShadeRecord,
+    sky::PhysicalSky,
+};
 
 pub struct World {
     t_min: f32,
     t_max: f32,
     objects: Vec<Box<dyn GeometricObject>>,
+    /// Addresses of the materials of the objects, in the order they were first added.
+    materials: Vec<*const ()>,
+    lights: Vec<Box<dyn Light>>,
+    fog: Option<Fog>,
+    background: Background,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
 }
 
 impl World {
     pub fn new() -> Self {
         World {
             objects: Vec::new(),
+            materials: Vec::new(),
+            lights: Vec::new(),
             t_min: 0.001,
             t_max: f32::MAX,
+            fog: None,
+            background: Background::default(),
         }
     }
 
-    #[inline]
     pub fn add_object(&mut self, object: Box<dyn GeometricObject>) {
+        let material = material_address(object.material());
+        if !self.materials.contains(&material) {
+            self.materials.push(material);
+        }
         self.objects.push(object);
     }
 
-    pub fn trace(&self, ray: &Ray) -> Option<ShadeRecord> {
+    #[inline]
+    pub fn add_light(&mut self, light: Box<dyn Light>) {
+        self.lights.push(light);
+    }
+
+    #[inline]
+    pub fn lights(&self) -> &[Box<dyn Light>] {
+        &self.lights
+    }
+
+    /// Fills the world with homogeneous atmospheric fog.
+    #[inline]
+    pub fn set_fog(&mut self, fog: Fog) {
+        self.fog = Some(fog);
+    }
+
+    #[inline]
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    /// Lights the world with a daylight sky and its sun.
+    pub fn set_sky(&mut self, sky: PhysicalSky) {
+        self.add_light(Box::new(sky.sun()));
+        self.background = Background::Sky(sky);
+    }
+
+    /// Lets camera rays that miss everything come out transparent, while the current
+    /// background keeps lighting the world.
+    pub fn set_transparent_background(&mut self) {
+        let lighting = mem::take(&mut self.background);
+        self.background = Background::Transparent {
+            lighting: Box::new(lighting),
+        };
+    }
+
+    #[inline]
+    pub const fn background(&self) -> &Background {
+        &self.background
+    }
+
+    /// Position of `object` among the objects of the world.
+    pub fn object_index(&self, object: &dyn GeometricObject) -> Option<usize> {
+        let address = object as *const dyn GeometricObject as *const ();
+        self.objects.iter().position(|candidate| {
+            candidate.as_ref() as *const dyn GeometricObject as *const () == address
+        })
+    }
+
+    /// Number of `material`, counting the materials of the objects in the order they were
+    /// added. Objects sharing one material share its number, the fog comes last.
+    pub fn material_index(&self, material: &dyn Material) -> Option<usize> {
+        let address = material_address(material);
+        self.materials
+            .iter()
+            .position(|&candidate| candidate == address)
+            .or_else(|| {
+                self.fog
+                    .as_ref()
+                    .filter(|fog| material_address(&fog.material) == address)
+                    .map(|_| self.materials.len())
+            })
+    }
+
+    pub fn trace(&self, ray: &Ray) -> Option<ShadeRecord<'_>> {
         let mut shade_record: Option<ShadeRecord> = None;
         let mut closest_so_far = self.t_max;
 
@@ -32,6 +123,33 @@ impl World {
                 shade_record = Some(rec);
             }
         }
+
+        // The ray may scatter in the fog before it reaches the closest surface.
+        if let Some(fog) = &self.fog {
+            if let Some(t) = fog.sample_scattering(ray, self.t_min, closest_so_far) {
+                return Some(medium_record(ray, t, &fog.material, None));
+            }
+        }
         shade_record
     }
+
+    /// Fraction of light travelling along `ray` that reaches parameter `t_max`, zero when
+    /// something solid is in the way.
+    pub fn transmittance(&self, ray: &Ray, t_max: f32) -> f32 {
+        let mut transmittance = match &self.fog {
+            Some(fog) => fog.transmittance(ray, self.t_min, t_max),
+            None => 1.0,
+        };
+        for object in &self.objects {
+            transmittance *= object.transmittance(ray, self.t_min, t_max);
+            if transmittance <= 0.0 {
+                return 0.0;
+            }
+        }
+        transmittance
+    }
+}
+
+fn material_address(material: &dyn Material) -> *const () {
+    material as *const dyn Material as *const ()
 }
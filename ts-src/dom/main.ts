@@ -13,10 +13,34 @@ const sceneSelectButtons = document.getElementsByName(
 const samplingSelectButtons = document.getElementsByName(
   'sampler-select'
 ) as NodeListOf<HTMLInputElement>;
+const filterSelect = document.getElementById('filter') as HTMLSelectElement;
+const filterRadiusInput = document.getElementById(
+  'filterRadius'
+) as HTMLInputElement;
+const spectralCheckbox = document.getElementById(
+  'spectral-rendering'
+) as HTMLInputElement;
+const transparentBackgroundCheckbox = document.getElementById(
+  'transparent-background'
+) as HTMLInputElement;
+const denoiseCheckbox = document.getElementById('denoise') as HTMLInputElement;
+const adaptiveSamplingCheckbox = document.getElementById(
+  'adaptive-sampling'
+) as HTMLInputElement;
+const noiseThresholdInput = document.getElementById(
+  'noiseThreshold'
+) as HTMLInputElement;
+const passesSelect = document.getElementById('passes') as HTMLSelectElement;
 const renderButton = document.getElementById(
   'renderButton'
 ) as HTMLButtonElement;
+const renderRegionButton = document.getElementById(
+  'renderRegionButton'
+) as HTMLButtonElement;
 const renderTime = document.getElementById('renderTime') as HTMLSpanElement;
+const sceneFileInputs = document.querySelectorAll(
+  '#scene-files input[type="file"]'
+) as NodeListOf<HTMLInputElement>;
 const canvas = document.getElementById('canvas') as HTMLCanvasElement;
 const ctx = canvas.getContext('2d') as CanvasRenderingContext2D;
 
@@ -32,14 +56,16 @@ raysPerPixel.addEventListener('change', event => {
 
 widthInput.addEventListener('change', event => {
   canvas.width = parseInt((event.target as HTMLInputElement).value, 10);
+  resetSelection();
 });
 
 heightInput.addEventListener('change', event => {
   canvas.height = parseInt((event.target as HTMLInputElement).value, 10);
+  resetSelection();
 });
 
 let sceneType = 'predefined-scene';
-let samplingType = 'jittered-sampling';
+let samplingType = 'sobol';
 let width = canvas.width;
 let height = canvas.height;
 let numberOfSamples = parseInt(samplesLabel.innerText, 10);
@@ -48,7 +74,85 @@ let t1 = 0;
 
 let preventRenderRequests = false;
 
-renderButton.addEventListener('click', event => {
+interface IRegion {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+// Region dragged out on the canvas, drawn over the last image.
+let selection: IRegion | null = null;
+let selectionStart: { x: number; y: number } | null = null;
+let lastImage: ImageData | null = null;
+// Seed of the scene of the last full render, region renders reuse it to line up.
+let seed = 0;
+
+const canvasPosition = (event: MouseEvent) => {
+  const rect = canvas.getBoundingClientRect();
+  return {
+    x: Math.round(((event.clientX - rect.left) * canvas.width) / rect.width),
+    y: Math.round(((event.clientY - rect.top) * canvas.height) / rect.height),
+  };
+};
+
+// Resizing the canvas clears it, regions can't be pasted into it any more.
+function resetSelection() {
+  lastImage = null;
+  selection = null;
+  renderRegionButton.disabled = true;
+}
+
+const drawSelection = () => {
+  if (lastImage) {
+    ctx.putImageData(lastImage, 0, 0);
+  }
+  if (selection) {
+    ctx.strokeStyle = 'red';
+    ctx.strokeRect(
+      selection.x + 0.5,
+      selection.y + 0.5,
+      selection.width - 1,
+      selection.height - 1
+    );
+  }
+};
+
+canvas.addEventListener('mousedown', event => {
+  if (!lastImage || preventRenderRequests) {
+    return;
+  }
+  selectionStart = canvasPosition(event);
+  selection = null;
+  drawSelection();
+});
+
+canvas.addEventListener('mousemove', event => {
+  if (!selectionStart) {
+    return;
+  }
+  const end = canvasPosition(event);
+  const x = Math.max(0, Math.min(selectionStart.x, end.x));
+  const y = Math.max(0, Math.min(selectionStart.y, end.y));
+  selection = {
+    x,
+    y,
+    width: Math.min(canvas.width, Math.max(selectionStart.x, end.x)) - x,
+    height: Math.min(canvas.height, Math.max(selectionStart.y, end.y)) - y,
+  };
+  drawSelection();
+});
+
+canvas.addEventListener('mouseup', () => {
+  selectionStart = null;
+  if (selection && (selection.width === 0 || selection.height === 0)) {
+    selection = null;
+  }
+  renderRegionButton.disabled = selection === null;
+  drawSelection();
+});
+
+const startRender = (region: IRegion | null) => {
   if (preventRenderRequests) {
     return;
   }
@@ -68,8 +172,24 @@ renderButton.addEventListener('click', event => {
   width = canvas.width;
   height = canvas.height;
   numberOfSamples = parseInt(samplesLabel.innerText, 10);
-  const isRandomScene = sceneType !== 'predefined-scene';
-  const isJitteredSampling = samplingType === 'jittered-sampling';
+  const scene = sceneType.replace(/-scene$/, '');
+  if (!region) {
+    seed = Math.floor(Math.random() * 0x100000000);
+  }
+  const filter = filterSelect.value;
+  const filterRadius = parseFloat(filterRadiusInput.value) || 0;
+  const isSpectral = spectralCheckbox.checked;
+  const isTransparentBackground = transparentBackgroundCheckbox.checked;
+  const isDenoised = denoiseCheckbox.checked;
+  const noiseThreshold = adaptiveSamplingCheckbox.checked
+    ? parseFloat(noiseThresholdInput.value)
+    : 0;
+  // Passes only come with full renders, regions are pasted into the canvas.
+  const passes = region
+    ? ''
+    : Array.from(passesSelect.selectedOptions)
+        .map(option => option.value)
+        .join(',');
 
   preventRenderRequests = true;
   renderSettings.style.pointerEvents = 'none';
@@ -83,12 +203,83 @@ renderButton.addEventListener('click', event => {
     width,
     height,
     numberOfSamples,
-    isRandomScene,
-    isJitteredSampling,
+    scene,
+    seed,
+    sampler: samplingType,
+    filter,
+    filterRadius,
+    isSpectral,
+    isTransparentBackground,
+    isDenoised,
+    noiseThreshold,
+    passes,
+    region,
+  });
+};
+
+// Photos are decoded to RGBA pixels here, workers have no canvas to draw on.
+const decodeImage = async (file: File) => {
+  const bitmap = await createImageBitmap(file);
+  const scratch = document.createElement('canvas');
+  scratch.width = bitmap.width;
+  scratch.height = bitmap.height;
+  const context = scratch.getContext('2d') as CanvasRenderingContext2D;
+  context.drawImage(bitmap, 0, 0);
+  return context.getImageData(0, 0, bitmap.width, bitmap.height);
+};
+
+// Files are handed to the worker, which keeps them for the scenes of later renders.
+sceneFileInputs.forEach(input => {
+  input.addEventListener('change', async () => {
+    const file = input.files && input.files[0];
+    if (!file) {
+      return;
+    }
+    const asset = input.dataset.asset;
+    if (input.dataset.image !== undefined) {
+      const { width, height, data } = await decodeImage(file);
+      const pixels = new Uint8Array(data.buffer);
+      worker.postMessage({ asset, bytes: pixels, width, height }, [
+        pixels.buffer,
+      ]);
+      return;
+    }
+    const bytes = new Uint8Array(await file.arrayBuffer());
+    worker.postMessage({ asset, bytes }, [bytes.buffer]);
   });
 });
 
-worker.onmessage = ({ data }: { data: Uint32Array }) => {
+renderButton.addEventListener('click', () => startRender(null));
+renderRegionButton.addEventListener('click', () => startRender(selection));
+
+interface IAssetLoaded {
+  asset: string;
+  error: string | null;
+}
+
+interface IRenderDone {
+  pixels: Uint32Array;
+  region: IRegion | null;
+  exr: Uint8Array | null;
+}
+
+const downloadExr = (exr: Uint8Array) => {
+  const link = document.createElement('a');
+  link.href = URL.createObjectURL(new Blob([exr], { type: 'image/x-exr' }));
+  link.download = `${sceneType.replace(/-scene$/, '')}.exr`;
+  link.click();
+  setTimeout(() => URL.revokeObjectURL(link.href));
+};
+
+worker.onmessage = ({ data }: { data: IAssetLoaded | IRenderDone }) => {
+  if ('asset' in data) {
+    renderTime.innerHTML = data.error
+      ? `Could not load the ${data.asset}: ${data.error}`
+      : `Loaded the ${data.asset}.`;
+    return;
+  }
+  const { pixels, region, exr } = data;
+
   t1 = performance.now();
   const delta = Math.round((t1 - t0) * 1000) / 1000;
 
@@ -101,9 +292,23 @@ worker.onmessage = ({ data }: { data: Uint32Array }) => {
   preventRenderRequests = false;
 
   const imageData = new ImageData(
-    new Uint8ClampedArray(data.buffer),
-    width,
-    height
+    new Uint8ClampedArray(pixels.buffer),
+    region ? region.width : width,
+    region ? region.height : height
   );
-  ctx.putImageData(imageData, 0, 0);
+  if (region && lastImage) {
+    // Paste the region into the last full image, the selection stays for another go.
+    ctx.putImageData(lastImage, 0, 0);
+    ctx.putImageData(imageData, region.x, region.y);
+    lastImage = ctx.getImageData(0, 0, width, height);
+  } else {
+    ctx.putImageData(imageData, 0, 0);
+    lastImage = imageData;
+    selection = null;
+    renderRegionButton.disabled = true;
+  }
+  drawSelection();
+  if (exr) {
+    downloadExr(exr);
+  }
 };
@@ -1,3 +1,18 @@
 /* tslint:disable */
-export function make_image(arg0: number, arg1: number, arg2: number, arg3: boolean, arg4: boolean): Uint32Array;
+export function make_image(arg0: number, arg1: number, arg2: number, arg3: string, arg4: number, arg5: string, arg6: string, arg7: number, arg8: boolean, arg9: boolean, arg10: boolean, arg11: number): Uint32Array;
+export function make_region(arg0: number, arg1: number, arg2: number, arg3: number, arg4: number, arg5: number, arg6: number, arg7: string, arg8: number, arg9: string, arg10: string, arg11: number, arg12: boolean, arg13: boolean, arg14: boolean, arg15: number): Uint32Array;
+export function load_voxel_grid(arg0: Uint8Array): void;
+export function load_ies_profile(arg0: string): void;
+export function load_environment_map(arg0: Uint8Array): void;
+export function load_backplate(arg0: number, arg1: number, arg2: Uint8Array): void;
+export function load_scene_description(arg0: string): void;
+export function render_passes(arg0: number, arg1: number, arg2: number, arg3: string, arg4: number, arg5: string, arg6: string, arg7: number, arg8: boolean, arg9: boolean, arg10: boolean, arg11: number, arg12: string): RenderResult;
 export function greet(arg0: string): void;
+export class RenderResult {
+free(): void;
+image(): Uint32Array;
+aov(arg0: string): Float32Array | undefined;
+sample_heatmap(): Uint32Array;
+sample_counts(): Uint32Array;
+exr(): Uint8Array;
+}
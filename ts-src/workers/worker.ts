@@ -1,17 +1,45 @@
-import { greet, make_image } from './typings/wasm_tracer';
+import {
+  greet,
+  load_backplate,
+  load_environment_map,
+  load_ies_profile,
+  load_scene_description,
+  load_voxel_grid,
+  make_image,
+  make_region,
+  render_passes,
+} from './typings/wasm_tracer';
 
 // @ts-ignore
 delete WebAssembly.instantiateStreaming;
 
 declare const wasm_bindgen: any;
 
+interface IAssetEvent extends Event {
+  data: {
+    asset: string;
+    bytes: Uint8Array;
+    width?: number;
+    height?: number;
+  };
+}
+
 interface IComEvent extends Event {
   data: {
     width: number;
     height: number;
-    isRandomScene: boolean;
+    scene: string;
+    seed: number;
     numberOfSamples: number;
-    isJitteredSampling: boolean;
+    sampler: string;
+    filter: string;
+    filterRadius: number;
+    isSpectral: boolean;
+    isTransparentBackground: boolean;
+    isDenoised: boolean;
+    noiseThreshold: number;
+    passes: string;
+    region: { x: number; y: number; width: number; height: number } | null;
   };
 }
 
@@ -21,25 +49,120 @@ interface IComEvent extends Event {
   await wasm_bindgen('/assets/wasm_tracer_bg.wasm');
   const myGreet: typeof greet = (wasm_bindgen as any).greet;
   const myMakeImage: typeof make_image = (wasm_bindgen as any).make_image;
+  const myMakeRegion: typeof make_region = (wasm_bindgen as any).make_region;
+  const myRenderPasses: typeof render_passes = (wasm_bindgen as any)
+    .render_passes;
+  const assetLoaders: {
+    [asset: string]: (bytes: Uint8Array, width: number, height: number) => void;
+  } = {
+    'voxel-grid': (wasm_bindgen as any)
+      .load_voxel_grid as typeof load_voxel_grid,
+    'environment-map': (wasm_bindgen as any)
+      .load_environment_map as typeof load_environment_map,
+    'ies-profile': bytes =>
+      ((wasm_bindgen as any).load_ies_profile as typeof load_ies_profile)(
+        new TextDecoder().decode(bytes)
+      ),
+    'scene-description': bytes =>
+      ((wasm_bindgen as any)
+        .load_scene_description as typeof load_scene_description)(
+        new TextDecoder().decode(bytes)
+      ),
+    backplate: (bytes, width, height) =>
+      ((wasm_bindgen as any).load_backplate as typeof load_backplate)(
+        width,
+        height,
+        bytes
+      ),
+  };
 
   self.addEventListener('message', event => {
+    if ('asset' in (event as IAssetEvent).data) {
+      const { asset, bytes, width, height } = (event as IAssetEvent).data;
+      try {
+        assetLoaders[asset](bytes, width || 0, height || 0);
+        postMessage({ asset, error: null });
+      } catch (error) {
+        postMessage({ asset, error: String(error) });
+      }
+      return;
+    }
+
     const {
       width,
       height,
-      isRandomScene,
-      isJitteredSampling,
+      scene,
+      seed,
+      sampler,
+      filter,
+      filterRadius,
       numberOfSamples,
+      isSpectral,
+      isTransparentBackground,
+      isDenoised,
+      noiseThreshold,
+      passes,
+      region,
     } = (event as IComEvent).data;
 
-    const result = myMakeImage(
-      width,
-      height,
-      numberOfSamples,
-      isRandomScene,
-      isJitteredSampling
-    );
+    if (passes && !region) {
+      const result = myRenderPasses(
+        width,
+        height,
+        numberOfSamples,
+        scene,
+        seed,
+        sampler,
+        filter,
+        filterRadius,
+        isSpectral,
+        isTransparentBackground,
+        isDenoised,
+        noiseThreshold,
+        passes
+      );
+      const image = result.image();
+      const exr = result.exr();
+      result.free();
+      postMessage({ pixels: image, region, exr });
+      return;
+    }
+
+    const pixels = region
+      ? myMakeRegion(
+          width,
+          height,
+          region.x,
+          region.y,
+          region.width,
+          region.height,
+          numberOfSamples,
+          scene,
+          seed,
+          sampler,
+          filter,
+          filterRadius,
+          isSpectral,
+          isTransparentBackground,
+          isDenoised,
+          noiseThreshold
+        )
+      : myMakeImage(
+          width,
+          height,
+          numberOfSamples,
+          scene,
+          seed,
+          sampler,
+          filter,
+          filterRadius,
+          isSpectral,
+          isTransparentBackground,
+          isDenoised,
+          noiseThreshold
+        );
 
-    postMessage(result);
+    postMessage({ pixels, region, exr: null });
   });
 
   myGreet('wasm works');